@@ -0,0 +1,116 @@
+//! Benchmarks for the FFG and fork-choice hot paths over synthetic views.
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+use threeSF::cache::JustificationCache;
+use threeSF::constants::JUSTIFICATION_CACHE_CAPACITY;
+use threeSF::ffg;
+use threeSF::fork_choice;
+use threeSF::types::*;
+use threeSF::validators::ValidatorRegistry;
+
+const VALIDATORS: u64 = 64;
+const SIZES: [usize; 3] = [100, 1_000, 10_000];
+
+/// A view with `size` blocks and about `size` votes.
+/// The canonical chain gets one block per slot, with a one-block sibling
+/// fork every 7th slot. Every `VALIDATORS`-th slot all validators vote for
+/// it, linking from the previous voted checkpoint, so justification chains
+/// through the whole view. Returns the view and its last voted checkpoint.
+fn synthetic_forked_view(size: usize) -> (View, Checkpoint) {
+    let mut view = View::default();
+    let genesis = Block::genesis();
+    let mut parent = genesis.hash.clone();
+    view.blocks.insert(genesis.hash.clone(), genesis);
+
+    let mut source = Checkpoint::genesis();
+    let mut slot = 0;
+    while view.blocks.len() < size {
+        slot += 1;
+        let hash = format!("block_{}", slot);
+        view.blocks.insert(hash.clone(), block(&hash, &parent, slot));
+        if slot % 7 == 0 && view.blocks.len() < size {
+            let fork = format!("fork_{}", slot);
+            view.blocks.insert(fork.clone(), block(&fork, &parent, slot));
+        }
+        parent = hash.clone();
+
+        if slot % VALIDATORS == 0 {
+            let target = Checkpoint { block_hash: hash.clone(), slot };
+            for validator_id in 0..VALIDATORS {
+                view.votes.push(Vote {
+                    chain_head_hash: hash.clone(),
+                    source: source.clone(),
+                    target: target.clone(),
+                    slot,
+                    validator_id,
+                    signature: String::new(),
+                });
+            }
+            source = target;
+        }
+    }
+    (view, source)
+}
+
+/// `synthetic_forked_view(size)`, checked to be the view the benchmarks
+/// claim to measure: `size` blocks, with the last voted checkpoint justified
+/// by the chain of links back to genesis.
+fn checked_view(size: usize, validators: &ValidatorRegistry) -> (View, Checkpoint) {
+    let (view, last_voted) = synthetic_forked_view(size);
+    assert_eq!(view.blocks.len(), size);
+    let mut cache = JustificationCache::new(JUSTIFICATION_CACHE_CAPACITY);
+    assert_eq!(ffg::is_justified(&last_voted, &view, validators, &mut cache), Ok(true));
+    assert_ne!(last_voted, Checkpoint::genesis());
+    (view, last_voted)
+}
+
+fn block(hash: &str, parent: &str, slot: u64) -> Block {
+    Block {
+        hash: hash.to_string(),
+        parent_hash: parent.to_string(),
+        slot,
+        proposer_id: slot % VALIDATORS,
+        transactions: vec![],
+    }
+}
+
+fn bench_ffg(c: &mut Criterion) {
+    let validators = ValidatorRegistry::new(0..VALIDATORS);
+    let mut group = c.benchmark_group("ffg");
+    for size in SIZES {
+        let (view, last_voted) = checked_view(size, &validators);
+        group.bench_with_input(BenchmarkId::new("is_justified", size), &view, |b, view| {
+            b.iter(|| {
+                let mut cache = JustificationCache::new(JUSTIFICATION_CACHE_CAPACITY);
+                ffg::is_justified(black_box(&last_voted), view, &validators, &mut cache)
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("greatest_justified_checkpoint", size), &view, |b, view| {
+            b.iter(|| {
+                let mut cache = JustificationCache::new(JUSTIFICATION_CACHE_CAPACITY);
+                ffg::greatest_justified_checkpoint(black_box(view), &validators, &mut cache)
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_fork_choice(c: &mut Criterion) {
+    let validators = ValidatorRegistry::new(0..VALIDATORS);
+    let mut group = c.benchmark_group("fork_choice");
+    for size in SIZES {
+        let (view, last_voted) = checked_view(size, &validators);
+        group.bench_with_input(BenchmarkId::new("rlmd_ghost_fork_choice", size), &view, |b, view| {
+            b.iter(|| {
+                fork_choice::rlmd_ghost_fork_choice(
+                    black_box(view), &validators, Block::genesis().hash, last_voted.slot + 1,
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_ffg, bench_fork_choice);
+criterion_main!(benches);
@@ -0,0 +1,315 @@
+//! Async driver for embedding a node in a tokio event loop (`tokio` feature).
+//! The protocol logic stays synchronous; this only feeds inbound messages to
+//! the node and fires its phases at the offsets of its `SlotSchedule`.
+
+use std::time::SystemTime;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::time::{Duration, Instant};
+use crate::cache::JustificationCache;
+use crate::config::SlotSchedule;
+use crate::ffg;
+use crate::node::Node;
+use crate::types::*;
+
+const PHASES: [SlotPhase; 4] = [SlotPhase::Propose, SlotPhase::Vote, SlotPhase::Confirm, SlotPhase::Merge];
+
+/// A scheduled step of the slot: freezing the view, or one of the phases.
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Freeze,
+    Phase(SlotPhase),
+}
+
+/// Wall-clock slot timing: slot 1 starts at `genesis`, and each slot lasts
+/// `slot_duration`. `phase_start` splits a slot into four equal phases.
+#[derive(Debug, Clone, Copy)]
+pub struct SlotClock {
+    pub genesis: Instant,
+    pub slot_duration: Duration,
+}
+
+impl SlotClock {
+    pub fn new(genesis: Instant, slot_duration: Duration) -> Self {
+        SlotClock { genesis, slot_duration }
+    }
+
+    /// A clock whose genesis is given in wall-clock time, e.g. a network's
+    /// agreed genesis timestamp, in the past or the future.
+    pub fn from_genesis_time(genesis_time: SystemTime, slot_duration: Duration) -> Self {
+        let (now, system_now) = (Instant::now(), SystemTime::now());
+        let genesis = match genesis_time.duration_since(system_now) {
+            Ok(until) => now + until,
+            Err(e) => now.checked_sub(e.duration()).unwrap_or(now),
+        };
+        SlotClock { genesis, slot_duration }
+    }
+
+    /// When `slot` begins.
+    pub fn slot_start(&self, slot: u64) -> Instant {
+        self.genesis + self.slot_duration * slot.saturating_sub(1) as u32
+    }
+
+    /// When `phase` of `slot` begins.
+    pub fn phase_start(&self, slot: u64, phase: SlotPhase) -> Instant {
+        let index = PHASES.iter().position(|&p| p == phase).unwrap_or(0) as u32;
+        self.slot_start(slot) + self.slot_duration / 4 * index
+    }
+}
+
+/// Something the node did that the surrounding stack may act on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsensusEvent {
+    /// A message the node wants sent to its peers, stamped with its fork digest.
+    Broadcast(Envelope),
+    /// The greatest justified checkpoint moved up.
+    Justified(Checkpoint),
+    /// `ch_fin` moved to a new block.
+    Finalized(Hash),
+    /// More validators equivocated than the fault threshold tolerates.
+    FaultThresholdExceeded { equivocators: usize },
+    /// `ch_fin` fell more than `finality_stall_threshold` slots behind.
+    /// Fires once per stall; finalizing again rearms it.
+    FinalityStalled { slots_since_finalization: u64 },
+}
+
+impl Node {
+    /// Run the node until `inbound` closes or `outbound` is dropped,
+    /// handing back the node. Steps fire at the config's `slot_schedule`
+    /// offsets into each of `clock`'s slots, starting from slot 1; inbound
+    /// messages are processed as they arrive.
+    ///
+    /// Panics if the schedule doesn't fit in `clock`'s slot.
+    pub async fn run(
+        mut self,
+        mut inbound: Receiver<Envelope>,
+        clock: SlotClock,
+        outbound: Sender<ConsensusEvent>,
+    ) -> Node {
+        let schedule = self.config().slot_schedule.unwrap_or_else(|| SlotSchedule::even(clock.slot_duration));
+        if let Err(e) = schedule.validate(clock.slot_duration) {
+            panic!("invalid slot schedule: {}", e);
+        }
+        let steps = [
+            (schedule.propose, Step::Phase(SlotPhase::Propose)),
+            (schedule.freeze, Step::Freeze),
+            (schedule.vote, Step::Phase(SlotPhase::Vote)),
+            (schedule.confirm, Step::Phase(SlotPhase::Confirm)),
+            (schedule.merge, Step::Phase(SlotPhase::Merge)),
+        ];
+        let mut cache = JustificationCache::new(self.config().justification_cache_capacity);
+        let mut gjc = ffg::greatest_justified_checkpoint(&self.view, self.validators(), &mut cache);
+        let (mut slot, mut step_index) = (1, 0);
+        let mut fault_reported = false;
+        let mut stall_reported = false;
+
+        loop {
+            let (offset, step) = steps[step_index];
+            tokio::select! {
+                msg = inbound.recv() => match msg {
+                    Some(msg) => self.handle_message(msg),
+                    None => return self,
+                },
+                _ = tokio::time::sleep_until(clock.slot_start(slot) + offset) => {
+                    let ch_fin = self.ch_fin.clone();
+                    let mut events = Vec::new();
+                    let phase = match step {
+                        Step::Freeze => {
+                            self.freeze_for(slot);
+                            None
+                        }
+                        Step::Phase(phase) => Some(phase),
+                    };
+                    if phase == Some(SlotPhase::Confirm) {
+                        self.flush_pending();
+                    }
+                    match phase.map(|phase| self.on_tick(slot, phase)) {
+                        Some(Ok(Some(msg))) => {
+                            // Peers don't echo our messages back, so deliver them locally
+                            let envelope = self.envelope(msg);
+                            self.handle_message(envelope.clone());
+                            events.push(ConsensusEvent::Broadcast(envelope));
+                        }
+                        Some(Err(e)) => eprintln!("Node {}: {}", self.validator.id, e),
+                        Some(Ok(None)) | None => {}
+                    }
+
+                    cache.clear();
+                    let new_gjc = ffg::greatest_justified_checkpoint(&self.view, self.validators(), &mut cache);
+                    if new_gjc.slot > gjc.slot {
+                        events.push(ConsensusEvent::Justified(new_gjc.clone()));
+                        gjc = new_gjc;
+                    }
+                    if self.ch_fin != ch_fin {
+                        events.push(ConsensusEvent::Finalized(self.ch_fin.clone()));
+                        stall_reported = false;
+                    }
+                    let local_slot = self.local_slot(slot);
+                    if self.finality_stalled(local_slot) && !stall_reported {
+                        let slots_since_finalization = self.slots_since_finalization(local_slot);
+                        events.push(ConsensusEvent::FinalityStalled { slots_since_finalization });
+                        stall_reported = true;
+                    }
+                    if self.fault_threshold_exceeded() && !fault_reported {
+                        events.push(ConsensusEvent::FaultThresholdExceeded { equivocators: self.equivocators().len() });
+                        fault_reported = true;
+                    }
+                    for event in events {
+                        if outbound.send(event).await.is_err() {
+                            return self;
+                        }
+                    }
+
+                    step_index = (step_index + 1) % steps.len();
+                    if step_index == 0 {
+                        slot += 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+    use crate::config::ProtocolConfig;
+    use crate::validators::ValidatorRegistry;
+
+    #[tokio::test(start_paused = true)]
+    async fn node_justifies_over_two_slots_of_channel_input() {
+        // A lone validator is its own supermajority
+        let node = Node::with_validators(0, ProtocolConfig::default(), ValidatorRegistry::new(0..1));
+        let (inbound, inbound_rx) = mpsc::channel(16);
+        let (outbound, mut events) = mpsc::channel(64);
+        let clock = SlotClock::new(Instant::now(), Duration::from_secs(4));
+        let driver = tokio::spawn(node.run(inbound_rx, clock, outbound));
+
+        let mut seen = Vec::new();
+        while let Some(event) = events.recv().await {
+            let done = matches!(event, ConsensusEvent::Justified(_));
+            seen.push(event);
+            if done {
+                break;
+            }
+        }
+        assert!(Instant::now() < clock.slot_start(3), "justified only after {:?}", seen);
+        assert!(seen.iter().any(|event| matches!(event, ConsensusEvent::Broadcast(Envelope { message: Message::Proposal(_), .. }))));
+        assert!(seen.iter().any(|event| matches!(event, ConsensusEvent::Broadcast(Envelope { message: Message::Vote(_), .. }))));
+
+        drop(inbound);
+        let node = driver.await.unwrap();
+        let Some(ConsensusEvent::Justified(checkpoint)) = seen.last() else { unreachable!() };
+        assert!(checkpoint.slot > 0);
+        assert!(node.view.blocks.contains_key(&checkpoint.block_hash));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fault_threshold_event_fires_once() {
+        // 4 / 3 + 1 = 2 equivocators exceed the fault threshold
+        let node = Node::with_validators(0, ProtocolConfig::default(), ValidatorRegistry::new(0..4));
+        let genesis = Block::genesis().hash;
+        let (inbound, inbound_rx) = mpsc::channel(16);
+        for head in ["x1", "y1"] {
+            let block = Block { hash: head.to_string(), parent_hash: genesis.clone(), slot: 1, proposer_id: 3, transactions: vec![] };
+            inbound.send(node.envelope(Message::Block(block))).await.unwrap();
+        }
+        for validator_id in 1..3 {
+            for head in ["x1", "y1"] {
+                let vote = Vote {
+                    chain_head_hash: head.to_string(),
+                    source: Checkpoint::genesis(),
+                    target: Checkpoint { block_hash: genesis.clone(), slot: 1 },
+                    slot: 1,
+                    validator_id,
+                    signature: Signature::new(),
+                };
+                inbound.send(node.envelope(Message::Vote(vote))).await.unwrap();
+            }
+        }
+        let (outbound, mut events) = mpsc::channel(64);
+        let clock = SlotClock::new(Instant::now(), Duration::from_secs(4));
+        let driver = tokio::spawn(node.run(inbound_rx, clock, outbound));
+
+        while let Some(event) = events.recv().await {
+            if let ConsensusEvent::FaultThresholdExceeded { equivocators } = event {
+                assert_eq!(equivocators, 2);
+                break;
+            }
+        }
+        tokio::time::sleep_until(clock.slot_start(4)).await;
+        drop(inbound);
+        let node = driver.await.unwrap();
+        assert!(node.fault_threshold_exceeded());
+        while let Ok(event) = events.try_recv() {
+            assert!(!matches!(event, ConsensusEvent::FaultThresholdExceeded { .. }), "fired again");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stall_event_fires_once_past_the_threshold() {
+        // Without its three peers' votes the node never finalizes anything
+        let config = ProtocolConfig { finality_stall_threshold: 3, ..ProtocolConfig::default() };
+        let node = Node::with_validators(0, config, ValidatorRegistry::new(0..4));
+        let (inbound, inbound_rx) = mpsc::channel(16);
+        let (outbound, mut events) = mpsc::channel(64);
+        let clock = SlotClock::new(Instant::now(), Duration::from_secs(4));
+        let driver = tokio::spawn(node.run(inbound_rx, clock, outbound));
+
+        while let Some(event) = events.recv().await {
+            assert!(!matches!(event, ConsensusEvent::Finalized(_)));
+            if let ConsensusEvent::FinalityStalled { slots_since_finalization } = event {
+                assert_eq!(slots_since_finalization, 4);
+                break;
+            }
+        }
+        let now = Instant::now();
+        assert!(clock.slot_start(4) <= now && now < clock.slot_start(5), "stalled at {:?}", now - clock.genesis);
+
+        tokio::time::sleep_until(clock.slot_start(8)).await;
+        drop(inbound);
+        let node = driver.await.unwrap();
+        assert!(node.finality_stalled(7));
+        while let Ok(event) = events.try_recv() {
+            assert!(!matches!(event, ConsensusEvent::FinalityStalled { .. }), "fired again");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn votes_between_freeze_and_vote_wait_for_the_next_slot() {
+        let secs = Duration::from_secs;
+        let schedule = SlotSchedule { propose: secs(0), freeze: secs(4), vote: secs(6), confirm: secs(8), merge: secs(10) };
+        let config = ProtocolConfig { slot_schedule: Some(schedule), ..ProtocolConfig::default() };
+        let node = Node::with_validators(9, config, ValidatorRegistry::new(0..10));
+        let genesis = Block::genesis().hash;
+        let head_vote = |validator_id, head: &str| Vote {
+            chain_head_hash: head.to_string(), source: Checkpoint::genesis(), target: Checkpoint::genesis(),
+            slot: 1, validator_id, signature: Signature::new(),
+        };
+        let (inbound, inbound_rx) = mpsc::channel(16);
+        for head in ["a1", "b1"] {
+            let block = Block { hash: head.to_string(), parent_hash: genesis.clone(), slot: 1, proposer_id: 0, transactions: vec![] };
+            inbound.send(node.envelope(Message::Block(block))).await.unwrap();
+        }
+        inbound.send(node.envelope(Message::Vote(head_vote(0, "a1")))).await.unwrap();
+        let (outbound, mut events) = mpsc::channel(64);
+        let clock = SlotClock::new(Instant::now(), secs(12));
+        let late_votes = [node.envelope(Message::Vote(head_vote(1, "b1"))), node.envelope(Message::Vote(head_vote(2, "b1")))];
+        let driver = tokio::spawn(node.run(inbound_rx, clock, outbound));
+
+        // Both b1 votes land after slot 1's freeze but before its vote
+        tokio::time::sleep_until(clock.slot_start(1) + secs(5)).await;
+        for envelope in late_votes {
+            inbound.send(envelope).await.unwrap();
+        }
+        let mut heads = Vec::new();
+        while heads.len() < 2 {
+            if let Some(ConsensusEvent::Broadcast(Envelope { message: Message::Vote(vote), .. })) = events.recv().await {
+                heads.push((vote.slot, vote.chain_head_hash));
+            }
+        }
+        assert_eq!(heads, [(1, "a1".to_string()), (2, "b1".to_string())]);
+        drop(inbound);
+        driver.await.unwrap();
+    }
+}
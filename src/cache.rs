@@ -0,0 +1,223 @@
+//! Bounded least-recently-used cache for FFG query results.
+//! Justification is monotonic within a view, so evicted entries can
+//! always be recomputed on a miss.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use crate::types::{Checkpoint, Hash as StateHash};
+
+/// Cache of `is_justified` results keyed by checkpoint.
+pub type JustificationCache = LruCache<Checkpoint, bool>;
+
+/// Cache of `is_justified_as_of` results keyed by checkpoint and as-of slot.
+pub type HistoricalJustificationCache = LruCache<(Checkpoint, u64), bool>;
+
+/// Lookup counters for a cache; `clear` leaves them untouched.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// Fraction of lookups served from the cache (0 if none were made).
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 { 0.0 } else { self.hits as f64 / total as f64 }
+    }
+}
+
+impl std::ops::Add for CacheStats {
+    type Output = CacheStats;
+
+    fn add(self, other: CacheStats) -> CacheStats {
+        CacheStats { hits: self.hits + other.hits, misses: self.misses + other.misses }
+    }
+}
+
+/// Fixed-capacity map that evicts the least recently used entry when full.
+#[derive(Debug, Clone)]
+pub struct LruCache<K, V> {
+    capacity: usize,
+    entries: HashMap<K, (V, u64)>, // value and last-use tick
+    recency: BTreeMap<u64, K>,      // tick -> key, oldest first
+    tick: u64,
+    stats: CacheStats,
+    state: Option<StateHash>, // View state the entries were computed for, if tagged
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    /// Create an empty cache holding at most `capacity` entries.
+    /// A capacity of zero disables caching entirely.
+    pub fn new(capacity: usize) -> Self {
+        LruCache {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats::default(),
+            state: None,
+        }
+    }
+
+    /// Look up a value, marking it as most recently used.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let tick = self.next_tick();
+        let Some((value, last_used)) = self.entries.get_mut(key) else {
+            self.stats.misses += 1;
+            return None;
+        };
+        self.stats.hits += 1;
+        self.recency.remove(last_used);
+        self.recency.insert(tick, key.clone());
+        *last_used = tick;
+        Some(value)
+    }
+
+    /// Insert or overwrite a value, evicting the oldest entry if full.
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let tick = self.next_tick();
+        if let Some((_, last_used)) = self.entries.remove(&key) {
+            self.recency.remove(&last_used);
+        } else if self.entries.len() >= self.capacity
+            && let Some((_, oldest)) = self.recency.pop_first()
+        {
+            self.entries.remove(&oldest);
+        }
+        self.recency.insert(tick, key.clone());
+        self.entries.insert(key, (value, tick));
+    }
+
+    /// Drop every entry.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.state = None;
+    }
+
+    /// Drop every entry for which `keep` returns false.
+    pub fn retain(&mut self, mut keep: impl FnMut(&K, &V) -> bool) {
+        let recency = &mut self.recency;
+        self.entries.retain(|key, (value, last_used)| {
+            let kept = keep(key, value);
+            if !kept {
+                recency.remove(last_used);
+            }
+            kept
+        });
+    }
+
+    /// Tag the cache with the view state (`View::state_hash`) its entries
+    /// are about to be used for. Entries survive if they were computed for
+    /// the same state and are dropped otherwise. Returns whether they survived.
+    pub fn sync_state(&mut self, state: StateHash) -> bool {
+        if self.state.as_ref() == Some(&state) {
+            return true;
+        }
+        self.clear();
+        self.state = Some(state);
+        false
+    }
+
+    /// Hits and misses recorded by `get` since creation or the last reset.
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffg;
+    use crate::types::{Block, Signature, View, Vote};
+    use crate::validators::ValidatorRegistry;
+
+    #[test]
+    fn evicts_the_least_recently_used_entry() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "one");
+        cache.insert(2, "two");
+        assert_eq!(cache.get(&1), Some(&"one"));
+        cache.insert(3, "three");
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some(&"one"));
+        assert_eq!(cache.get(&3), Some(&"three"));
+        assert_eq!(cache.stats(), CacheStats { hits: 3, misses: 1 });
+    }
+
+    #[test]
+    fn small_cache_stays_bounded_and_correct() {
+        // A chain of 20 checkpoints, each justified by a link from the last
+        let validators = ValidatorRegistry::new(0..10);
+        let mut view = View::default();
+        let genesis = Block::genesis();
+        view.blocks.insert(genesis.hash.clone(), genesis);
+        let mut source = Checkpoint::genesis();
+        for slot in 1..=20 {
+            let hash = format!("c{}", slot);
+            view.blocks.insert(hash.clone(), Block {
+                hash: hash.clone(), parent_hash: source.block_hash.clone(), slot, proposer_id: 0, transactions: vec![],
+            });
+            let target = Checkpoint { block_hash: hash.clone(), slot };
+            for validator_id in 0..7 {
+                view.insert_vote(Vote {
+                    chain_head_hash: hash.clone(), source: source.clone(), target: target.clone(),
+                    slot, validator_id, signature: Signature::new(),
+                });
+            }
+            source = target;
+        }
+        // A sibling checkpoint with no votes behind it
+        view.blocks.insert("x".to_string(), Block {
+            hash: "x".to_string(), parent_hash: "c9".to_string(), slot: 10, proposer_id: 0, transactions: vec![],
+        });
+        let unjustified = Checkpoint { block_hash: "x".to_string(), slot: 10 };
+
+        let mut cache = JustificationCache::new(3);
+        for _ in 0..2 {
+            for checkpoint in view.all_checkpoints() {
+                let justified = ffg::is_justified(&checkpoint, &view, &validators, &mut cache).unwrap();
+                assert_eq!(justified, checkpoint != unjustified, "{:?}", checkpoint);
+                assert!(cache.len() <= 3);
+            }
+        }
+    }
+
+    #[test]
+    fn retain_drops_entries_and_their_recency() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, false);
+        cache.insert(2, true);
+        cache.retain(|_, &value| value);
+        assert_eq!(cache.len(), 1);
+        // The freed slot is reused without evicting the survivor
+        cache.insert(3, false);
+        assert_eq!(cache.get(&2), Some(&true));
+        assert_eq!(cache.get(&3), Some(&false));
+    }
+}
@@ -0,0 +1,244 @@
+//! Tunable parameters for a node and the simulation.
+//! Defaults mirror the constants in `constants.rs`.
+
+use crate::constants::*;
+use crate::hashing::HashFunction;
+use crate::types::{ForkDigest, Hash};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::time::Duration;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// How the proposer for each slot is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ProposerSelection {
+    /// Cycle through the active validators in id order.
+    #[default]
+    RoundRobin,
+    /// Pseudo-random pick among active validators, fixed by (seed, slot).
+    Seeded(u64),
+}
+
+/// How GHOST picks among sibling subtrees of equal weight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TieBreak {
+    /// The child with the highest hash.
+    #[default]
+    HighestHash,
+    /// The child with the lowest hash.
+    LowestHash,
+    /// Pseudo-random pick among the tied children, fixed by (seed, slot).
+    Seeded(u64),
+}
+
+/// Fork-choice discount for votes included late: each slot a vote took to
+/// reach the view beyond `grace_slots` costs it `percent_per_slot` of its
+/// weight, down to nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InclusionPenalty {
+    pub grace_slots: u64,
+    pub percent_per_slot: u64,
+}
+
+impl InclusionPenalty {
+    /// Percent of its full weight a vote for `vote_slot` keeps when
+    /// included in `included_slot`.
+    pub fn retained_percent(&self, vote_slot: u64, included_slot: u64) -> u64 {
+        let late = included_slot.saturating_sub(vote_slot).saturating_sub(self.grace_slots);
+        100u64.saturating_sub(late.saturating_mul(self.percent_per_slot))
+    }
+}
+
+/// When each step of a slot happens, as offsets from the slot's start.
+/// Freezing the view is split out from voting, so votes arriving between
+/// the two are left out of that slot's vote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SlotSchedule {
+    pub propose: Duration,
+    pub freeze: Duration,
+    pub vote: Duration,
+    pub confirm: Duration,
+    pub merge: Duration,
+}
+
+impl SlotSchedule {
+    /// Four equal phases, with the view frozen as the vote phase starts.
+    pub fn even(slot_duration: Duration) -> Self {
+        let phase = slot_duration / 4;
+        SlotSchedule { propose: Duration::ZERO, freeze: phase, vote: phase, confirm: phase * 2, merge: phase * 3 }
+    }
+
+    /// Check that the steps come in protocol order and all start within a
+    /// slot of `slot_duration`.
+    pub fn validate(&self, slot_duration: Duration) -> Result<(), ScheduleError> {
+        let steps = [
+            ("propose", self.propose), ("freeze", self.freeze), ("vote", self.vote),
+            ("confirm", self.confirm), ("merge", self.merge),
+        ];
+        for pair in steps.windows(2) {
+            if pair[1].1 < pair[0].1 {
+                return Err(ScheduleError::OutOfOrder { step: pair[1].0, before: pair[0].0 });
+            }
+        }
+        if self.merge >= slot_duration {
+            return Err(ScheduleError::PastSlotEnd { step: "merge", offset: self.merge, slot_duration });
+        }
+        Ok(())
+    }
+}
+
+/// Why a `SlotSchedule` can't drive a slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleError {
+    /// `step` is scheduled before `before`, which must precede it.
+    OutOfOrder { step: &'static str, before: &'static str },
+    /// `step` starts at or after the end of the slot.
+    PastSlotEnd { step: &'static str, offset: Duration, slot_duration: Duration },
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::OutOfOrder { step, before } =>
+                write!(f, "{} is scheduled before {}", step, before),
+            ScheduleError::PastSlotEnd { step, offset, slot_duration } =>
+                write!(f, "{} at {:?} starts past the end of a {:?} slot", step, offset, slot_duration),
+        }
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+/// Configuration shared by every node in a run.
+/// When deserializing, missing fields take their default.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
+pub struct ProtocolConfig {
+    /// Maximum entries kept in each justification/finalization cache.
+    pub justification_cache_capacity: usize,
+    /// Largest slot offset (either direction) a node's clock may drift by.
+    /// Zero keeps every node in lockstep with the driver.
+    pub max_clock_skew: u64,
+    /// Votes kept per validator in the node's vote history (oldest dropped first).
+    pub vote_history_cap: usize,
+    /// Proposer assignment rule; every node must use the same one.
+    pub proposer_selection: ProposerSelection,
+    /// Fraction of active validators whose head votes fast-confirm a block
+    /// into `ch_ava`; support must be strictly above `num/den`.
+    pub availability_threshold_num: u64,
+    pub availability_threshold_den: u64,
+    /// Fraction of active validators needed for FFG justification and
+    /// finalization; support must be strictly above `num/den`.
+    pub finality_threshold_num: u64,
+    pub finality_threshold_den: u64,
+    /// Post-merge snapshots kept for `Node::rewind_to` (oldest dropped first).
+    /// Zero disables snapshotting.
+    pub snapshot_history: usize,
+    /// Fraction of active validators that may be caught equivocating before
+    /// the protocol's fault assumption is broken; more than `num/den` is.
+    pub fault_threshold_num: u64,
+    pub fault_threshold_den: u64,
+    /// Stop advancing `ch_fin` once the fault threshold is exceeded, since
+    /// finality guarantees no longer hold.
+    pub halt_on_fault_threshold: bool,
+    /// Hash proposed blocks' contents with this function. `None` keeps the
+    /// readable `blk_s{slot}_p{id}_n{counter}` names, where the proposer's
+    /// own block counter keeps its blocks apart even within one slot.
+    pub hash_function: Option<HashFunction>,
+    /// Hold received votes until `Node::flush_pending` instead of applying
+    /// them on receipt, so the driver controls when they become visible.
+    pub buffer_votes: bool,
+    /// Tie-break between equal-weight forks in fork choice.
+    pub tie_break: TieBreak,
+    /// Received votes beyond this many from one validator for one slot are dropped.
+    pub max_votes_per_validator_slot: usize,
+    /// Received votes for more than this many slots past our clock are dropped.
+    pub max_future_vote_slots: u64,
+    /// Votes stored in the view, and separately among those pending. A full
+    /// view is pruned below `ch_fin`; if that frees nothing, it still takes
+    /// votes for the current slot and refuses the rest. Full pending
+    /// storage evicts its oldest vote.
+    pub max_stored_votes: usize,
+    /// Refuse head changes that abandon more than this many blocks of the
+    /// previous head's chain, keeping the previous head. `None` allows any.
+    pub max_reorg_depth: Option<u64>,
+    /// Drop received votes whose signature doesn't verify under the
+    /// voter's key for the vote's slot. Only meaningful with `LocalSigner`s.
+    pub verify_signatures: bool,
+    /// Finality is stalled once `ch_fin` is more than this many slots behind.
+    pub finality_stall_threshold: u64,
+    /// Intra-slot timeline for the async driver. `None` splits the clock's
+    /// slot into four equal phases (`SlotSchedule::even`).
+    pub slot_schedule: Option<SlotSchedule>,
+    /// Discount late-included votes in fork choice. `None` weighs every
+    /// counted vote the same.
+    pub inclusion_penalty: Option<InclusionPenalty>,
+}
+
+impl Default for ProtocolConfig {
+    fn default() -> Self {
+        ProtocolConfig {
+            justification_cache_capacity: JUSTIFICATION_CACHE_CAPACITY,
+            max_clock_skew: 0,
+            vote_history_cap: VOTE_HISTORY_CAP,
+            proposer_selection: ProposerSelection::RoundRobin,
+            availability_threshold_num: 2,
+            availability_threshold_den: 3,
+            finality_threshold_num: 2,
+            finality_threshold_den: 3,
+            snapshot_history: 0,
+            fault_threshold_num: 1,
+            fault_threshold_den: 3,
+            halt_on_fault_threshold: false,
+            hash_function: None,
+            buffer_votes: false,
+            tie_break: TieBreak::HighestHash,
+            max_votes_per_validator_slot: MAX_VOTES_PER_VALIDATOR_SLOT,
+            max_future_vote_slots: MAX_FUTURE_VOTE_SLOTS,
+            max_stored_votes: MAX_STORED_VOTES,
+            max_reorg_depth: None,
+            verify_signatures: false,
+            finality_stall_threshold: FINALITY_STALL_THRESHOLD,
+            slot_schedule: None,
+            inclusion_penalty: None,
+        }
+    }
+}
+
+/// Identifies the network a node belongs to: the first four bytes of a
+/// SHA-256 over the genesis hash and every parameter nodes must agree on to
+/// process each other's messages. Local policy (caches, limits, tie-breaks)
+/// is left out, so nodes may tune it without splitting the network.
+pub fn fork_digest(genesis_hash: &Hash, config: &ProtocolConfig) -> ForkDigest {
+    let (selection, selection_seed) = match config.proposer_selection {
+        ProposerSelection::RoundRobin => (0, 0),
+        ProposerSelection::Seeded(seed) => (1, seed),
+    };
+    let hash_function = match config.hash_function {
+        None => 0,
+        Some(HashFunction::Sha256) => 1,
+        Some(HashFunction::Blake3) => 2,
+    };
+    let mut bytes = Vec::new();
+    bytes.extend((genesis_hash.len() as u64).to_le_bytes());
+    bytes.extend(genesis_hash.as_bytes());
+    for value in [
+        ETA, PROPOSER_BOOST_PERCENT, selection, selection_seed, hash_function,
+        config.availability_threshold_num, config.availability_threshold_den,
+        config.finality_threshold_num, config.finality_threshold_den,
+    ] {
+        bytes.extend(value.to_le_bytes());
+    }
+    let digest = Sha256::digest(&bytes);
+    [digest[0], digest[1], digest[2], digest[3]]
+}
+
+/// Smallest weight strictly above `num/den` of `n`.
+pub fn threshold_weight(n: u64, num: u64, den: u64) -> u64 {
+    n * num / den + 1
+}
@@ -8,3 +8,29 @@ pub const KAPPA: u64 = 4;
 
 /// Vote expiration period in slots.
 pub const ETA: u64 = 5;
+
+/// Number of validators in the simulation (quorum denominator).
+pub const NUM_VALIDATORS: u64 = 10;
+
+/// Proposer boost as a percentage of the validator set's vote weight.
+pub const PROPOSER_BOOST_PERCENT: u64 = 40;
+
+/// Default number of checkpoints kept in the justification cache.
+pub const JUSTIFICATION_CACHE_CAPACITY: usize = 1024;
+
+/// Default number of votes remembered per validator for diagnostics.
+pub const VOTE_HISTORY_CAP: usize = 64;
+
+/// Default number of votes accepted from one validator for one slot.
+/// Two is enough to hold evidence of an equivocation.
+pub const MAX_VOTES_PER_VALIDATOR_SLOT: usize = 2;
+
+/// Default number of slots past our clock a received vote may be for.
+pub const MAX_FUTURE_VOTE_SLOTS: u64 = 4;
+
+/// Default cap on votes a node stores, counting those held pending.
+pub const MAX_STORED_VOTES: usize = 1 << 16;
+
+/// Default number of slots `ch_fin` may go without advancing before
+/// finality counts as stalled.
+pub const FINALITY_STALL_THRESHOLD: u64 = 8;
@@ -2,60 +2,799 @@
 //! Handles checkpoint justification based on Section 4.
 
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 use crate::types::*;
+use crate::cache::{JustificationCache, HistoricalJustificationCache};
+use crate::validators::ValidatorRegistry;
+
+/// Errors from FFG queries on a malformed or partial view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FfgError {
+    /// The queried checkpoint's block is not in the view.
+    UnknownBlock(Hash),
+    /// The checkpoint's slot precedes its own block's slot, so no honest
+    /// vote could have produced it.
+    InvalidCheckpoint(Checkpoint),
+}
+
+impl fmt::Display for FfgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FfgError::UnknownBlock(hash) => write!(f, "checkpoint block {} not in view", hash),
+            FfgError::InvalidCheckpoint(checkpoint) => write!(f, "checkpoint {} precedes its block", checkpoint),
+        }
+    }
+}
+
+impl std::error::Error for FfgError {}
+
+/// Breakdown of the FFG support behind a checkpoint.
+/// Shows exactly how far a checkpoint is from quorum.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JustificationSupport {
+    pub voters: HashSet<ValidatorId>, // Validators with a valid link to the checkpoint
+    pub weight: u64,                  // Total weight of those voters
+    pub threshold: u64,               // Minimum weight needed for a supermajority
+    pub justified: bool,
+}
 
 /// Determines if a checkpoint is justified given the current view.
-/// Uses recursion with caching for efficiency. Based on Algorithm 1's `J(C, V)`.
+/// Resolves sources iteratively with caching. Based on Algorithm 1's `J(C, V)`.
 pub fn is_justified(
     checkpoint: &Checkpoint,
     view: &View,
-    justification_cache: &mut HashMap<Checkpoint, bool>,
-) -> bool {
+    validators: &ValidatorRegistry,
+    justification_cache: &mut JustificationCache,
+) -> Result<bool, FfgError> {
+    is_justified_within(checkpoint, view, validators, u64::MAX, justification_cache)
+}
+
+/// Whether `checkpoint` was justified as of `as_of_slot`, counting only votes
+/// cast in or before that slot. Results are cached per (checkpoint, slot).
+pub fn is_justified_as_of(
+    checkpoint: &Checkpoint,
+    view: &View,
+    validators: &ValidatorRegistry,
+    as_of_slot: u64,
+    historical_cache: &mut HistoricalJustificationCache,
+) -> Result<bool, FfgError> {
+    let key = (checkpoint.clone(), as_of_slot);
+    if let Some(&is_justified) = historical_cache.get(&key) {
+        return Ok(is_justified);
+    }
+    // Recursive results only hold for this cutoff, so use a scratch cache
+    let mut scoped_cache = JustificationCache::new(historical_cache.capacity());
+    let result = is_justified_within(checkpoint, view, validators, as_of_slot, &mut scoped_cache)?;
+    historical_cache.insert(key, result);
+    Ok(result)
+}
+
+/// `is_justified` restricted to votes with `slot <= max_vote_slot`.
+/// The cache must only hold results computed under the same cutoff.
+fn is_justified_within(
+    checkpoint: &Checkpoint,
+    view: &View,
+    validators: &ValidatorRegistry,
+    max_vote_slot: u64,
+    justification_cache: &mut JustificationCache,
+) -> Result<bool, FfgError> {
     // Use cache to skip redundant calculations
     if let Some(&is_justified) = justification_cache.get(checkpoint) {
-        return is_justified;
+        return Ok(is_justified);
     }
+    Ok(support_within(checkpoint, view, validators, max_vote_slot, justification_cache)?.justified)
+}
+
+/// Collects the voters whose FFG votes justify `checkpoint`.
+/// Same rules as `is_justified`, but reports the full voter set and weights.
+pub fn justification_support(
+    checkpoint: &Checkpoint,
+    view: &View,
+    validators: &ValidatorRegistry,
+    justification_cache: &mut JustificationCache,
+) -> Result<JustificationSupport, FfgError> {
+    support_within(checkpoint, view, validators, u64::MAX, justification_cache)
+}
+
+fn support_within(
+    checkpoint: &Checkpoint,
+    view: &View,
+    validators: &ValidatorRegistry,
+    max_vote_slot: u64,
+    justification_cache: &mut JustificationCache,
+) -> Result<JustificationSupport, FfgError> {
+    let threshold = validators.supermajority_threshold(checkpoint.slot);
 
-    // Genesis is always justified
-    if checkpoint.block_hash == "genesis_hash" && checkpoint.slot == 0 {
+    // Genesis, a trusted anchor or a pinned checkpoint is always justified
+    if view.is_trusted(checkpoint) {
         justification_cache.insert(checkpoint.clone(), true);
-        return true;
+        return Ok(JustificationSupport { voters: HashSet::new(), weight: 0, threshold, justified: true });
     }
 
-    let mut supermajority_voters = HashSet::new();
-    for vote in &view.votes {
+    let supermajority_voters: HashSet<ValidatorId> =
+        supporting_votes(checkpoint, view, validators, max_vote_slot, justification_cache)?
+            .iter()
+            .map(|vote| vote.validator_id)
+            .collect();
+
+    let weight = supermajority_voters.len() as u64;
+    let justified = weight >= threshold;
+    justification_cache.insert(checkpoint.clone(), justified);
+    Ok(JustificationSupport { voters: supermajority_voters, weight, threshold, justified })
+}
+
+/// Votes forming a supermajority link into `checkpoint`: justified source,
+/// matching target slot, and `source <= checkpoint <= target` on one chain.
+fn supporting_votes<'a>(
+    checkpoint: &Checkpoint,
+    view: &'a View,
+    validators: &ValidatorRegistry,
+    max_vote_slot: u64,
+    justification_cache: &mut JustificationCache,
+) -> Result<Vec<&'a Vote>, FfgError> {
+    let candidates = link_candidates(checkpoint, view, validators, max_vote_slot)?;
+    let sources: Vec<Checkpoint> = candidates.iter().map(|vote| vote.source.clone()).collect();
+    let justified = resolve_justification(sources, view, validators, max_vote_slot, justification_cache);
+    Ok(candidates.into_iter()
+        .filter(|vote| justified.get(&vote.source) == Some(&true))
+        .collect())
+}
+
+/// Votes that link into `checkpoint` provided their source is justified.
+/// Votes referencing blocks we can't resolve simply don't count, and neither
+/// do votes with malformed checkpoints: a source not strictly before its
+/// target, or a checkpoint slot before its block's slot. Only the exact base
+/// checkpoint is justified without votes, so a spoofed source such as
+/// `(genesis_hash, 5)` needs real support like any other checkpoint.
+fn link_candidates<'a>(
+    checkpoint: &Checkpoint,
+    view: &'a View,
+    validators: &ValidatorRegistry,
+    max_vote_slot: u64,
+) -> Result<Vec<&'a Vote>, FfgError> {
+    let checkpoint_block = view.blocks.get(&checkpoint.block_hash)
+        .ok_or_else(|| FfgError::UnknownBlock(checkpoint.block_hash.clone()))?;
+    if checkpoint_block.slot > checkpoint.slot {
+        return Err(FfgError::InvalidCheckpoint(checkpoint.clone()));
+    }
+
+    let mut candidates = Vec::new();
+    for vote in view.votes.iter().filter(|v| v.slot <= max_vote_slot) {
         // Vote target slot must match checkpoint slot
-        if vote.target.slot == checkpoint.slot {
-            // Source checkpoint must also be justified (recursive check)
-            if is_justified(&vote.source, view, justification_cache) {
-                let source_block = view.blocks.get(&vote.source.block_hash).unwrap();
-                let target_block = view.blocks.get(&vote.target.block_hash).unwrap();
-                let checkpoint_block = view.blocks.get(&checkpoint.block_hash).unwrap();
-
-                // Check ancestry: source <= checkpoint <= target
-                if source_block.is_ancestor_of(checkpoint_block, view) &&
-                   checkpoint_block.is_ancestor_of(target_block, view) {
-                    supermajority_voters.insert(vote.validator_id);
-                }
+        if vote.target.slot != checkpoint.slot {
+            continue;
+        }
+        // Only validators active when the vote was cast count
+        if !validators.is_active(vote.validator_id, vote.slot) {
+            continue;
+        }
+        let (Some(source_block), Some(target_block)) = (
+            view.blocks.get(&vote.source.block_hash),
+            view.blocks.get(&vote.target.block_hash),
+        ) else {
+            continue;
+        };
+        // Reject malformed links
+        if vote.source.slot >= vote.target.slot
+            || source_block.slot > vote.source.slot
+            || target_block.slot > vote.target.slot
+        {
+            continue;
+        }
+
+        // Check ancestry: source <= checkpoint <= target
+        let source_ok = source_block.hash == checkpoint_block.hash
+            || source_block.try_is_ancestor_of(checkpoint_block, view) == Ok(true);
+        let target_ok = checkpoint_block.hash == target_block.hash
+            || checkpoint_block.try_is_ancestor_of(target_block, view) == Ok(true);
+        if source_ok && target_ok {
+            candidates.push(vote);
+        }
+    }
+    Ok(candidates)
+}
+
+/// Whether each of `checkpoints` is justified, along with every source they
+/// transitively depend on. Works bottom-up from an explicit stack rather
+/// than by recursion, so arbitrarily long justification chains can't
+/// overflow the stack. Sources strictly precede their targets, so the link
+/// graph is acyclic; should a cycle show up anyway, the checkpoint that
+/// closes it counts as unjustified. Checkpoints we can't resolve (unknown
+/// or malformed) count as unjustified too.
+fn resolve_justification(
+    checkpoints: Vec<Checkpoint>,
+    view: &View,
+    validators: &ValidatorRegistry,
+    max_vote_slot: u64,
+    justification_cache: &mut JustificationCache,
+) -> HashMap<Checkpoint, bool> {
+    let mut resolved: HashMap<Checkpoint, bool> = HashMap::new();
+    // Checkpoints whose sources are still being resolved, with their candidate links
+    let mut pending: HashMap<Checkpoint, Vec<&Vote>> = HashMap::new();
+    let mut stack: Vec<(Checkpoint, bool)> = checkpoints.into_iter().map(|cp| (cp, false)).collect();
+
+    while let Some((checkpoint, sources_done)) = stack.pop() {
+        if sources_done {
+            let candidates = pending.remove(&checkpoint).unwrap_or_default();
+            let voters: HashSet<ValidatorId> = candidates.iter()
+                .filter(|vote| resolved.get(&vote.source) == Some(&true))
+                .map(|vote| vote.validator_id)
+                .collect();
+            let justified = voters.len() as u64 >= validators.supermajority_threshold(checkpoint.slot);
+            justification_cache.insert(checkpoint.clone(), justified);
+            resolved.insert(checkpoint, justified);
+            continue;
+        }
+        if resolved.contains_key(&checkpoint) {
+            continue;
+        }
+        if pending.contains_key(&checkpoint) {
+            // Reached again from one of its own sources
+            resolved.insert(checkpoint, false);
+            continue;
+        }
+        if let Some(&is_justified) = justification_cache.get(&checkpoint) {
+            resolved.insert(checkpoint, is_justified);
+            continue;
+        }
+        if view.is_trusted(&checkpoint) {
+            justification_cache.insert(checkpoint.clone(), true);
+            resolved.insert(checkpoint, true);
+            continue;
+        }
+        let Ok(candidates) = link_candidates(&checkpoint, view, validators, max_vote_slot) else {
+            resolved.insert(checkpoint, false);
+            continue;
+        };
+
+        // Revisit once every source below it is resolved
+        stack.push((checkpoint.clone(), true));
+        for vote in &candidates {
+            if !resolved.contains_key(&vote.source) {
+                stack.push((vote.source.clone(), false));
+            }
+        }
+        pending.insert(checkpoint, candidates);
+    }
+    resolved
+}
+
+/// Reconstructs the chain of justified checkpoints leading to `checkpoint`,
+/// ordered from genesis (or the view's anchor, or a checkpoint pinned when
+/// the view was pruned) up to and including `checkpoint` itself.
+/// Each step follows the greatest justified source that links into the next.
+/// Returns an empty path if `checkpoint` is not justified.
+pub fn justification_path(
+    checkpoint: &Checkpoint,
+    view: &View,
+    validators: &ValidatorRegistry,
+    justification_cache: &mut JustificationCache,
+) -> Result<Vec<Checkpoint>, FfgError> {
+    if !is_justified(checkpoint, view, validators, justification_cache)? {
+        return Ok(vec![]);
+    }
+
+    let mut path = vec![checkpoint.clone()];
+    let mut current = checkpoint.clone();
+    while !view.is_trusted(&current) {
+        let source = supporting_votes(&current, view, validators, u64::MAX, justification_cache)?
+            .iter()
+            .map(|vote| vote.source.clone())
+            .filter(|source| source.slot < current.slot) // Sources must move strictly back
+            .max_by(|a, b| a.cmp(b).then_with(|| a.block_hash.cmp(&b.block_hash)));
+        match source {
+            Some(source) => {
+                path.push(source.clone());
+                current = source;
             }
+            None => break,
         }
     }
-    
-    let n = 100; // Validator count for this simulation
-    let result = supermajority_voters.len() as u64 > (2 * n / 3);
-    justification_cache.insert(checkpoint.clone(), result);
-    result
+    path.reverse();
+    Ok(path)
+}
+
+/// Key for picking the greatest of several justified (or finalized)
+/// checkpoints: slot first, then the slot of the checkpoint's block, then
+/// its hash. A vote's link justifies every checkpoint between its source
+/// and target at the target's slot, so one slot can hold justified
+/// checkpoints on both a block and its ancestor; the block's own slot
+/// prefers the descendant, which carries more of the chain.
+pub fn checkpoint_rank<'a>(checkpoint: &'a Checkpoint, view: &View) -> (u64, u64, &'a Hash) {
+    let block_slot = view.blocks.get(&checkpoint.block_hash).map_or(0, |b| b.slot);
+    (checkpoint.slot, block_slot, &checkpoint.block_hash)
 }
 
 /// Returns the highest justified checkpoint by slot number.
-/// See Section 4 for ordering rules.
+/// See Section 4 for ordering rules. Starts from the view's base checkpoint
+/// (genesis or a trusted anchor) and only moves up to a justified checkpoint
+/// named by some vote (as source or target), so a view with no justified
+/// checkpoints yields the base. Checkpoints on blocks
+/// we don't have can't be verified and are skipped, so this never fails.
 pub fn greatest_justified_checkpoint(
     view: &View,
-    justification_cache: &mut HashMap<Checkpoint, bool>,
+    validators: &ValidatorRegistry,
+    justification_cache: &mut JustificationCache,
 ) -> Checkpoint {
-    view.votes.iter()
-        .map(|v| v.source.clone())
-        .filter(|cp| is_justified(cp, view, justification_cache))
-        .max()
-        .unwrap_or(Checkpoint { block_hash: "genesis_hash".to_string(), slot: 0 })
+    let mut greatest = view.base_checkpoint();
+    for checkpoint in view.votes.iter().flat_map(|v| [&v.source, &v.target]) {
+        if checkpoint_rank(checkpoint, view) > checkpoint_rank(&greatest, view)
+            && is_justified(checkpoint, view, validators, justification_cache) == Ok(true)
+        {
+            greatest = checkpoint.clone();
+        }
+    }
+    greatest
+}
+
+/// Like `greatest_justified_checkpoint`, but only counting checkpoints whose
+/// block is `finalized` or descends from it. Justification on a fork that
+/// left the chain below `finalized` can only come from slashable votes, and
+/// must not drag fork choice off the finalized chain; justification on a
+/// sibling fork above `finalized` is legitimate and counts as usual, so the
+/// result may move across forks as long as they share `finalized`.
+pub fn greatest_justified_checkpoint_extending(
+    view: &View,
+    validators: &ValidatorRegistry,
+    justification_cache: &mut JustificationCache,
+    finalized: &Hash,
+) -> Checkpoint {
+    let mut greatest = view.base_checkpoint();
+    for checkpoint in view.votes.iter().flat_map(|v| [&v.source, &v.target]) {
+        if checkpoint_rank(checkpoint, view) > checkpoint_rank(&greatest, view)
+            && view.ancestors(&checkpoint.block_hash).any(|b| b.hash == *finalized)
+            && is_justified(checkpoint, view, validators, justification_cache) == Ok(true)
+        {
+            greatest = checkpoint.clone();
+        }
+    }
+    greatest
+}
+
+/// Drop history below the finalized checkpoint `finalized`, re-anchoring
+/// the view there so it is justified and finalized by definition.
+///
+/// Justification-safe: we keep every block descending from `finalized`,
+/// every vote that could still justify something above it or weigh in fork
+/// choice (a vote at or below `finalized` only weighs while it is its
+/// validator's latest), and the stretch of `finalized`'s ancestors back to the oldest
+/// source those votes name. Each checkpoint at or below `finalized` that a
+/// kept vote names is pinned on the view with its justification and
+/// finality from before pruning, so pruning neither loses nor grants either.
+/// The caller must only pass a checkpoint it has seen finalized.
+pub fn prune_finalized(
+    view: &mut View,
+    finalized: &Checkpoint,
+    validators: &ValidatorRegistry,
+    justification_cache: &mut JustificationCache,
+) -> Result<(), FfgError> {
+    if !view.blocks.contains_key(&finalized.block_hash) {
+        return Err(FfgError::UnknownBlock(finalized.block_hash.clone()));
+    }
+
+    let mut keep: HashSet<Hash> = view.blocks.keys()
+        .filter(|hash| view.ancestors(hash).any(|b| b.hash == finalized.block_hash))
+        .cloned()
+        .collect();
+    let mut latest: HashMap<ValidatorId, u64> = HashMap::new();
+    for vote in &view.votes {
+        let slot = latest.entry(vote.validator_id).or_insert(vote.slot);
+        *slot = (*slot).max(vote.slot);
+    }
+    let kept = |vote: &Vote| vote.target.slot > finalized.slot
+        || keep.contains(&vote.chain_head_hash) && latest[&vote.validator_id] == vote.slot;
+
+    // Settle what the kept votes name below the anchor while their history is still here
+    let below_anchor: HashSet<Checkpoint> = view.votes.iter()
+        .filter(|vote| kept(vote))
+        .flat_map(|vote| [&vote.source, &vote.target])
+        .filter(|checkpoint| checkpoint.slot <= finalized.slot && *checkpoint != finalized)
+        .cloned()
+        .collect();
+    let links = LinkIndex::from_view(view, validators);
+    let (mut pinned_justified, mut pinned_finalized) = (HashSet::new(), HashSet::new());
+    for checkpoint in below_anchor {
+        if !is_justified(&checkpoint, view, validators, justification_cache)? {
+            continue;
+        }
+        if view.is_trusted_finalized(&checkpoint) || links.consecutive_target(&checkpoint).is_some() {
+            pinned_finalized.insert(checkpoint.clone());
+        }
+        pinned_justified.insert(checkpoint);
+    }
+    view.votes.retain(|vote| kept(vote));
+
+    // Keep the finalized block's ancestors back to the oldest source still in use
+    let oldest_source = view.votes.iter()
+        .map(|vote| &vote.source)
+        .filter(|source| source.slot < finalized.slot)
+        .map(|source| source.slot)
+        .min();
+    if let Some(oldest_source) = oldest_source {
+        for block in view.ancestors(&finalized.block_hash) {
+            keep.insert(block.hash.clone());
+            if block.slot <= oldest_source {
+                break;
+            }
+        }
+    }
+
+    view.blocks.retain(|hash, _| keep.contains(hash));
+    view.anchor = Some(finalized.clone());
+    view.pinned_justified = pinned_justified;
+    view.pinned_finalized = pinned_finalized;
+    Ok(())
+}
+
+/// Index of supermajority links (source → targets), built vote by vote.
+/// A link exists once more than 2/3 of validators cast an FFG vote with
+/// exactly that source and target.
+#[derive(Debug, Clone, Default)]
+pub struct LinkIndex {
+    voters: HashMap<(Checkpoint, Checkpoint), HashSet<ValidatorId>>,
+    links: HashMap<Checkpoint, HashSet<Checkpoint>>,
+    // Everyone FFG-voting for each target, whatever the source
+    target_voters: HashMap<Checkpoint, HashSet<ValidatorId>>,
+}
+
+impl LinkIndex {
+    /// Build the index from every vote in a view.
+    pub fn from_view(view: &View, validators: &ValidatorRegistry) -> Self {
+        let mut index = LinkIndex::default();
+        for vote in &view.votes {
+            index.add_vote(vote, validators);
+        }
+        index
+    }
+
+    /// Record a vote. Returns true if it completed a new supermajority link.
+    /// Votes from validators inactive in the vote's slot are ignored.
+    pub fn add_vote(&mut self, vote: &Vote, validators: &ValidatorRegistry) -> bool {
+        if !validators.is_active(vote.validator_id, vote.slot) {
+            return false;
+        }
+        self.target_voters.entry(vote.target.clone()).or_default().insert(vote.validator_id);
+        let key = (vote.source.clone(), vote.target.clone());
+        let voters = self.voters.entry(key).or_default();
+        if !voters.insert(vote.validator_id)
+            || (voters.len() as u64) < validators.supermajority_threshold(vote.target.slot)
+        {
+            return false;
+        }
+        self.links.entry(vote.source.clone()).or_default().insert(vote.target.clone())
+    }
+
+    /// Validators, active in their vote's slot, with an FFG vote for
+    /// `target` from any source.
+    pub fn target_voters(&self, target: &Checkpoint) -> Option<&HashSet<ValidatorId>> {
+        self.target_voters.get(target)
+    }
+
+    /// All targets with a supermajority link from `source`.
+    pub fn targets(&self, source: &Checkpoint) -> Option<&HashSet<Checkpoint>> {
+        self.links.get(source)
+    }
+
+    /// Whether a supermajority link `source → target` exists.
+    pub fn has_link(&self, source: &Checkpoint, target: &Checkpoint) -> bool {
+        self.links.get(source).is_some_and(|targets| targets.contains(target))
+    }
+
+    /// A linked target in the very next slot, which finalizes `source`
+    /// once `source` is itself justified.
+    pub fn consecutive_target(&self, source: &Checkpoint) -> Option<&Checkpoint> {
+        self.links.get(source)?.iter().find(|target| target.slot == source.slot + 1)
+    }
+
+    /// Sources that have a consecutive-slot link, i.e. finalization candidates.
+    pub fn finalization_candidates(&self) -> impl Iterator<Item = &Checkpoint> {
+        self.links.keys().filter(|source| self.consecutive_target(source).is_some())
+    }
+}
+
+/// Whether `checkpoint` is finalized: justified, with a supermajority link
+/// to a checkpoint in the next slot. The view's base checkpoint is finalized
+/// by definition. Rebuilds the link index from scratch;
+/// nodes keep a `LinkIndex` up to date instead.
+pub fn is_finalized(
+    checkpoint: &Checkpoint,
+    view: &View,
+    validators: &ValidatorRegistry,
+    justification_cache: &mut JustificationCache,
+) -> Result<bool, FfgError> {
+    if view.is_trusted_finalized(checkpoint) {
+        return Ok(true);
+    }
+    let links = LinkIndex::from_view(view, validators);
+    Ok(links.consecutive_target(checkpoint).is_some()
+        && is_justified(checkpoint, view, validators, justification_cache)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoint(hash: &str, slot: u64) -> Checkpoint {
+        Checkpoint { block_hash: hash.to_string(), slot }
+    }
+
+    fn vote(validator_id: ValidatorId, source: Checkpoint, target: Checkpoint) -> Vote {
+        Vote {
+            chain_head_hash: target.block_hash.clone(),
+            slot: target.slot,
+            source,
+            target,
+            validator_id,
+            signature: Signature::new(),
+        }
+    }
+
+    #[test]
+    fn link_forms_as_votes_cross_the_threshold() {
+        let validators = ValidatorRegistry::new(0..10);
+        let (source, target) = (checkpoint("a", 1), checkpoint("b", 2));
+        let mut links = LinkIndex::default();
+        for id in 0..6 {
+            assert!(!links.add_vote(&vote(id, source.clone(), target.clone()), &validators));
+        }
+        assert!(!links.has_link(&source, &target));
+        assert_eq!(links.finalization_candidates().count(), 0);
+
+        assert!(links.add_vote(&vote(6, source.clone(), target.clone()), &validators));
+        assert!(links.has_link(&source, &target));
+        assert_eq!(links.consecutive_target(&source), Some(&target));
+        assert_eq!(links.finalization_candidates().collect::<Vec<_>>(), [&source]);
+
+        // Further and repeated votes keep the one link without reporting it again
+        assert!(!links.add_vote(&vote(7, source.clone(), target.clone()), &validators));
+        assert!(!links.add_vote(&vote(6, source.clone(), target.clone()), &validators));
+        assert_eq!(links.targets(&source).map(HashSet::len), Some(1));
+    }
+
+    #[test]
+    fn link_forms_once_threshold_drops_below_voters() {
+        let mut validators = ValidatorRegistry::new(0..10);
+        let (source, target) = (checkpoint("a", 1), checkpoint("b", 2));
+        let mut links = LinkIndex::default();
+        for id in 0..6 {
+            links.add_vote(&vote(id, source.clone(), target.clone()), &validators);
+        }
+        // Three exits lower the threshold to 5 of 7, already passed
+        for id in 7..10 {
+            validators.slash(id, 0);
+        }
+        assert!(links.add_vote(&vote(6, source.clone(), target.clone()), &validators));
+        assert!(links.has_link(&source, &target));
+    }
+
+    #[test]
+    fn non_consecutive_link_is_not_a_finalization_candidate() {
+        let validators = ValidatorRegistry::new(0..10);
+        let (source, target) = (checkpoint("a", 1), checkpoint("c", 3));
+        let mut links = LinkIndex::default();
+        for id in 0..7 {
+            links.add_vote(&vote(id, source.clone(), target.clone()), &validators);
+        }
+        assert!(links.has_link(&source, &target));
+        assert_eq!(links.consecutive_target(&source), None);
+        assert_eq!(links.finalization_candidates().count(), 0);
+    }
+
+    #[test]
+    fn index_matches_rebuild_from_view() {
+        let validators = ValidatorRegistry::new(0..10);
+        let pairs = [(checkpoint("g", 0), checkpoint("a", 1)), (checkpoint("a", 1), checkpoint("b", 2))];
+        let mut view = View::default();
+        let mut incremental = LinkIndex::default();
+        for (source, target) in &pairs {
+            for id in 0..8 {
+                let vote = vote(id, source.clone(), target.clone());
+                incremental.add_vote(&vote, &validators);
+                view.insert_vote(vote);
+            }
+        }
+        let rebuilt = LinkIndex::from_view(&view, &validators);
+        for (source, target) in &pairs {
+            assert!(incremental.has_link(source, target));
+            assert!(rebuilt.has_link(source, target));
+        }
+    }
+
+    #[test]
+    fn support_reports_how_far_short_of_quorum() {
+        let validators = ValidatorRegistry::new(0..10);
+        let genesis = Block::genesis();
+        let target = checkpoint("a", 1);
+        let mut view = View::default();
+        view.blocks.insert(genesis.hash.clone(), genesis.clone());
+        view.blocks.insert("a".to_string(), Block {
+            hash: "a".to_string(), parent_hash: genesis.hash, slot: 1, proposer_id: 0, transactions: vec![],
+        });
+        for id in 0..6 {
+            view.insert_vote(vote(id, Checkpoint::genesis(), target.clone()));
+        }
+
+        let support = justification_support(&target, &view, &validators, &mut JustificationCache::new(16)).unwrap();
+        assert!(!support.justified);
+        assert_eq!(support.weight, 6);
+        assert_eq!(support.threshold, 7);
+        assert_eq!(support.voters, (0..6).collect());
+        assert_eq!(is_justified(&target, &view, &validators, &mut JustificationCache::new(16)), Ok(false));
+
+        view.insert_vote(vote(6, Checkpoint::genesis(), target.clone()));
+        let support = justification_support(&target, &view, &validators, &mut JustificationCache::new(16)).unwrap();
+        assert!(support.justified);
+        assert_eq!(is_justified(&target, &view, &validators, &mut JustificationCache::new(16)), Ok(true));
+    }
+
+    fn block(hash: &str, parent: &str, slot: u64) -> Block {
+        Block { hash: hash.to_string(), parent_hash: parent.to_string(), slot, proposer_id: 0, transactions: vec![] }
+    }
+
+    /// View of genesis followed by `c1..=c{length}`, one block per slot,
+    /// each checkpoint justified by a 7-of-10 link from the one before.
+    fn justified_chain(length: u64) -> View {
+        let mut view = View::default();
+        let genesis = Block::genesis();
+        view.blocks.insert(genesis.hash.clone(), genesis);
+        let mut source = Checkpoint::genesis();
+        for slot in 1..=length {
+            let target = checkpoint(&format!("c{}", slot), slot);
+            view.blocks.insert(target.block_hash.clone(), block(&target.block_hash, &source.block_hash, slot));
+            for id in 0..7 {
+                view.insert_vote(vote(id, source.clone(), target.clone()));
+            }
+            source = target;
+        }
+        view
+    }
+
+    #[test]
+    fn justification_path_walks_back_to_genesis() {
+        let validators = ValidatorRegistry::new(0..10);
+        let mut view = justified_chain(3);
+        let path = justification_path(&checkpoint("c3", 3), &view, &validators, &mut JustificationCache::new(16)).unwrap();
+        assert_eq!(path, [Checkpoint::genesis(), checkpoint("c1", 1), checkpoint("c2", 2), checkpoint("c3", 3)]);
+
+        // An unjustified checkpoint has no path at all
+        view.blocks.insert("x".to_string(), block("x", "c3", 4));
+        let path = justification_path(&checkpoint("x", 4), &view, &validators, &mut JustificationCache::new(16)).unwrap();
+        assert!(path.is_empty());
+    }
+
+    #[test]
+    fn justified_as_of_counts_only_votes_cast_by_then() {
+        let validators = ValidatorRegistry::new(0..10);
+        let mut view = View::default();
+        let genesis = Block::genesis();
+        view.blocks.insert("c3".to_string(), block("c3", &genesis.hash, 3));
+        view.blocks.insert(genesis.hash.clone(), genesis);
+        let target = checkpoint("c3", 3);
+        // Four votes land on time, three only in slot 5
+        for id in 0..7 {
+            let slot = if id < 4 { 3 } else { 5 };
+            view.insert_vote(Vote { slot, ..vote(id, Checkpoint::genesis(), target.clone()) });
+        }
+
+        // One cache serves every cutoff without mixing them up
+        let mut cache = HistoricalJustificationCache::new(16);
+        assert_eq!(is_justified_as_of(&target, &view, &validators, 3, &mut cache), Ok(false));
+        assert_eq!(is_justified_as_of(&target, &view, &validators, 4, &mut cache), Ok(false));
+        assert_eq!(is_justified_as_of(&target, &view, &validators, 5, &mut cache), Ok(true));
+        assert_eq!(is_justified_as_of(&target, &view, &validators, 3, &mut cache), Ok(false));
+        assert_eq!(is_justified(&target, &view, &validators, &mut JustificationCache::new(16)), Ok(true));
+    }
+
+    #[test]
+    fn malformed_checkpoints_are_errors() {
+        let validators = ValidatorRegistry::new(0..10);
+        let view = justified_chain(2);
+        let mut cache = JustificationCache::new(16);
+        assert_eq!(is_justified(&checkpoint("missing", 1), &view, &validators, &mut cache),
+                   Err(FfgError::UnknownBlock("missing".to_string())));
+        // c2's block is at slot 2, so a slot-1 checkpoint for it is impossible
+        assert_eq!(is_justified(&checkpoint("c2", 1), &view, &validators, &mut cache),
+                   Err(FfgError::InvalidCheckpoint(checkpoint("c2", 1))));
+        assert_eq!(justification_support(&checkpoint("missing", 1), &view, &validators, &mut cache),
+                   Err(FfgError::UnknownBlock("missing".to_string())));
+    }
+
+    #[test]
+    fn activated_validator_counts_only_from_its_activation_slot() {
+        let mut validators = ValidatorRegistry::new(0..8);
+        validators.schedule_activation(8, 4);
+        assert_eq!((validators.supermajority_threshold(3), validators.supermajority_threshold(4)), (6, 7));
+
+        let mut view = View::default();
+        let genesis = Block::genesis();
+        view.blocks.insert("c3".to_string(), block("c3", &genesis.hash, 3));
+        view.blocks.insert("c4".to_string(), block("c4", "c3", 4));
+        view.blocks.insert(genesis.hash.clone(), genesis);
+        let (c3, c4) = (checkpoint("c3", 3), checkpoint("c4", 4));
+        for id in (0..5).chain([8]) {
+            view.insert_vote(vote(id, Checkpoint::genesis(), c3.clone()));
+        }
+        for id in (0..6).chain([8]) {
+            view.insert_vote(vote(id, Checkpoint::genesis(), c4.clone()));
+        }
+
+        let mut cache = JustificationCache::new(16);
+        let before = justification_support(&c3, &view, &validators, &mut cache).unwrap();
+        assert_eq!((before.weight, before.justified), (5, false));
+        assert!(!before.voters.contains(&8));
+        let after = justification_support(&c4, &view, &validators, &mut cache).unwrap();
+        assert_eq!((after.weight, after.threshold, after.justified), (7, 7, true));
+        assert!(after.voters.contains(&8));
+    }
+
+    #[test]
+    fn spoofed_genesis_source_is_not_justified() {
+        let validators = ValidatorRegistry::new(0..10);
+        let mut view = justified_chain(0);
+        let genesis = Block::genesis().hash;
+        view.blocks.insert("c6".to_string(), block("c6", &genesis, 6));
+        let spoofed = checkpoint(&genesis, 5);
+        let target = checkpoint("c6", 6);
+        for id in 0..7 {
+            view.insert_vote(vote(id, spoofed.clone(), target.clone()));
+        }
+
+        // Genesis is only justified at slot 0, so these links have no justified source
+        let mut cache = JustificationCache::new(16);
+        assert_eq!(is_justified(&spoofed, &view, &validators, &mut cache), Ok(false));
+        assert_eq!(is_justified(&target, &view, &validators, &mut cache), Ok(false));
+        assert_eq!(justification_support(&target, &view, &validators, &mut cache).unwrap().weight, 0);
+    }
+
+    #[test]
+    fn deep_justification_chain_resolves_without_recursion() {
+        // A lone validator makes every single-vote link a supermajority
+        let validators = ValidatorRegistry::new(0..1);
+        let mut view = justified_chain(0);
+        let mut source = Checkpoint::genesis();
+        for slot in 1..=5000 {
+            let target = checkpoint(&format!("c{}", slot), slot);
+            view.blocks.insert(target.block_hash.clone(), block(&target.block_hash, &source.block_hash, slot));
+            view.insert_vote(vote(0, source, target.clone()));
+            source = target;
+        }
+        let mut cache = JustificationCache::new(8192);
+        assert_eq!(is_justified(&source, &view, &validators, &mut cache), Ok(true));
+        let path = justification_path(&source, &view, &validators, &mut cache).unwrap();
+        assert_eq!(path.len(), 5001);
+    }
+
+    #[test]
+    fn pruning_does_not_justify_links_from_unjustified_sources() {
+        // g - a1 - a2 - a3 - a4, with links g -> a2 -> a3 and a1 -> a4,
+        // where a1 itself was never justified
+        let validators = ValidatorRegistry::new(0..10);
+        let mut view = View::default();
+        let genesis = Block::genesis();
+        view.blocks.insert(genesis.hash.clone(), genesis.clone());
+        let mut parent = genesis.hash;
+        for slot in 1..=4 {
+            let hash = format!("a{}", slot);
+            view.blocks.insert(hash.clone(), block(&hash, &parent, slot));
+            parent = hash;
+        }
+        for (source, target) in [(Checkpoint::genesis(), checkpoint("a2", 2)), (checkpoint("a2", 2), checkpoint("a3", 3)), (checkpoint("a1", 1), checkpoint("a4", 4))] {
+            for id in 0..7 {
+                view.insert_vote(vote(id, source.clone(), target.clone()));
+            }
+        }
+        let status = |view: &View| -> Vec<bool> {
+            let mut cache = JustificationCache::new(16);
+            ["a1", "a2", "a3", "a4"].iter().zip(1..)
+                .map(|(hash, slot)| is_justified(&checkpoint(hash, slot), view, &validators, &mut cache).unwrap())
+                .collect()
+        };
+        assert_eq!(status(&view), [false, true, true, false]);
+        assert_eq!(is_finalized(&checkpoint("a2", 2), &view, &validators, &mut JustificationCache::new(16)), Ok(true));
+
+        prune_finalized(&mut view, &checkpoint("a2", 2), &validators, &mut JustificationCache::new(16)).unwrap();
+        assert!(view.votes.iter().any(|v| v.source == checkpoint("a1", 1)));
+        assert_eq!(status(&view), [false, true, true, false]);
+        assert_eq!(greatest_justified_checkpoint(&view, &validators, &mut JustificationCache::new(16)), checkpoint("a3", 3));
+    }
 }
@@ -2,13 +2,54 @@
 //! Based on Section 6.1 and Algorithm 5.
 
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
+use rand::SeedableRng;
 use crate::types::*;
-use crate::constants::ETA;
+use crate::config::{InclusionPenalty, TieBreak};
+use crate::constants::{ETA, PROPOSER_BOOST_PERCENT};
+use crate::validators::ValidatorRegistry;
+
+/// Errors from running fork choice over a malformed or partial view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ForkChoiceError {
+    /// The block to start the descent from is not in the view.
+    UnknownStart(Hash),
+    /// A block's ancestry has a gap, so its subtree can't be determined.
+    BrokenAncestry { block: Hash, missing_parent: Hash },
+}
+
+impl fmt::Display for ForkChoiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ForkChoiceError::UnknownStart(hash) => write!(f, "start block {} not in view", hash),
+            ForkChoiceError::BrokenAncestry { block, missing_parent } => {
+                write!(f, "ancestry of {} broken at missing block {}", block, missing_parent)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ForkChoiceError {}
 
 /// Filters votes using RLMD rules: keeps latest, removes expired and equivocating votes.
 /// This is `FIL_rlmd(V, t)` from Algorithm 5.
-fn filter_rlmd_votes(view: &View, current_slot: u64) -> HashMap<ValidatorId, Vote> {
+/// Votes from validators inactive in the vote's slot are dropped as well.
+///
+/// Following Algorithm 5, expiry runs before the equivocation filter: a
+/// validator loses its influence only while one of its equivocating vote
+/// pairs is unexpired. That removal is not permanent; once the conflicting
+/// votes age out of the `ETA` window, its later honest votes count again.
+/// Equivocation at any unexpired slot counts, not just at the latest one,
+/// so the result doesn't depend on the order votes arrived in.
+fn filter_rlmd_votes(
+    view: &View,
+    validators: &ValidatorRegistry,
+    current_slot: u64,
+) -> HashMap<ValidatorId, Vote> {
     let mut latest_votes: HashMap<ValidatorId, &Vote> = HashMap::new();
+    let mut heads_by_slot: HashMap<(ValidatorId, u64), &Hash> = HashMap::new();
     let mut equivocators = HashSet::new();
 
     // Find latest votes per validator and catch equivocators
@@ -17,16 +58,20 @@ fn filter_rlmd_votes(view: &View, current_slot: u64) -> HashMap<ValidatorId, Vot
         if vote.slot < current_slot.saturating_sub(ETA) {
             continue;
         }
+        // Skip votes from validators not active at the time
+        if !validators.is_active(vote.validator_id, vote.slot) {
+            continue;
+        }
 
-        if let Some(latest) = latest_votes.get(&vote.validator_id) {
-            if vote.slot > latest.slot {
-                latest_votes.insert(vote.validator_id, vote);
-            } else if vote.slot == latest.slot && vote.chain_head_hash != latest.chain_head_hash {
-                // Voting for different heads in same slot = equivocation
-                equivocators.insert(vote.validator_id);
-            }
-        } else {
-            latest_votes.insert(vote.validator_id, vote);
+        // Voting for different heads in same slot = equivocation
+        let head = heads_by_slot.entry((vote.validator_id, vote.slot)).or_insert(&vote.chain_head_hash);
+        if **head != vote.chain_head_hash {
+            equivocators.insert(vote.validator_id);
+        }
+
+        let latest = latest_votes.entry(vote.validator_id).or_insert(vote);
+        if vote.slot > latest.slot {
+            *latest = vote;
         }
     }
 
@@ -37,44 +82,829 @@ fn filter_rlmd_votes(view: &View, current_slot: u64) -> HashMap<ValidatorId, Vot
         .collect()
 }
 
+/// Subtree weight of every block reachable from `start_hash`.
+/// Each filtered vote adds its `vote_weight` to its head block and every
+/// ancestor down to `start_hash`; the boosted block's branch additionally
+/// gets proposer boost.
+/// A vote whose head can't be traced back to `start_hash` through the view,
+/// because a block in between is missing, counts for nothing.
+fn subtree_weights(
+    view: &View,
+    filtered_votes: &HashMap<ValidatorId, Vote>,
+    start_hash: &Hash,
+    vote_weight: impl Fn(&Vote) -> u64,
+    boost: Option<(&Hash, u64)>,
+) -> Result<HashMap<Hash, u64>, ForkChoiceError> {
+    if !view.blocks.contains_key(start_hash) {
+        return Err(ForkChoiceError::UnknownStart(start_hash.clone()));
+    }
+
+    // Every block reachable from start begins at zero weight
+    let mut weights = HashMap::new();
+    let mut frontier = vec![start_hash.clone()];
+    while let Some(hash) = frontier.pop() {
+        if weights.insert(hash.clone(), 0).is_some() {
+            continue; // Already visited (only possible in a malformed view)
+        }
+        frontier.extend(view.blocks.values()
+            .filter(|b| b.parent_hash == hash)
+            .map(|b| b.hash.clone()));
+    }
+
+    let weighted_heads = filtered_votes.values()
+        .map(|vote| (&vote.chain_head_hash, vote_weight(vote)))
+        .chain(boost);
+
+    for (head_hash, weight) in weighted_heads {
+        // Ignore votes for unknown blocks or blocks outside the subtree,
+        // including blocks cut off from it by a gap in their ancestry
+        if !weights.contains_key(head_hash) {
+            continue;
+        }
+        // Credit the head and each ancestor back to start
+        let mut current = head_hash;
+        loop {
+            *weights.get_mut(current).expect("ancestors of a reachable block are reachable") += weight;
+            if current == start_hash {
+                break;
+            }
+            let block = view.blocks.get(current).ok_or_else(|| ForkChoiceError::BrokenAncestry {
+                block: head_hash.clone(),
+                missing_parent: current.clone(),
+            })?;
+            current = &block.parent_hash;
+        }
+    }
+    Ok(weights)
+}
+
 /// GHOST rule: follow the heaviest subtree at each fork.
-/// This is `GHOST(V, B_start)` from Algorithm 5.
-fn ghost(view: &View, filtered_votes: &HashMap<ValidatorId, Vote>, start_hash: Hash) -> Hash {
+/// This is `GHOST(V, B_start)` from Algorithm 5, plus an optional proposer boost
+/// credited to every subtree containing the boosted block.
+fn ghost(
+    view: &View,
+    filtered_votes: &HashMap<ValidatorId, Vote>,
+    start_hash: Hash,
+    vote_weight: impl Fn(&Vote) -> u64,
+    boost: Option<(&Hash, u64)>,
+    tie_break: TieBreak,
+    current_slot: u64,
+) -> Result<Hash, ForkChoiceError> {
+    let weights = subtree_weights(view, filtered_votes, &start_hash, vote_weight, boost)?;
+    Ok(descend(view, start_hash, |hash| weights.get(hash).copied().unwrap_or(0), tie_break, current_slot))
+}
+
+/// Walk down from `start_hash`, taking the heaviest child by `weight` at
+/// each fork, until reaching a leaf.
+fn descend(
+    view: &View,
+    start_hash: Hash,
+    weight: impl Fn(&Hash) -> u64,
+    tie_break: TieBreak,
+    current_slot: u64,
+) -> Hash {
     let mut current_hash = start_hash;
 
     loop {
-        // Get all child blocks
-        let children: Vec<_> = view.blocks.values()
+        // Pick the child with most votes in its subtree, breaking ties by
+        // policy rather than map iteration order
+        let children: Vec<&Hash> = view.blocks.values()
             .filter(|b| b.parent_hash == current_hash)
+            .map(|b| &b.hash)
             .collect();
-
-        if children.is_empty() {
+        let Some(best_weight) = children.iter().map(|hash| weight(hash)).max() else {
             break; // No more children, we found the head
+        };
+        let tied: Vec<&Hash> = children.into_iter().filter(|hash| weight(hash) == best_weight).collect();
+        current_hash = break_tie(tied, tie_break, current_slot).clone();
+    }
+    current_hash
+}
+
+/// Pick one of `tied` (non-empty) according to `tie_break`. The candidates
+/// are sorted first, so the pick never depends on the order they came in.
+fn break_tie(mut tied: Vec<&Hash>, tie_break: TieBreak, current_slot: u64) -> &Hash {
+    tied.sort();
+    let pick = match tie_break {
+        TieBreak::HighestHash => tied.last(),
+        TieBreak::LowestHash => tied.first(),
+        TieBreak::Seeded(seed) => {
+            let mut rng = StdRng::seed_from_u64(seed ^ current_slot.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            tied.choose(&mut rng)
         }
+    };
+    pick.expect("tie-break needs at least one candidate")
+}
+
+/// Subtree weight of every block reachable from `start_hash`, using the same
+/// RLMD-filtered votes as `rlmd_ghost_fork_choice` (without proposer boost).
+/// This is the full weight map GHOST descends through, for debugging.
+pub fn weight_tree(
+    view: &View,
+    validators: &ValidatorRegistry,
+    start_hash: &Hash,
+    current_slot: u64,
+) -> Result<HashMap<Hash, u64>, ForkChoiceError> {
+    let filtered_votes = filter_rlmd_votes(view, validators, current_slot);
+    subtree_weights(view, &filtered_votes, start_hash, |_| 1, None)
+}
 
-        // Pick the child with most votes in its subtree
-        let best_child = children.iter()
-            .max_by_key(|child_block| {
-                filtered_votes.values().filter(|vote| {
-                    // Make sure the voted block exists in our view
-                    if let Some(vote_block) = view.blocks.get(&vote.chain_head_hash) {
-                        // Vote counts if it's for this child or any descendant
-                        child_block.hash == vote_block.hash || child_block.is_ancestor_of(vote_block, view)
-                    } else {
-                        false // Ignore votes for unknown blocks
-                    }
-                }).count()
-            })
-            .unwrap(); // There's always at least one child here
+/// Why `explain` followed a child at a fork.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChoiceReason {
+    /// The parent has no other child.
+    OnlyChild,
+    /// The child's subtree is strictly the heaviest.
+    Heaviest,
+    /// Several children share the greatest weight; the tie-break picked it.
+    TieBreak(TieBreak),
+    /// Nothing carries weight, so the head is the highest-slot descendant
+    /// and the child is the one leading to it.
+    ColdStart,
+}
 
-        current_hash = best_child.hash.clone();
+impl fmt::Display for ChoiceReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChoiceReason::OnlyChild => write!(f, "only child"),
+            ChoiceReason::Heaviest => write!(f, "heaviest"),
+            ChoiceReason::TieBreak(tie_break) => write!(f, "tie broken by {:?}", tie_break),
+            ChoiceReason::ColdStart => write!(f, "no weight, toward highest slot"),
+        }
     }
-    current_hash
+}
+
+/// One step of a GHOST descent, as traced by `explain`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForkChoiceStep {
+    pub parent: Hash,
+    /// Every child of `parent` with its subtree weight, heaviest first,
+    /// then by hash.
+    pub children: Vec<(Hash, u64)>,
+    pub chosen: Hash,
+    pub reason: ChoiceReason,
+}
+
+impl fmt::Display for ForkChoiceStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {} ({}) among", short_hash(&self.parent), short_hash(&self.chosen), self.reason)?;
+        for (child, weight) in &self.children {
+            write!(f, " {}: {}", short_hash(child), weight)?;
+        }
+        Ok(())
+    }
+}
+
+/// Trace of the RLMD-GHOST descent from `start_hash`: one step per block
+/// on the way to the head, recording its children's weights and why the
+/// descent went where it did. The last step's `chosen` is the head `rule`
+/// returns; an empty trace means `start_hash` is. With an inclusion penalty,
+/// weights are in percent of a vote, as `rule` counts them.
+pub fn explain(
+    view: &View,
+    validators: &ValidatorRegistry,
+    start_hash: &Hash,
+    current_slot: u64,
+    boosted_block: Option<&Hash>,
+    rule: RlmdGhost,
+) -> Result<Vec<ForkChoiceStep>, ForkChoiceError> {
+    let RlmdGhost { tie_break, inclusion_penalty } = rule;
+    let filtered_votes = filter_rlmd_votes(view, validators, current_slot);
+    let cold_start = filtered_votes.is_empty() && boosted_block.is_none();
+    let boost_percent = validators.active_count(current_slot) * PROPOSER_BOOST_PERCENT;
+    let boost_weight = if inclusion_penalty.is_some() { boost_percent } else { boost_percent / 100 };
+    let boost = boosted_block.map(|hash| (hash, boost_weight));
+    let vote_weight = |vote: &Vote| match inclusion_penalty {
+        Some(penalty) => penalty.retained_percent(vote.slot, view.inclusion_slot(vote)),
+        None => 1,
+    };
+    let weights = subtree_weights(view, &filtered_votes, start_hash, vote_weight, boost)?;
+    let cold_head = if cold_start {
+        Some(cold_start_head(view, start_hash.clone(), tie_break, current_slot)?)
+    } else {
+        None
+    };
+
+    let mut steps = Vec::new();
+    let mut current_hash = start_hash.clone();
+    loop {
+        let mut children: Vec<(Hash, u64)> = view.blocks.values()
+            .filter(|b| b.parent_hash == current_hash && b.hash != current_hash)
+            .map(|b| (b.hash.clone(), weights.get(&b.hash).copied().unwrap_or(0)))
+            .collect();
+        let Some(best_weight) = children.iter().map(|&(_, weight)| weight).max() else {
+            break;
+        };
+        children.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let (chosen, reason) = if let Some(head) = &cold_head {
+            let Some((child, _)) = children.iter().find(|(child, _)| view.ancestors(head).any(|b| b.hash == *child)) else {
+                break;
+            };
+            (child.clone(), ChoiceReason::ColdStart)
+        } else {
+            let tied: Vec<&Hash> = children.iter()
+                .filter(|&&(_, weight)| weight == best_weight)
+                .map(|(child, _)| child)
+                .collect();
+            let reason = if tied.len() == 1 { ChoiceReason::Heaviest } else { ChoiceReason::TieBreak(tie_break) };
+            (break_tie(tied, tie_break, current_slot).clone(), reason)
+        };
+        let reason = if children.len() == 1 { ChoiceReason::OnlyChild } else { reason };
+        steps.push(ForkChoiceStep { parent: current_hash, children, chosen: chosen.clone(), reason });
+        current_hash = chosen;
+    }
+    Ok(steps)
+}
+
+/// For each validator whose vote survives RLMD filtering, whether that vote
+/// counts toward `block`'s subtree, i.e. whether its head is `block` or a
+/// descendant. Expired votes and equivocators are absent.
+pub fn subtree_support(
+    view: &View,
+    validators: &ValidatorRegistry,
+    block: &Hash,
+    current_slot: u64,
+) -> HashMap<ValidatorId, bool> {
+    filter_rlmd_votes(view, validators, current_slot).into_iter()
+        .map(|(id, vote)| (id, view.ancestors(&vote.chain_head_hash).any(|b| b.hash == *block)))
+        .collect()
 }
 
 /// Complete RLMD-GHOST fork choice algorithm.
 /// This is `RLMD-GHOST(V, B_start, t)` from Algorithm 5.
-pub fn rlmd_ghost_fork_choice(view: &View, start_hash: Hash, current_slot: u64) -> Hash {
-    let filtered_votes = filter_rlmd_votes(view, current_slot);
-    ghost(view, &filtered_votes, start_hash)
+pub fn rlmd_ghost_fork_choice(
+    view: &View,
+    validators: &ValidatorRegistry,
+    start_hash: Hash,
+    current_slot: u64,
+) -> Result<Hash, ForkChoiceError> {
+    rlmd_ghost_fork_choice_with_boost(view, validators, start_hash, current_slot, None)
+}
+
+/// RLMD-GHOST with proposer boost applied to the current slot's proposal.
+/// Pass `None` when there is no (or an equivocating) proposal this slot.
+pub fn rlmd_ghost_fork_choice_with_boost(
+    view: &View,
+    validators: &ValidatorRegistry,
+    start_hash: Hash,
+    current_slot: u64,
+    boosted_block: Option<&Hash>,
+) -> Result<Hash, ForkChoiceError> {
+    RlmdGhost::default().head(view, validators, &start_hash, current_slot, boosted_block)
+}
+
+/// Head when nothing carries weight yet, e.g. a fresh node before any votes:
+/// the highest-slot block descending from `start_hash` (the tip of the only
+/// chain, if there is just one), or `start_hash` itself if it has no
+/// children. `tie_break` picks between forks reaching the same slot.
+fn cold_start_head(view: &View, start_hash: Hash, tie_break: TieBreak, current_slot: u64) -> Result<Hash, ForkChoiceError> {
+    let Some(start) = view.blocks.get(&start_hash) else {
+        return Err(ForkChoiceError::UnknownStart(start_hash));
+    };
+    let mut descendants = vec![start];
+    let mut frontier = vec![start];
+    while let Some(block) = frontier.pop() {
+        let children = view.blocks.values().filter(|b| b.parent_hash == block.hash && b.hash != block.hash);
+        for child in children {
+            descendants.push(child);
+            frontier.push(child);
+        }
+    }
+    let top_slot = descendants.iter().map(|b| b.slot).max().unwrap_or(start.slot);
+    let tips: Vec<&Hash> = descendants.iter().filter(|b| b.slot == top_slot).map(|b| &b.hash).collect();
+    Ok(break_tie(tips, tie_break, current_slot).clone())
+}
+
+/// RLMD-GHOST with the finalized block as an immovable root.
+/// Descends from `start_hash` (normally the GJC) when it extends
+/// `finalized_hash`, and from the finalized block itself otherwise, so the
+/// returned head never conflicts with finality however the votes fall.
+pub fn rlmd_ghost_fork_choice_with_finality(
+    view: &View,
+    validators: &ValidatorRegistry,
+    start_hash: Hash,
+    finalized_hash: &Hash,
+    current_slot: u64,
+    boosted_block: Option<&Hash>,
+) -> Result<Hash, ForkChoiceError> {
+    head_with_finality(&RlmdGhost::default(), view, validators, start_hash, finalized_hash, current_slot, boosted_block)
+}
+
+/// Run `rule` rooted at the finalized block, as
+/// `rlmd_ghost_fork_choice_with_finality` does for RLMD-GHOST.
+pub fn head_with_finality(
+    rule: &dyn ForkChoice,
+    view: &View,
+    validators: &ValidatorRegistry,
+    start_hash: Hash,
+    finalized_hash: &Hash,
+    current_slot: u64,
+    boosted_block: Option<&Hash>,
+) -> Result<Hash, ForkChoiceError> {
+    let start_hash = finality_root(view, start_hash, finalized_hash)?;
+    rule.head(view, validators, &start_hash, current_slot, boosted_block)
+}
+
+/// Where a finality-respecting descent starts: `start_hash` if it extends
+/// `finalized_hash`, else the finalized block itself.
+pub fn finality_root(view: &View, start_hash: Hash, finalized_hash: &Hash) -> Result<Hash, ForkChoiceError> {
+    if !view.blocks.contains_key(finalized_hash) {
+        return Err(ForkChoiceError::UnknownStart(finalized_hash.clone()));
+    }
+    if view.ancestors(&start_hash).any(|b| b.hash == *finalized_hash) {
+        Ok(start_hash)
+    } else {
+        Ok(finalized_hash.clone())
+    }
+}
+
+/// A fork-choice rule: picks a head among the descendants of `start_hash`.
+/// Nodes run `RlmdGhost`, as the protocol specifies; other rules can be
+/// swapped in to compare against it.
+pub trait ForkChoice: Send {
+    fn head(
+        &self,
+        view: &View,
+        validators: &ValidatorRegistry,
+        start_hash: &Hash,
+        current_slot: u64,
+        boosted_block: Option<&Hash>,
+    ) -> Result<Hash, ForkChoiceError>;
+}
+
+/// RLMD-GHOST with proposer boost, from Algorithm 5.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RlmdGhost {
+    /// Policy for forks of equal weight.
+    pub tie_break: TieBreak,
+    /// Discount for votes included late, per the view's inclusion slots.
+    /// Weights are then in percent of a vote, boost included.
+    pub inclusion_penalty: Option<InclusionPenalty>,
+}
+
+impl ForkChoice for RlmdGhost {
+    fn head(
+        &self,
+        view: &View,
+        validators: &ValidatorRegistry,
+        start_hash: &Hash,
+        current_slot: u64,
+        boosted_block: Option<&Hash>,
+    ) -> Result<Hash, ForkChoiceError> {
+        let filtered_votes = filter_rlmd_votes(view, validators, current_slot);
+        if filtered_votes.is_empty() && boosted_block.is_none() {
+            return cold_start_head(view, start_hash.clone(), self.tie_break, current_slot);
+        }
+        let start_hash = start_hash.clone();
+        let boost_percent = validators.active_count(current_slot) * PROPOSER_BOOST_PERCENT;
+        match self.inclusion_penalty {
+            None => {
+                let boost = boosted_block.map(|hash| (hash, boost_percent / 100));
+                ghost(view, &filtered_votes, start_hash, |_| 1, boost, self.tie_break, current_slot)
+            }
+            Some(penalty) => {
+                let vote_weight = |vote: &Vote| penalty.retained_percent(vote.slot, view.inclusion_slot(vote));
+                let boost = boosted_block.map(|hash| (hash, boost_percent));
+                ghost(view, &filtered_votes, start_hash, vote_weight, boost, self.tie_break, current_slot)
+            }
+        }
+    }
+}
+
+/// RLMD-GHOST weights maintained vote by vote, so the head can be read
+/// without refiltering the whole view. Tracks each validator's latest vote
+/// and how many latest votes sit in each block's subtree; when a validator's
+/// latest vote moves, its weight leaves the old head's chain and joins the
+/// new one. Expiry and equivocation depend on the slot asked about, so
+/// `head` backs those votes out at read time, giving the same head as
+/// `RlmdGhost` over the same view.
+#[derive(Debug, Clone, Default)]
+pub struct VoteWeights {
+    latest: HashMap<ValidatorId, Vote>,
+    weights: HashMap<Hash, u64>,
+    // First head seen from each (validator, slot)
+    heads: HashMap<(ValidatorId, u64), Hash>,
+    // Latest slot each validator voted for two heads in
+    equivocated: HashMap<ValidatorId, u64>,
+    // Votes added, to spot a view edited behind our back
+    votes: usize,
+}
+
+impl VoteWeights {
+    /// Build the weights from every vote in a view.
+    pub fn from_view(view: &View, validators: &ValidatorRegistry) -> Self {
+        let mut weights = VoteWeights::default();
+        for vote in &view.votes {
+            weights.add_vote(vote, view, validators);
+        }
+        weights
+    }
+
+    /// Number of votes added so far, active or not. Matches the length of
+    /// the view's vote list while the two are kept in step.
+    pub fn vote_count(&self) -> usize {
+        self.votes
+    }
+
+    /// Record a vote already in `view`. Votes from validators inactive in
+    /// the vote's slot are ignored, as `filter_rlmd_votes` ignores them.
+    pub fn add_vote(&mut self, vote: &Vote, view: &View, validators: &ValidatorRegistry) {
+        self.votes += 1;
+        if !validators.is_active(vote.validator_id, vote.slot) {
+            return;
+        }
+        let head = self.heads.entry((vote.validator_id, vote.slot)).or_insert_with(|| vote.chain_head_hash.clone());
+        if *head != vote.chain_head_hash {
+            let slot = self.equivocated.entry(vote.validator_id).or_insert(vote.slot);
+            *slot = (*slot).max(vote.slot);
+        }
+
+        let old_head = match self.latest.get(&vote.validator_id) {
+            Some(latest) if latest.slot >= vote.slot => return,
+            Some(latest) => Some(latest.chain_head_hash.clone()),
+            None => None,
+        };
+        if let Some(old_head) = old_head {
+            for block in view.ancestors(&old_head) {
+                if let Some(weight) = self.weights.get_mut(&block.hash) {
+                    *weight = weight.saturating_sub(1);
+                }
+            }
+        }
+        for block in view.ancestors(&vote.chain_head_hash) {
+            *self.weights.entry(block.hash.clone()).or_insert(0) += 1;
+        }
+        self.latest.insert(vote.validator_id, vote.clone());
+    }
+
+    /// Each validator's latest vote that counts in fork choice at
+    /// `current_slot`, i.e. what `filter_rlmd_votes` would keep.
+    pub fn counted_votes(&self, current_slot: u64) -> impl Iterator<Item = &Vote> {
+        self.latest.values().filter(move |vote| self.counts(vote, current_slot))
+    }
+
+    /// Whether a latest vote counts at `current_slot`: it hasn't expired,
+    /// and its validator has no unexpired equivocation.
+    fn counts(&self, vote: &Vote, current_slot: u64) -> bool {
+        let cutoff = current_slot.saturating_sub(ETA);
+        vote.slot >= cutoff && self.equivocated.get(&vote.validator_id).is_none_or(|&slot| slot < cutoff)
+    }
+
+    /// RLMD-GHOST head from `start_hash` at `current_slot`, as
+    /// `RlmdGhost { tie_break }` would compute it over `view`.
+    pub fn head(
+        &self,
+        view: &View,
+        validators: &ValidatorRegistry,
+        start_hash: &Hash,
+        current_slot: u64,
+        boosted_block: Option<&Hash>,
+        tie_break: TieBreak,
+    ) -> Result<Hash, ForkChoiceError> {
+        if !view.blocks.contains_key(start_hash) {
+            return Err(ForkChoiceError::UnknownStart(start_hash.clone()));
+        }
+        let discounted: Vec<&Vote> = self.latest.values()
+            .filter(|vote| !self.counts(vote, current_slot))
+            .collect();
+        if discounted.len() == self.latest.len() && boosted_block.is_none() {
+            return cold_start_head(view, start_hash.clone(), tie_break, current_slot);
+        }
+
+        let mut adjustments: HashMap<&Hash, (u64, u64)> = HashMap::new();
+        for vote in discounted {
+            for block in view.ancestors(&vote.chain_head_hash) {
+                adjustments.entry(&block.hash).or_default().1 += 1;
+            }
+        }
+        if let Some(boosted) = boosted_block {
+            let boost_weight = validators.active_count(current_slot) * PROPOSER_BOOST_PERCENT / 100;
+            for block in view.ancestors(boosted) {
+                adjustments.entry(&block.hash).or_default().0 += boost_weight;
+            }
+        }
+        let weight = |hash: &Hash| {
+            let (added, removed) = adjustments.get(hash).copied().unwrap_or_default();
+            (self.weights.get(hash).copied().unwrap_or(0) + added).saturating_sub(removed)
+        };
+        Ok(descend(view, start_hash.clone(), weight, tie_break, current_slot))
+    }
+}
+
+/// Longest chain: the block furthest (in blocks) from `start_hash`,
+/// ignoring votes and boost entirely. The highest hash breaks ties.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LongestChain;
+
+impl ForkChoice for LongestChain {
+    fn head(
+        &self,
+        view: &View,
+        _validators: &ValidatorRegistry,
+        start_hash: &Hash,
+        _current_slot: u64,
+        _boosted_block: Option<&Hash>,
+    ) -> Result<Hash, ForkChoiceError> {
+        if !view.blocks.contains_key(start_hash) {
+            return Err(ForkChoiceError::UnknownStart(start_hash.clone()));
+        }
+        let mut deepest = (0, start_hash.clone());
+        let mut frontier = vec![(0, start_hash.clone())];
+        while let Some((depth, hash)) = frontier.pop() {
+            if (depth, &hash) > (deepest.0, &deepest.1) {
+                deepest = (depth, hash.clone());
+            }
+            frontier.extend(view.blocks.values()
+                .filter(|b| b.parent_hash == hash && b.hash != hash)
+                .map(|b| (depth + 1, b.hash.clone())));
+        }
+        Ok(deepest.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(hash: &str, parent: &str, slot: u64) -> Block {
+        Block { hash: hash.to_string(), parent_hash: parent.to_string(), slot, proposer_id: 0, transactions: vec![] }
+    }
+
+    fn head_vote(validator_id: ValidatorId, head: &str, slot: u64) -> Vote {
+        let genesis = Checkpoint::genesis();
+        Vote { chain_head_hash: head.to_string(), source: genesis.clone(), target: genesis, slot, validator_id, signature: Signature::new() }
+    }
+
+    /// Genesis with children `a` (slot 1) and `b` (slot 2, on `a`).
+    fn chain() -> View {
+        let mut view = View::default();
+        let genesis = Block::genesis();
+        view.blocks.insert("a".to_string(), block("a", &genesis.hash, 1));
+        view.blocks.insert("b".to_string(), block("b", "a", 2));
+        view.blocks.insert(genesis.hash.clone(), genesis);
+        view
+    }
+
+    #[test]
+    fn unknown_start_is_an_error() {
+        let validators = ValidatorRegistry::new(0..4);
+        let view = chain();
+        let missing = "missing".to_string();
+        assert_eq!(rlmd_ghost_fork_choice(&view, &validators, missing.clone(), 2),
+                   Err(ForkChoiceError::UnknownStart(missing.clone())));
+        assert_eq!(weight_tree(&view, &validators, &missing, 2), Err(ForkChoiceError::UnknownStart(missing.clone())));
+        assert_eq!(explain(&view, &validators, &missing, 2, None, RlmdGhost::default()).unwrap_err(),
+                   ForkChoiceError::UnknownStart(missing.clone()));
+    }
+
+    #[test]
+    fn unknown_finalized_block_is_an_error() {
+        let validators = ValidatorRegistry::new(0..4);
+        let view = chain();
+        let missing = "missing".to_string();
+        assert_eq!(finality_root(&view, "b".to_string(), &missing), Err(ForkChoiceError::UnknownStart(missing.clone())));
+        assert_eq!(rlmd_ghost_fork_choice_with_finality(&view, &validators, "a".to_string(), &missing, 2, None),
+                   Err(ForkChoiceError::UnknownStart(missing)));
+    }
+
+    #[test]
+    fn broken_ancestry_is_an_error() {
+        // A block filed under the wrong key can be reached from its parent
+        // but not walked back from
+        let validators = ValidatorRegistry::new(0..4);
+        let mut view = chain();
+        view.blocks.insert("filed_as".to_string(), block("c", "b", 3));
+        view.insert_vote(head_vote(0, "c", 3));
+        assert_eq!(rlmd_ghost_fork_choice(&view, &validators, "a".to_string(), 3),
+                   Err(ForkChoiceError::BrokenAncestry { block: "c".to_string(), missing_parent: "c".to_string() }));
+    }
+
+    #[test]
+    fn votes_for_unknown_or_detached_blocks_count_for_nothing() {
+        let validators = ValidatorRegistry::new(0..4);
+        let mut view = chain();
+        view.blocks.insert("orphan".to_string(), block("orphan", "missing", 3));
+        view.insert_vote(head_vote(0, "unknown", 2));
+        view.insert_vote(head_vote(1, "orphan", 3));
+        view.insert_vote(head_vote(2, "a", 1));
+        let genesis = Block::genesis().hash;
+        assert_eq!(rlmd_ghost_fork_choice(&view, &validators, genesis.clone(), 3), Ok("b".to_string()));
+        let weights = weight_tree(&view, &validators, &genesis, 3).unwrap();
+        assert_eq!((weights[&genesis], weights["a"], weights["b"]), (1, 1, 0));
+        assert!(!weights.contains_key("orphan"));
+    }
+
+    #[test]
+    fn weight_tree_sums_votes_up_the_tree() {
+        // genesis - a - b
+        //             \ c - d
+        let validators = ValidatorRegistry::new(0..6);
+        let mut view = chain();
+        view.blocks.insert("c".to_string(), block("c", "a", 2));
+        view.blocks.insert("d".to_string(), block("d", "c", 3));
+        for (id, head) in [(0, "b"), (1, "b"), (2, "d"), (3, "a"), (4, "b")] {
+            view.insert_vote(head_vote(id, head, 3));
+        }
+        // An expired vote is filtered out as ghost would
+        view.insert_vote(head_vote(5, "d", 0));
+
+        let genesis = Block::genesis().hash;
+        let weights = weight_tree(&view, &validators, &genesis, 3 + ETA).unwrap();
+        let expected = [(genesis.as_str(), 5), ("a", 5), ("b", 3), ("c", 1), ("d", 1)];
+        assert_eq!(weights, expected.iter().map(|&(hash, weight)| (hash.to_string(), weight)).collect());
+        // Each block weighs its own votes plus its children's subtrees
+        for (hash, weight) in &weights {
+            let own = view.votes.iter().filter(|v| v.chain_head_hash == *hash && v.slot == 3).count() as u64;
+            let children: u64 = view.blocks.values().filter(|b| b.parent_hash == *hash).map(|b| weights[&b.hash]).sum();
+            assert_eq!(*weight, own + children, "{}", hash);
+        }
+        assert_eq!(rlmd_ghost_fork_choice(&view, &validators, genesis, 3 + ETA), Ok("b".to_string()));
+    }
+
+    #[test]
+    fn old_equivocation_stops_counting_once_expired() {
+        let validators = ValidatorRegistry::new(0..4);
+        let mut view = chain();
+        view.blocks.insert("c".to_string(), block("c", "a", 2));
+        // Validator 0 equivocates in slot 1, then votes honestly in slot 1 + ETA
+        view.insert_vote(head_vote(0, "a", 1));
+        view.insert_vote(head_vote(0, "b", 1));
+        let honest_slot = 1 + ETA;
+        view.insert_vote(head_vote(0, "c", honest_slot));
+
+        let supports = |slot| subtree_support(&view, &validators, &"c".to_string(), slot);
+        // While the conflicting pair is unexpired, validator 0 weighs nothing
+        assert_eq!(supports(honest_slot).get(&0), None);
+        // Once it ages out, the later honest vote counts again
+        assert_eq!(supports(honest_slot + 1).get(&0), Some(&true));
+        assert_eq!(rlmd_ghost_fork_choice(&view, &validators, Block::genesis().hash, honest_slot + 1), Ok("c".to_string()));
+    }
+
+    #[test]
+    fn fork_choice_never_leaves_the_finalized_block() {
+        // genesis - a (finalized) - b
+        //         \ f - g, with most of the votes
+        let validators = ValidatorRegistry::new(0..4);
+        let mut view = chain();
+        let genesis = Block::genesis().hash;
+        view.blocks.insert("f".to_string(), block("f", &genesis, 1));
+        view.blocks.insert("g".to_string(), block("g", "f", 2));
+        for id in 0..3 {
+            view.insert_vote(head_vote(id, "g", 2));
+        }
+        view.insert_vote(head_vote(3, "b", 2));
+        let finalized = "a".to_string();
+
+        assert_eq!(rlmd_ghost_fork_choice(&view, &validators, genesis.clone(), 2), Ok("g".to_string()));
+        assert_eq!(rlmd_ghost_fork_choice_with_finality(&view, &validators, genesis.clone(), &finalized, 2, None),
+                   Ok("b".to_string()));
+        // Even a start on the conflicting fork is rooted back at the finalized block
+        assert_eq!(rlmd_ghost_fork_choice_with_finality(&view, &validators, "f".to_string(), &finalized, 2, Some(&"g".to_string())),
+                   Ok("b".to_string()));
+        assert_eq!(finality_root(&view, "b".to_string(), &finalized), Ok("b".to_string()));
+    }
+
+    #[test]
+    fn cold_start_with_only_genesis_stays_at_genesis() {
+        let validators = ValidatorRegistry::new(0..4);
+        let genesis = Block::genesis();
+        let mut view = View::default();
+        view.blocks.insert(genesis.hash.clone(), genesis.clone());
+        assert_eq!(rlmd_ghost_fork_choice(&view, &validators, genesis.hash.clone(), 1), Ok(genesis.hash));
+    }
+
+    #[test]
+    fn cold_start_follows_the_only_chain_to_its_tip() {
+        let validators = ValidatorRegistry::new(0..4);
+        let genesis = Block::genesis().hash;
+        let mut view = chain();
+        assert_eq!(rlmd_ghost_fork_choice(&view, &validators, genesis.clone(), 3), Ok("b".to_string()));
+        assert_eq!(rlmd_ghost_fork_choice(&view, &validators, "b".to_string(), 3), Ok("b".to_string()));
+
+        // With a single block on genesis, that block is the head
+        view.blocks.remove("b");
+        assert_eq!(rlmd_ghost_fork_choice(&view, &validators, genesis, 3), Ok("a".to_string()));
+    }
+
+    #[test]
+    fn each_tie_break_policy_is_reproducible() {
+        // Eight children of genesis with one vote each
+        let validators = ValidatorRegistry::new(0..8);
+        let genesis = Block::genesis();
+        let mut view = View::default();
+        for id in 0..8 {
+            let child = format!("c{}", id);
+            view.blocks.insert(child.clone(), block(&child, &genesis.hash, 1));
+            view.insert_vote(head_vote(id, &child, 1));
+        }
+        view.blocks.insert(genesis.hash.clone(), genesis.clone());
+        let head = |tie_break: TieBreak, slot: u64| {
+            RlmdGhost { tie_break, inclusion_penalty: None }.head(&view, &validators, &genesis.hash, slot, None).unwrap()
+        };
+
+        assert_eq!(head(TieBreak::HighestHash, 2), "c7");
+        assert_eq!(head(TieBreak::LowestHash, 2), "c0");
+        for seed in 0..16 {
+            assert_eq!(head(TieBreak::Seeded(seed), 2), head(TieBreak::Seeded(seed), 2));
+        }
+        // Seeds and slots vary the pick
+        let picks: HashSet<Hash> = (0..16).map(|seed| head(TieBreak::Seeded(seed), 2)).collect();
+        assert!(picks.len() > 1);
+        let picks: HashSet<Hash> = (2..6).map(|slot| head(TieBreak::Seeded(7), slot)).collect();
+        assert!(picks.len() > 1);
+    }
+
+    #[test]
+    fn votes_beyond_a_gap_in_the_chain_are_not_counted() {
+        // genesis - a - b, and c - d hanging off a missing block m on b
+        let validators = ValidatorRegistry::new(0..4);
+        let mut view = chain();
+        view.blocks.insert("c".to_string(), block("c", "m", 4));
+        view.blocks.insert("d".to_string(), block("d", "c", 5));
+        for id in 0..3 {
+            view.insert_vote(head_vote(id, "d", 5));
+        }
+        view.insert_vote(head_vote(3, "a", 5));
+
+        let genesis = Block::genesis().hash;
+        assert_eq!(rlmd_ghost_fork_choice(&view, &validators, genesis.clone(), 5), Ok("b".to_string()));
+        let weights = weight_tree(&view, &validators, &genesis, 5).unwrap();
+        assert_eq!((weights[&genesis], weights["a"], weights["b"]), (1, 1, 0));
+        assert!(!weights.contains_key("c") && !weights.contains_key("d"));
+        assert_eq!(view.blocks["b"].try_is_ancestor_of(&view.blocks["d"], &view), Err("m".to_string()));
+    }
+
+    #[test]
+    fn late_included_votes_weigh_less_under_the_penalty() {
+        let validators = ValidatorRegistry::new(0..4);
+        let genesis = Block::genesis();
+        let mut view = View::default();
+        for block in [genesis.clone(), block("a1", &genesis.hash, 1), block("b1", &genesis.hash, 1)] {
+            view.blocks.insert(block.hash.clone(), block);
+        }
+        // Two votes for a1 only made it into the view two slots late
+        for (id, head, included) in [(0, "a1", 3), (1, "a1", 3), (2, "b1", 1)] {
+            let vote = head_vote(id, head, 1);
+            view.record_inclusion(&vote, included);
+            view.insert_vote(vote);
+        }
+        let penalty = InclusionPenalty { grace_slots: 0, percent_per_slot: 40 };
+        assert_eq!((penalty.retained_percent(1, 3), penalty.retained_percent(1, 1)), (20, 100));
+
+        let head = |inclusion_penalty| {
+            RlmdGhost { tie_break: TieBreak::default(), inclusion_penalty }.head(&view, &validators, &genesis.hash, 3, None).unwrap()
+        };
+        assert_eq!(head(None), "a1");
+        assert_eq!(head(Some(penalty)), "b1");
+        // Within the grace period the late votes count in full again
+        assert_eq!(head(Some(InclusionPenalty { grace_slots: 2, ..penalty })), "a1");
+
+        // explain weighs the votes the same way, in percent of a vote
+        let rule = RlmdGhost { tie_break: TieBreak::default(), inclusion_penalty: Some(penalty) };
+        let steps = explain(&view, &validators, &genesis.hash, 3, None, rule).unwrap();
+        assert_eq!(steps[0].children, vec![("b1".to_string(), 100), ("a1".to_string(), 40)]);
+        assert_eq!(steps[0].chosen, "b1");
+    }
+
+    #[test]
+    fn explain_lists_every_child_with_its_weight() {
+        // genesis - a1 - a2
+        //         \ b1
+        let validators = ValidatorRegistry::new(0..4);
+        let genesis = Block::genesis();
+        let mut view = View::default();
+        for block in [genesis.clone(), block("a1", &genesis.hash, 1), block("a2", "a1", 2), block("b1", &genesis.hash, 1)] {
+            view.blocks.insert(block.hash.clone(), block);
+        }
+        for (id, head) in [(0, "a2"), (1, "a2"), (2, "b1")] {
+            view.insert_vote(head_vote(id, head, 2));
+        }
+        let steps = explain(&view, &validators, &genesis.hash, 3, None, RlmdGhost::default()).unwrap();
+        assert_eq!(steps, vec![
+            ForkChoiceStep {
+                parent: genesis.hash.clone(),
+                children: vec![("a1".to_string(), 2), ("b1".to_string(), 1)],
+                chosen: "a1".to_string(),
+                reason: ChoiceReason::Heaviest,
+            },
+            ForkChoiceStep {
+                parent: "a1".to_string(),
+                children: vec![("a2".to_string(), 2)],
+                chosen: "a2".to_string(),
+                reason: ChoiceReason::OnlyChild,
+            },
+        ]);
+
+        // An even split is settled by the tie-break, and explain agrees with head
+        view.insert_vote(head_vote(3, "b1", 2));
+        for tie_break in [TieBreak::HighestHash, TieBreak::LowestHash] {
+            let rule = RlmdGhost { tie_break, inclusion_penalty: None };
+            let steps = explain(&view, &validators, &genesis.hash, 3, None, rule).unwrap();
+            assert_eq!(steps[0].children, vec![("a1".to_string(), 2), ("b1".to_string(), 2)]);
+            assert_eq!(steps[0].reason, ChoiceReason::TieBreak(tie_break));
+            let head = rule.head(&view, &validators, &genesis.hash, 3, None).unwrap();
+            assert_eq!(steps.last().unwrap().chosen, head);
+        }
+    }
 }
@@ -0,0 +1,159 @@
+//! Multi-hop gossip network for the simulation.
+//! Each node relays newly seen messages to a random subset of peers for a
+//! few rounds, so delivery takes several hops instead of one all-to-all broadcast.
+
+use std::collections::HashSet;
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
+use rand::SeedableRng;
+use crate::node::Node;
+use crate::types::*;
+
+/// Rounds a node keeps relaying a message after first seeing it.
+pub const DEFAULT_RELAY_ROUNDS: usize = 8;
+
+/// Gossip overlay connecting a fixed set of nodes.
+/// Peer selection is driven by a seeded RNG, so runs are reproducible.
+pub struct GossipNetwork {
+    fanout: usize,
+    relay_rounds: usize,
+    rng: StdRng,
+    inboxes: Vec<Vec<Envelope>>,          // Messages arriving at each node next round
+    relaying: Vec<Vec<(Envelope, usize)>>, // Messages each node still relays, with rounds left
+    seen: Vec<HashSet<Envelope>>,          // Messages each node has already processed
+}
+
+impl GossipNetwork {
+    /// Create a network of `num_nodes` peers relaying to `fanout` peers per round.
+    pub fn new(num_nodes: usize, fanout: usize, seed: u64) -> Self {
+        GossipNetwork {
+            fanout,
+            relay_rounds: DEFAULT_RELAY_ROUNDS,
+            rng: StdRng::seed_from_u64(seed),
+            inboxes: vec![Vec::new(); num_nodes],
+            relaying: vec![Vec::new(); num_nodes],
+            seen: vec![HashSet::new(); num_nodes],
+        }
+    }
+
+    /// Override how many rounds each node relays a message for.
+    pub fn with_relay_rounds(mut self, relay_rounds: usize) -> Self {
+        self.relay_rounds = relay_rounds;
+        self
+    }
+
+    /// Inject a message at `origin`; it is processed there on the next round.
+    pub fn publish(&mut self, origin: usize, msg: Envelope) {
+        self.inboxes[origin].push(msg);
+    }
+
+    /// Run one gossip round: deliver pending messages, then relay.
+    /// Returns how many messages were new to their receiver.
+    pub fn round(&mut self, nodes: &mut [Node]) -> usize {
+        let mut delivered = 0;
+
+        // Deliver everything that arrived since the last round
+        for (i, node) in nodes.iter_mut().enumerate() {
+            for msg in std::mem::take(&mut self.inboxes[i]) {
+                if !self.seen[i].insert(msg.clone()) {
+                    continue; // Already processed, don't relay again
+                }
+                delivered += 1;
+                node.handle_message(msg.clone());
+                self.relaying[i].push((msg, self.relay_rounds));
+            }
+        }
+
+        // Relay each active message to a random subset of the other peers
+        let num_nodes = self.inboxes.len();
+        for i in 0..num_nodes {
+            let peers: Vec<usize> = (0..num_nodes).filter(|&p| p != i).collect();
+            for (msg, rounds_left) in self.relaying[i].iter_mut() {
+                for &peer in peers.choose_multiple(&mut self.rng, self.fanout) {
+                    self.inboxes[peer].push(msg.clone());
+                }
+                *rounds_left -= 1;
+            }
+            self.relaying[i].retain(|(_, rounds_left)| *rounds_left > 0);
+        }
+        delivered
+    }
+
+    /// Run rounds until no messages are in flight or `max_rounds` is hit.
+    /// Returns the number of rounds executed.
+    pub fn run_until_quiet(&mut self, nodes: &mut [Node], max_rounds: usize) -> usize {
+        let mut rounds = 0;
+        while rounds < max_rounds && !self.is_quiet() {
+            self.round(nodes);
+            rounds += 1;
+        }
+        rounds
+    }
+
+    /// True once nothing is waiting for delivery or relay.
+    pub fn is_quiet(&self) -> bool {
+        self.inboxes.iter().all(|inbox| inbox.is_empty())
+            && self.relaying.iter().all(|active| active.is_empty())
+    }
+
+    /// Whether node `i` has processed `msg`.
+    pub fn has_seen(&self, i: usize, msg: &Envelope) -> bool {
+        self.seen[i].contains(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vote_from_node_zero(nodes: &[Node]) -> Envelope {
+        let genesis = Checkpoint::genesis();
+        let vote = Vote {
+            chain_head_hash: genesis.block_hash.clone(), source: genesis.clone(), target: genesis,
+            slot: 0, validator_id: 0, signature: Signature::new(),
+        };
+        Envelope { fork_digest: nodes[0].fork_digest(), message: Message::Vote(vote) }
+    }
+
+    /// Rounds until every node has seen a vote published at node 0, if it spreads.
+    fn rounds_to_reach_all(num_nodes: usize, fanout: usize, seed: u64) -> Option<usize> {
+        let mut nodes: Vec<Node> = (0..num_nodes as ValidatorId).map(Node::new).collect();
+        let msg = vote_from_node_zero(&nodes);
+        let mut network = GossipNetwork::new(num_nodes, fanout, seed);
+        network.publish(0, msg.clone());
+        for round in 1..=32 {
+            network.round(&mut nodes);
+            if (0..num_nodes).all(|i| network.has_seen(i, &msg)) {
+                return Some(round);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn vote_reaches_every_node_within_bounded_rounds() {
+        for fanout in [2, 3] {
+            for seed in 0..10 {
+                let rounds = rounds_to_reach_all(30, fanout, seed);
+                assert!(rounds.is_some_and(|r| r <= 8), "fanout {} seed {}: {:?}", fanout, seed, rounds);
+            }
+        }
+    }
+
+    #[test]
+    fn peer_selection_is_deterministic_per_seed() {
+        // Who has the vote after each round depends only on the seed
+        let coverage = |seed| {
+            let mut nodes: Vec<Node> = (0..30).map(Node::new).collect();
+            let msg = vote_from_node_zero(&nodes);
+            let mut network = GossipNetwork::new(30, 2, seed);
+            network.publish(0, msg.clone());
+            (0..4).map(|_| {
+                network.round(&mut nodes);
+                (0..30).filter(|&i| network.has_seen(i, &msg)).collect::<Vec<_>>()
+            }).collect::<Vec<_>>()
+        };
+        assert_eq!(coverage(7), coverage(7));
+        assert_ne!(coverage(7), coverage(8));
+    }
+}
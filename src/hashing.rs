@@ -0,0 +1,91 @@
+//! Pluggable hash functions for computing block hashes.
+//! The demo names blocks by slot and proposer; a `Hasher` gives them
+//! content-derived hashes instead, e.g. to match an external chain.
+
+use sha2::{Digest, Sha256};
+use crate::types::Hash;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Turns bytes into a hash, rendered as lowercase hex.
+pub trait Hasher: Send + Sync {
+    fn hash(&self, bytes: &[u8]) -> Hash;
+}
+
+/// SHA-256.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha256Hasher;
+
+impl Hasher for Sha256Hasher {
+    fn hash(&self, bytes: &[u8]) -> Hash {
+        hex(&Sha256::digest(bytes))
+    }
+}
+
+/// BLAKE3, much faster than SHA-256 in software.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Blake3Hasher;
+
+impl Hasher for Blake3Hasher {
+    fn hash(&self, bytes: &[u8]) -> Hash {
+        hex(blake3::hash(bytes).as_bytes())
+    }
+}
+
+/// Hash function selectable through `ProtocolConfig`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum HashFunction {
+    #[default]
+    Sha256,
+    Blake3,
+}
+
+impl Hasher for HashFunction {
+    fn hash(&self, bytes: &[u8]) -> Hash {
+        match self {
+            HashFunction::Sha256 => Sha256Hasher.hash(bytes),
+            HashFunction::Blake3 => Blake3Hasher.hash(bytes),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> Hash {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Block, View};
+
+    /// A block on `parent` at `slot`, named by `hasher`.
+    fn hashed_block(hasher: &dyn Hasher, parent: &Block, slot: u64) -> Block {
+        let mut block = Block { hash: String::new(), parent_hash: parent.hash.clone(), slot, proposer_id: 1, transactions: vec![] };
+        block.hash = block.compute_hash(hasher);
+        block
+    }
+
+    #[test]
+    fn hashers_disagree_but_each_chains_blocks() {
+        let genesis = Block::genesis();
+        let sha = hashed_block(&Sha256Hasher, &genesis, 1);
+        let blake = hashed_block(&Blake3Hasher, &genesis, 1);
+        assert_ne!(sha.hash, blake.hash);
+        assert_eq!(sha.hash, hashed_block(&HashFunction::Sha256, &genesis, 1).hash);
+        assert_eq!(blake.hash, hashed_block(&HashFunction::Blake3, &genesis, 1).hash);
+
+        for hasher in [HashFunction::Sha256, HashFunction::Blake3] {
+            let mut view = View::default();
+            let first = hashed_block(&hasher, &genesis, 1);
+            let second = hashed_block(&hasher, &first, 2);
+            assert_eq!(second.hash.len(), 64);
+            for block in [genesis.clone(), first.clone(), second.clone()] {
+                view.blocks.insert(block.hash.clone(), block);
+            }
+            let ancestry: Vec<&str> = view.ancestors(&second.hash).map(|block| block.hash.as_str()).collect();
+            assert_eq!(ancestry, [second.hash.as_str(), first.hash.as_str(), genesis.hash.as_str()]);
+            assert_eq!(first.try_is_ancestor_of(&second, &view), Ok(true));
+        }
+    }
+}
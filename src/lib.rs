@@ -4,8 +4,23 @@
 //! focusing on the RLMD-GHOST version from Section 6 of the paper.
 //! It is structured as a library with a simulation binary.
 
+#![allow(non_snake_case)]
+
 pub mod constants;
+pub mod config;
+pub mod cache;
 pub mod types;
+pub mod hashing;
+pub mod validators;
 pub mod ffg;
 pub mod fork_choice;
+pub mod signer;
 pub mod node;
+pub mod gossip;
+pub mod simulation;
+#[cfg(feature = "wire")]
+pub mod wire;
+#[cfg(feature = "scenario")]
+pub mod scenario;
+#[cfg(feature = "tokio")]
+pub mod async_node;
@@ -2,113 +2,127 @@
 //! Reference: https://ethresear.ch/t/3-slot-finality-ssf-is-not-about-single-slot/20927
 
 use threeSF::node::Node;
-use threeSF::types::{Vote, Checkpoint};
+use threeSF::types::{Checkpoint, short_hash};
 use threeSF::ffg;
-use std::collections::HashMap;
+use threeSF::constants::JUSTIFICATION_CACHE_CAPACITY;
+use threeSF::cache::JustificationCache;
+use threeSF::simulation::{Simulation, SimulationConfig, SlotReport};
+use std::collections::HashSet;
 
 fn main() {
     println!("=== 3-Slot Finality (3SF) Protocol Simulation ===");
     println!("Demonstrating finalization within 3 slots for honest proposers\n");
 
-    // Set up 10 validator nodes
-    let mut nodes: Vec<Node> = (0..10).map(Node::new).collect();
-    let num_slots = 8; // Run enough slots to see finalization cycles
-    
-    println!("🔧 Initialized {} validator nodes", nodes.len());
+    // `--seed N` makes proposer selection (and all other randomness) seeded
+    let config = SimulationConfig { seed: parse_seed(), ..SimulationConfig::default() };
+    let num_slots = config.num_slots;
+    if let Some(seed) = config.seed {
+        println!("🎲 Using seed {}", seed);
+    }
+    let mut sim = Simulation::new(config);
+
+    println!("🔧 Initialized {} validator nodes", sim.nodes.len());
     println!("📊 Simulating {} slots to demonstrate 3SF finality\n", num_slots);
 
     for current_slot in 1..=num_slots {
-        simulate_slot(&mut nodes, current_slot);
-        
+        println!("🕐 SLOT {} - Beginning Protocol Phases", current_slot);
+        let report = sim.run_slot(current_slot);
+        print_slot_report(&report);
+
         // Display protocol state after each slot
-        display_protocol_state(&nodes, current_slot);
-        
+        display_protocol_state(&mut sim.nodes, current_slot);
+
         // Check for finalization events
-        check_finalization_status(&mut nodes, current_slot);
-        
+        check_finalization_status(&mut sim.nodes, current_slot);
+
         println!("{}", "=".repeat(80));
     }
 
+    // `--dot PATH` writes node 0's final block tree as GraphViz DOT
+    if let Some(path) = flag_value("--dot") {
+        std::fs::write(&path, sim.nodes[0].to_dot()).expect("failed to write DOT file");
+        println!("🖼️  Wrote block tree to {}", path);
+    }
+
+    // The headline property: honest blocks finalize within 3 slots.
+    // tests/three_slot_finality.rs asserts it; here we just report it.
+    let slow = sim.slow_finality(3);
+    for violation in &slow {
+        println!("❌ 3-slot finality violated: {}", violation);
+    }
+    if slow.is_empty() {
+        println!("✅ Every block old enough to judge finalized within 3 slots");
+    }
+
+    // `--compare-casper EPOCH_LENGTH` replays the chain under Casper FFG
+    if let Some(epoch_length) = flag_value("--compare-casper") {
+        let epoch_length = epoch_length.parse().expect("--compare-casper must be an unsigned integer");
+        println!("⏱️  Finality latency: {}", sim.compare_with_casper(epoch_length));
+    }
+
     println!("\n🎯 3SF Simulation Complete!");
     println!("The simulation demonstrates how blocks proposed by honest proposers");
     println!("achieve finalization within 3 slots under the 3SF protocol.");
 }
 
-fn simulate_slot(nodes: &mut Vec<Node>, slot: u64) {
-    println!("🕐 SLOT {} - Beginning Protocol Phases", slot);
-    
-    // Pick proposer using round-robin
-    let proposer_id = ((slot - 1) % nodes.len() as u64) as usize;
-    println!("👤 Proposer: Node {}", proposer_id);
-    
-    // PROPOSE PHASE
+/// Reads `--seed N` from the command line.
+fn parse_seed() -> Option<u64> {
+    let value = flag_value("--seed")?;
+    Some(value.parse().expect("--seed must be an unsigned integer"))
+}
+
+/// The argument following `flag` on the command line, if present.
+fn flag_value(flag: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    let position = args.iter().position(|arg| arg == flag)?;
+    Some(args.get(position + 1).unwrap_or_else(|| panic!("{} requires a value", flag)).clone())
+}
+
+fn print_slot_report(report: &SlotReport) {
+    match report.proposer {
+        Some(proposer_id) => println!("👤 Proposer: Node {}", proposer_id),
+        None => println!("👤 Proposer: none (no active validators)"),
+    }
+
     println!("📝 PROPOSE Phase:");
-    let proposal = nodes[proposer_id].propose(slot);
-    println!("   ✓ Node {} proposed block: {}", proposer_id, proposal.chain_head_hash);
-    
-    // Send proposal to all validators
-    println!("📡 Distributing proposal to all validators...");
-    for (i, node) in nodes.iter_mut().enumerate() {
-        if i != proposer_id {
-            node.on_receive_proposal(&proposal);
-        }
+    for proposal in &report.proposals {
+        println!("   ✓ Proposed block {} carrying {} votes", proposal.block, proposal.votes.len());
     }
-    
-    // VOTE PHASE
+    if report.proposals.is_empty() {
+        println!("   - No proposal; slot left empty");
+    }
+
     println!("🗳️  VOTE Phase:");
-    let votes: Vec<Vote> = nodes.iter_mut().map(|node| {
-        let vote = node.vote(slot);
-        println!("   ✓ Node {} voted for head: {} (FFG: {} -> {})", 
-                 vote.validator_id, 
-                 vote.chain_head_hash,
-                 format!("({}, {})", vote.source.block_hash, vote.source.slot),
-                 format!("({}, {})", vote.target.block_hash, vote.target.slot));
-        vote
-    }).collect();
-    
-    // Broadcast votes to network
-    println!("📡 Broadcasting {} votes to network...", votes.len());
-    for node in nodes.iter_mut() {
-        for vote in &votes {
-            node.receive_message(None, Some(vote.clone()));
-        }
+    for vote in &report.votes {
+        println!("   ✓ {}", vote);
     }
-    
-    // FAST CONFIRM PHASE
+    println!("📡 Broadcast {} votes to network", report.votes.len());
+
     println!("⚡ FAST CONFIRM Phase:");
-    let mut fast_confirmations = 0;
-    for node in nodes.iter_mut() {
-        let old_ch_ava = node.ch_ava.clone();
-        node.fast_confirm(slot);
-        if node.ch_ava != old_ch_ava {
-            fast_confirmations += 1;
-        }
-    }
-    if fast_confirmations > 0 {
-        println!("   ✓ {} nodes fast-confirmed blocks", fast_confirmations);
+    if report.fast_confirmations > 0 {
+        println!("   ✓ {} nodes fast-confirmed blocks", report.fast_confirmations);
     } else {
         println!("   - No fast-confirmations in this slot");
     }
-    
-    // MERGE PHASE
-    println!("🔄 MERGE Phase: Updating validator views");
-    for node in nodes.iter_mut() {
-        node.merge();
-    }
+
+    println!("🔄 MERGE Phase: Updated validator views");
 }
 
-fn display_protocol_state(nodes: &Vec<Node>, slot: u64) {
+fn display_protocol_state(nodes: &mut [Node], slot: u64) {
     println!("\n📊 Protocol State After Slot {}:", slot);
     
     // Show state from a few different nodes
     let sample_nodes = [0, 3, 7];
     for &node_id in &sample_nodes {
         if node_id < nodes.len() {
-            let node = &nodes[node_id];
-            println!("   Node {}: ch_ava={}, ch_fin={}", 
+            let node = &mut nodes[node_id];
+            let head = node.current_head(slot)
+                .unwrap_or_else(|e| format!("<{}>", e));
+            println!("   Node {}: head={}, ch_ava={}, ch_fin={}",
                      node_id, 
-                     truncate_hash(&node.ch_ava), 
-                     truncate_hash(&node.ch_fin));
+                     short_hash(&head),
+                     short_hash(&node.ch_ava),
+                     short_hash(&node.ch_fin));
         }
     }
     
@@ -116,9 +130,13 @@ fn display_protocol_state(nodes: &Vec<Node>, slot: u64) {
     let total_blocks: usize = nodes[0].view.blocks.len();
     let total_votes: usize = nodes[0].view.votes.len();
     println!("   Network State: {} blocks, {} votes in view", total_blocks, total_votes);
+
+    // Divergence check across all nodes' views
+    let distinct_views: HashSet<_> = nodes.iter().map(|node| node.view.state_hash()).collect();
+    println!("   View Agreement: {} distinct view(s) across {} nodes", distinct_views.len(), nodes.len());
 }
 
-fn check_finalization_status(nodes: &mut Vec<Node>, slot: u64) {
+fn check_finalization_status(nodes: &mut [Node], slot: u64) {
     if slot < 3 {
         return; // Need 3+ slots to check finalization
     }
@@ -126,8 +144,9 @@ fn check_finalization_status(nodes: &mut Vec<Node>, slot: u64) {
     println!("\n🔍 Checking Finalization Status:");
     
     // Look at recent checkpoints for justification
-    let mut justification_cache = HashMap::new();
+    let mut justification_cache = JustificationCache::new(JUSTIFICATION_CACHE_CAPACITY);
     let node = &nodes[0]; // Use node 0's view
+    let validators = node.validators();
     
     // Check recent slots
     for check_slot in (slot.saturating_sub(2))..=slot {
@@ -142,15 +161,15 @@ fn check_finalization_status(nodes: &mut Vec<Node>, slot: u64) {
                 slot: check_slot,
             };
             
-            let is_justified = ffg::is_justified(&checkpoint, &node.view, &mut justification_cache);
-            if is_justified {
+            let is_justified = ffg::is_justified(&checkpoint, &node.view, validators, &mut justification_cache);
+            if is_justified == Ok(true) {
                 println!("   ✅ JUSTIFIED: Block {} in slot {}", 
-                         truncate_hash(&block.hash), check_slot);
+                         short_hash(&block.hash), check_slot);
                 
                 // Might be ready for finalization
                 if check_slot <= slot.saturating_sub(2) {
                     println!("   🎯 POTENTIAL FINALIZATION: Block {} (proposed in slot {}) may be finalized", 
-                             truncate_hash(&block.hash), check_slot);
+                             short_hash(&block.hash), check_slot);
                 }
             }
         }
@@ -162,11 +181,3 @@ fn check_finalization_status(nodes: &mut Vec<Node>, slot: u64) {
                  slot - 3);
     }
 }
-
-fn truncate_hash(hash: &str) -> String {
-    if hash.len() > 12 {
-        format!("{}...", &hash[..12])
-    } else {
-        hash.to_string()
-    }
-}
@@ -1,11 +1,308 @@
 //! Node implementation for validators in the 3SF protocol.
 //! Coordinates FFG and fork choice logic.
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
 use crate::types::*;
 use crate::constants::*;
+use crate::config::{fork_digest, threshold_weight, ProtocolConfig};
+use crate::cache::{CacheStats, JustificationCache};
+use crate::validators::ValidatorRegistry;
 use crate::ffg;
-use crate::fork_choice;
+use crate::fork_choice::{self, ForkChoice, ForkChoiceError, RlmdGhost};
+use crate::signer::{self, LocalSigner, Signer, SigningKey};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/// Deterministic per-validator clock offset in `[-max_skew, max_skew]`.
+fn default_slot_offset(id: ValidatorId, max_skew: u64) -> i64 {
+    let span = 2 * max_skew + 1;
+    (id % span) as i64 - max_skew as i64
+}
+
+/// A phase was driven out of the Propose → Vote → Confirm → Merge order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhaseError {
+    pub slot: u64,
+    pub phase: SlotPhase,
+    pub last_completed: Option<(u64, SlotPhase)>,
+}
+
+impl fmt::Display for PhaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "phase {:?} for slot {} out of order (last completed: {:?})",
+               self.phase, self.slot, self.last_completed)
+    }
+}
+
+impl std::error::Error for PhaseError {}
+
+/// No snapshot exists for the requested slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RewindError {
+    pub slot: u64,
+    pub available: Vec<u64>, // Slots we do hold snapshots for, oldest first
+}
+
+impl fmt::Display for RewindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no snapshot for slot {} (available: {:?})", self.slot, self.available)
+    }
+}
+
+impl std::error::Error for RewindError {}
+
+/// A broken relationship between `ch_ava`, `ch_fin` and the view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvariantError {
+    /// `ch_ava` or `ch_fin` names a block missing from the view.
+    MissingBlock(Hash),
+    /// `ch_fin` is not `ch_ava` or one of its ancestors.
+    FinalizedNotPrefix { ch_fin: Hash, ch_ava: Hash },
+    /// `ch_fin` is not on the chain of the greatest finalized checkpoint:
+    /// a conflicting checkpoint was finalized, a safety violation.
+    NotFinalized(Hash),
+}
+
+impl fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvariantError::MissingBlock(hash) => write!(f, "chain head {} missing from view", hash),
+            InvariantError::FinalizedNotPrefix { ch_fin, ch_ava } =>
+                write!(f, "ch_fin {} is not a prefix of ch_ava {}", ch_fin, ch_ava),
+            InvariantError::NotFinalized(hash) => write!(f, "ch_fin {} is not finalized", hash),
+        }
+    }
+}
+
+impl std::error::Error for InvariantError {}
+
+/// Why `try_finalize` refused to move `ch_fin`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FinalizeError {
+    /// The checkpoint's block is not in our view.
+    UnknownBlock(Hash),
+    /// The checkpoint is not finalized in our view.
+    NotFinalized(Checkpoint),
+    /// The checkpoint neither extends nor precedes `ch_fin`. Finalizing it
+    /// would revert finality, so this always indicates a bug.
+    ConflictsWithFinalized { candidate: Checkpoint, ch_fin: Hash },
+    /// Computing finality failed.
+    Ffg(ffg::FfgError),
+}
+
+impl fmt::Display for FinalizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FinalizeError::UnknownBlock(hash) => write!(f, "block {} not in view", hash),
+            FinalizeError::NotFinalized(checkpoint) => write!(f, "checkpoint {} is not finalized", checkpoint),
+            FinalizeError::ConflictsWithFinalized { candidate, ch_fin } =>
+                write!(f, "checkpoint {} conflicts with finalized block {}", candidate, ch_fin),
+            FinalizeError::Ffg(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for FinalizeError {}
+
+/// Failure of one of the node's protocol operations: `on_tick` and the
+/// phases it drives, and `on_receive_proposal`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeError {
+    /// A phase was driven out of order.
+    Phase(PhaseError),
+    /// A block needed in `slot` is not in our view.
+    MissingBlock { hash: Hash, slot: u64 },
+    /// The vote we would cast is invalid.
+    InvalidVote(VoteError),
+    /// A proposal for `slot` was rejected.
+    InvalidProposal { slot: u64, error: ProposalError },
+    /// Fork choice for `slot` failed over our view.
+    ForkChoice { slot: u64, error: ForkChoiceError },
+    /// Moving `ch_fin` to the greatest finalized checkpoint was refused.
+    FinalityConflict(FinalizeError),
+    /// The merge phase found the chain heads inconsistent with our view.
+    Invariant(InvariantError),
+    /// `from_view` was given a root block that isn't in the view.
+    UnknownRoot(Hash),
+}
+
+impl fmt::Display for NodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeError::Phase(e) => write!(f, "{}", e),
+            NodeError::MissingBlock { hash, slot } => write!(f, "block {} needed in slot {} is not in our view", hash, slot),
+            NodeError::InvalidVote(e) => write!(f, "invalid vote: {}", e),
+            NodeError::InvalidProposal { slot, error } => write!(f, "invalid proposal for slot {}: {}", slot, error),
+            NodeError::ForkChoice { slot, error } => write!(f, "fork choice failed in slot {}: {}", slot, error),
+            NodeError::FinalityConflict(e) => write!(f, "refusing to finalize: {}", e),
+            NodeError::Invariant(e) => write!(f, "chain heads inconsistent: {}", e),
+            NodeError::UnknownRoot(hash) => write!(f, "root block {} is not in the view", hash),
+        }
+    }
+}
+
+impl std::error::Error for NodeError {}
+
+impl From<PhaseError> for NodeError {
+    fn from(e: PhaseError) -> Self {
+        NodeError::Phase(e)
+    }
+}
+
+/// Node state captured at the end of a slot's merge: everything the
+/// protocol derives from the messages seen so far. The validator registry
+/// and local settings (withheld slots, clock offset) are left as they are.
+#[derive(Debug, Clone)]
+struct Snapshot {
+    slot: u64,
+    view: View,
+    ch_ava: Hash,
+    ch_fin: Hash,
+    finalized_checkpoint: Option<Checkpoint>,
+    last_proposal_slot: u64,
+    blocks_proposed: u64,
+    last_head: Option<Hash>,
+    seen_proposals: HashMap<(ValidatorId, u64), Hash>,
+    proposer_equivocations: Vec<ProposerEquivocation>,
+    block_conflicts: Vec<BlockConflict>,
+    finality_conflicts: Vec<FinalityConflict>,
+    vote_history: HashMap<ValidatorId, Vec<Vote>>,
+    vote_heads: HashMap<(ValidatorId, u64), Hash>,
+    equivocators: BTreeSet<ValidatorId>,
+    fault_threshold_exceeded: bool,
+    abstentions: Vec<u64>,
+    pending_votes: Vec<Vote>,
+    orphan_pool: HashMap<Hash, Vec<Block>>,
+    buffered_votes: Vec<Vote>,
+}
+
+/// One-call snapshot of a node's consensus state, from `Node::status`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ConsensusStatus {
+    /// Slot of the last phase driven through `on_tick`; 0 before the first.
+    pub slot: u64,
+    /// Fork-choice head at `slot`, or `None` if fork choice failed.
+    pub head: Option<Hash>,
+    pub ch_ava: Hash,
+    pub ch_fin: Hash,
+    pub gjc: Checkpoint,
+    /// Slot of `ch_fin`'s block.
+    pub finalized_slot: u64,
+    /// Slot of `gjc`.
+    pub justified_slot: u64,
+    pub num_blocks: usize,
+    pub num_votes: usize,
+    pub equivocators: BTreeSet<ValidatorId>,
+}
+
+/// Why a received vote was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VoteError {
+    /// The voter is not in our validator registry.
+    UnknownValidator(ValidatorId),
+    /// The signature doesn't match the voter's key for the vote's slot.
+    BadSignature { validator: ValidatorId, slot: u64 },
+    /// The vote's checkpoints are out of order: the source must not be
+    /// after the target, nor the target after the vote's own slot.
+    SlotOrder { source_slot: u64, target_slot: u64, slot: u64 },
+    /// The vote names a head, target or source block not in our view.
+    UnknownBlock(Hash),
+    /// The FFG target is not on the chain ending at the voted head.
+    TargetOffHeadChain { target: Hash, head: Hash },
+    /// The head doesn't descend from our greatest justified checkpoint, so
+    /// no FFG vote for it is safe.
+    HeadConflictsWithJustified { head: Hash, justified: Checkpoint },
+    /// The vote is for a slot further ahead of our clock than we accept.
+    TooFarInFuture { slot: u64, current_slot: u64 },
+    /// We already hold as many votes from this validator for this slot as
+    /// we accept.
+    TooManyForSlot { validator: ValidatorId, slot: u64 },
+    /// Casting the vote would double-vote or surround-vote against an
+    /// earlier vote of ours, which is slashable.
+    Slashable { earlier: Box<Vote> },
+    /// Our vote store is full of votes above `ch_fin`, none of which may
+    /// be evicted, and the vote isn't one for our current slot.
+    StoreFull { cap: usize },
+}
+
+impl fmt::Display for VoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VoteError::UnknownValidator(id) => write!(f, "vote from unknown validator {}", id),
+            VoteError::BadSignature { validator, slot } =>
+                write!(f, "bad signature on vote from validator {} for slot {}", validator, slot),
+            VoteError::SlotOrder { source_slot, target_slot, slot } =>
+                write!(f, "vote for slot {} has source slot {} and target slot {}, out of order", slot, source_slot, target_slot),
+            VoteError::UnknownBlock(hash) => write!(f, "vote references unknown block {}", hash),
+            VoteError::TargetOffHeadChain { target, head } =>
+                write!(f, "FFG target {} is not an ancestor of head {}", target, head),
+            VoteError::HeadConflictsWithJustified { head, justified } =>
+                write!(f, "head {} does not descend from justified checkpoint {}", head, justified),
+            VoteError::TooFarInFuture { slot, current_slot } =>
+                write!(f, "vote for slot {} is too far ahead of current slot {}", slot, current_slot),
+            VoteError::TooManyForSlot { validator, slot } =>
+                write!(f, "too many votes from validator {} for slot {}", validator, slot),
+            VoteError::Slashable { earlier } =>
+                write!(f, "vote would be slashable against our earlier vote for slot {}", earlier.slot),
+            VoteError::StoreFull { cap } => write!(f, "vote store full at cap of {}", cap),
+        }
+    }
+}
+
+impl std::error::Error for VoteError {}
+
+/// Why a received proposal was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProposalError {
+    /// The proposed block's parent is not in our view, so we can't check it.
+    UnknownParent(Hash),
+    /// The proposed block's parent doesn't descend from our greatest
+    /// justified checkpoint, violating the proposal rule.
+    ConflictsWithJustified { parent: Hash, justified: Checkpoint },
+    /// The proposed block itself was rejected: its slot isn't after its
+    /// parent's, another block holds its hash, or it forks off below
+    /// `ch_fin`. The latter two are recorded as conflicts.
+    RejectedBlock(Hash),
+}
+
+impl fmt::Display for ProposalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProposalError::UnknownParent(hash) => write!(f, "proposal builds on unknown block {}", hash),
+            ProposalError::ConflictsWithJustified { parent, justified } =>
+                write!(f, "proposal builds on {}, which does not descend from justified checkpoint {}", parent, justified),
+            ProposalError::RejectedBlock(hash) => write!(f, "proposed block {} was rejected", hash),
+        }
+    }
+}
+
+impl std::error::Error for ProposalError {}
+
+/// Check that a vote comes from a registered validator, that
+/// `source.slot <= target.slot <= slot`, and that its FFG target lies on the
+/// chain it votes for as head, so its head vote and FFG vote are consistent.
+pub fn validate_vote(vote: &Vote, view: &View, validators: &ValidatorRegistry) -> Result<(), VoteError> {
+    if !validators.contains(vote.validator_id) {
+        return Err(VoteError::UnknownValidator(vote.validator_id));
+    }
+    if vote.source.slot > vote.target.slot || vote.target.slot > vote.slot {
+        return Err(VoteError::SlotOrder { source_slot: vote.source.slot, target_slot: vote.target.slot, slot: vote.slot });
+    }
+    for hash in [&vote.chain_head_hash, &vote.target.block_hash, &vote.source.block_hash] {
+        if !view.blocks.contains_key(hash) {
+            return Err(VoteError::UnknownBlock(hash.clone()));
+        }
+    }
+    if !view.ancestors(&vote.chain_head_hash).any(|b| b.hash == vote.target.block_hash) {
+        return Err(VoteError::TargetOffHeadChain {
+            target: vote.target.block_hash.clone(),
+            head: vote.chain_head_hash.clone(),
+        });
+    }
+    Ok(())
+}
 
 /// A validator node's complete state in the 3SF protocol.
 /// Matches the `v_i` state from Algorithm 7.
@@ -13,16 +310,143 @@ pub struct Node {
     pub validator: Validator,
     pub view: View,
     pub frozen_view: View, // `V_i^frozen` in the paper
-    pub ch_ava: Hash,      // Available chain head
-    pub ch_fin: Hash,      // Finalized chain head
+    /// Available chain head (`chAva`). Trails the fork-choice head by the
+    /// k-deep rule and only moves forward through votes or fast-confirmation.
+    pub ch_ava: Hash,
+    /// Finalized chain head (`chFin`). Always a prefix of `ch_ava`.
+    pub ch_fin: Hash,
+    config: ProtocolConfig,
+    // Network we belong to; envelopes stamped with another digest are dropped
+    fork_digest: ForkDigest,
+    // Validator set and its activation/exit schedule
+    validators: ValidatorRegistry,
+    // Slot of the latest proposal we made or received
+    last_proposal_slot: u64,
+    // Blocks we have proposed, numbering our readable block names
+    blocks_proposed: u64,
+    // Checkpoint `ch_fin` was last moved to. It stays finalized even if the
+    // votes that finalized it are later dropped or stop counting
+    finalized_checkpoint: Option<Checkpoint>,
+    // First block seen from each (proposer, slot), to catch equivocation
+    seen_proposals: HashMap<(ValidatorId, u64), Hash>,
+    proposer_equivocations: Vec<ProposerEquivocation>,
+    // Blocks rejected because a different body already holds their hash
+    block_conflicts: Vec<BlockConflict>,
+    // Blocks rejected because they fork off below `ch_fin`
+    finality_conflicts: Vec<FinalityConflict>,
+    // Block receiving proposer boost and the only slot it applies in
+    proposer_boost: Option<(Hash, u64)>,
+    // Last phase driven through `on_tick`
+    last_phase: Option<(u64, SlotPhase)>,
+    // How far this node's clock runs ahead (+) or behind (-) the driver, in slots
+    slot_offset: i64,
     // Cache results to speed up repeated calculations
-    justification_cache: HashMap<Checkpoint, bool>,
-    finalization_cache: HashMap<Checkpoint, bool>,
+    justification_cache: JustificationCache,
+    finalization_cache: JustificationCache,
+    // Justification results for the frozen view, which `vote` reads
+    frozen_justification_cache: JustificationCache,
+    // Supermajority links seen so far, updated as votes arrive
+    links: ffg::LinkIndex,
+    // Recent votes per validator, in arrival order, for diagnostics
+    vote_history: HashMap<ValidatorId, Vec<Vote>>,
+    // Slots in which we deliberately abstained from voting
+    abstentions: Vec<u64>,
+    // Votes waiting for blocks they reference to arrive
+    pending_votes: Vec<Vote>,
+    // Blocks whose parent we haven't seen yet, keyed by that parent
+    orphan_pool: HashMap<Hash, Vec<Block>>,
+    // Received votes held back until `flush_pending`, with `buffer_votes` on
+    buffered_votes: Vec<Vote>,
+    // Slots in which we stay silent even if we are the proposer
+    withheld_slots: HashSet<u64>,
+    // Post-merge state of recent slots, oldest first, for `rewind_to`
+    snapshots: VecDeque<Snapshot>,
+    // Checkpoint a full vote store was last pruned at; pruning again before
+    // finality moves couldn't free anything
+    pruned_at: Option<Checkpoint>,
+    // Signs the votes and proposals this node emits
+    signer: Box<dyn Signer>,
+    // Last head fork choice gave us, to measure reorgs against
+    last_head: Option<Hash>,
+    // Slot whose view was frozen ahead of its vote phase by `freeze_for`
+    frozen_slot: Option<u64>,
+    // Rule picking our head; RLMD-GHOST unless swapped out for comparison
+    fork_choice: Box<dyn ForkChoice>,
+    // Live view's RLMD-GHOST weights, kept up to date as votes arrive;
+    // `None` once a different rule is swapped in
+    vote_weights: Option<fork_choice::VoteWeights>,
+    // First head voted for by each (validator, slot), to catch equivocation
+    vote_heads: HashMap<(ValidatorId, u64), Hash>,
+    // Validators caught equivocating, as voters or proposers
+    equivocators: BTreeSet<ValidatorId>,
+    // Set once more equivocators than the fault threshold allows are seen
+    fault_threshold_exceeded: bool,
 }
 
 impl Node {
     /// Initialize a new validator node starting from genesis.
     pub fn new(id: ValidatorId) -> Self {
+        Self::with_config(id, ProtocolConfig::default())
+    }
+
+    /// Initialize a node from genesis with custom protocol parameters.
+    pub fn with_config(id: ValidatorId, config: ProtocolConfig) -> Self {
+        Self::with_validators(id, config, ValidatorRegistry::new(0..NUM_VALIDATORS))
+    }
+
+    /// Start from a trusted weak-subjectivity checkpoint instead of genesis.
+    /// The anchor is treated as justified and finalized, and becomes the
+    /// starting point for fork choice, `ch_ava` and `ch_fin`.
+    pub fn with_trusted_checkpoint(mut self, anchor: Checkpoint, anchor_block: Block) -> Self {
+        let view = View::from_anchor(anchor.clone(), anchor_block);
+        self.view = view.clone();
+        self.frozen_view = view;
+        self.ch_ava = anchor.block_hash.clone();
+        self.ch_fin = anchor.block_hash;
+        self.last_proposal_slot = anchor.slot;
+        self
+    }
+
+    /// Seed a node (as validator 0) directly from a prebuilt view rooted at
+    /// `genesis_hash`, skipping the propose/vote loop. Meant for fork-choice
+    /// experiments: build a block tree and votes, then ask `current_head`.
+    /// A root other than the standard genesis block becomes the view's anchor.
+    /// Fails with `UnknownRoot` if `genesis_hash` isn't in the view.
+    pub fn from_view(mut view: View, genesis_hash: Hash) -> Result<Self, NodeError> {
+        let Some(root) = view.blocks.get(&genesis_hash) else {
+            return Err(NodeError::UnknownRoot(genesis_hash));
+        };
+        if view.anchor.is_none() && genesis_hash != Block::genesis().hash {
+            view.anchor = Some(Checkpoint { block_hash: genesis_hash.clone(), slot: root.slot });
+        }
+        let mut node = Self::new(0);
+        node.view = view.clone();
+        node.frozen_view = view;
+        node.rebuild_vote_indexes();
+        node.fork_digest = fork_digest(&genesis_hash, &node.config);
+        node.ch_ava = genesis_hash.clone();
+        node.ch_fin = genesis_hash;
+        Ok(node)
+    }
+
+    /// Sign with `signer` instead of the default in-process key.
+    /// Consensus decisions stay in the node; only signing is delegated.
+    pub fn with_signer(mut self, signer: impl Signer + 'static) -> Self {
+        self.signer = Box::new(signer);
+        self
+    }
+
+    /// Pick heads with `rule` instead of RLMD-GHOST, e.g. to compare rules.
+    /// Finality still roots the rule at `ch_fin`.
+    pub fn with_fork_choice(mut self, rule: impl ForkChoice + 'static) -> Self {
+        self.fork_choice = Box::new(rule);
+        self.vote_weights = None;
+        self
+    }
+
+    /// Initialize a node from genesis with a custom validator schedule.
+    pub fn with_validators(id: ValidatorId, config: ProtocolConfig, validators: ValidatorRegistry) -> Self {
+        let validators = validators.with_supermajority(config.finality_threshold_num, config.finality_threshold_den);
         let genesis_block = Block::genesis();
         let genesis_hash = genesis_block.hash.clone();
         let mut initial_view = View::default();
@@ -34,130 +458,2894 @@ impl Node {
             frozen_view: initial_view,
             ch_ava: genesis_hash.clone(),
             ch_fin: genesis_hash,
-            justification_cache: HashMap::new(),
-            finalization_cache: HashMap::new(),
+            last_proposal_slot: 0,
+            blocks_proposed: 0,
+            finalized_checkpoint: None,
+            seen_proposals: HashMap::new(),
+            proposer_equivocations: Vec::new(),
+            block_conflicts: Vec::new(),
+            finality_conflicts: Vec::new(),
+            proposer_boost: None,
+            last_phase: None,
+            slot_offset: default_slot_offset(id, config.max_clock_skew),
+            fork_digest: fork_digest(&Block::genesis().hash, &config),
+            justification_cache: JustificationCache::new(config.justification_cache_capacity),
+            finalization_cache: JustificationCache::new(config.justification_cache_capacity),
+            frozen_justification_cache: JustificationCache::new(config.justification_cache_capacity),
+            links: ffg::LinkIndex::default(),
+            vote_history: HashMap::new(),
+            abstentions: Vec::new(),
+            pending_votes: Vec::new(),
+            orphan_pool: HashMap::new(),
+            buffered_votes: Vec::new(),
+            withheld_slots: HashSet::new(),
+            snapshots: VecDeque::new(),
+            pruned_at: None,
+            signer: Box::new(LocalSigner::new(id)),
+            last_head: None,
+            frozen_slot: None,
+            fork_choice: Box::new(RlmdGhost { tie_break: config.tie_break, inclusion_penalty: config.inclusion_penalty }),
+            // Maintained weights count every vote alike
+            vote_weights: config.inclusion_penalty.is_none().then(fork_choice::VoteWeights::default),
+            vote_heads: HashMap::new(),
+            equivocators: BTreeSet::new(),
+            fault_threshold_exceeded: false,
+            config,
+            validators,
+        }
+    }
+
+    /// Single entry point for anything received from the network. Envelopes
+    /// from another network, by fork digest, are dropped unread.
+    pub fn handle_message(&mut self, envelope: Envelope) {
+        if envelope.fork_digest != self.fork_digest {
+            eprintln!("Node {} DROPPING message with fork digest {:02x?}, ours is {:02x?}",
+                      self.validator.id, envelope.fork_digest, self.fork_digest);
+            return;
+        }
+        self.process_message(envelope.message);
+    }
+
+    /// Stamp `message` with our fork digest for sending.
+    pub fn envelope(&self, message: Message) -> Envelope {
+        Envelope { fork_digest: self.fork_digest, message }
+    }
+
+    /// Our network's fork digest, from genesis and our protocol config.
+    pub fn fork_digest(&self) -> ForkDigest {
+        self.fork_digest
+    }
+
+    /// Dispatch a message already known to come from our network.
+    fn process_message(&mut self, msg: Message) {
+        match msg {
+            Message::Block(block) => self.receive_message(Some(block), None),
+            Message::Vote(vote) => self.receive_message(None, Some(vote)),
+            Message::Proposal(proposal) => {
+                if let Err(e) = self.on_receive_proposal(&proposal) {
+                    eprintln!("Node {} rejected proposal for slot {}: {}", self.validator.id, proposal.slot, e);
+                }
+            }
+            Message::Aggregate(aggregate) => {
+                for vote in aggregate.votes() {
+                    self.receive_message(None, Some(vote));
+                }
+            }
         }
     }
 
     /// Handle incoming blocks and votes from the network.
     pub fn receive_message(&mut self, block: Option<Block>, vote: Option<Vote>) {
         if let Some(b) = block {
-            self.view.blocks.entry(b.hash.clone()).or_insert(b);
+            self.insert_block(b);
         }
         if let Some(v) = vote {
-            self.view.votes.push(v);
+            if let Err(e) = self.admit_vote(&v) {
+                eprintln!("Node {} dropped vote from {}: {}", self.validator.id, v.validator_id, e);
+                return;
+            }
+            if self.config.buffer_votes {
+                if !self.buffered_votes.contains(&v) {
+                    self.buffered_votes.push(v);
+                }
+            } else {
+                self.apply_vote(v);
+            }
         }
     }
 
-    // Core 3SF protocol phases from Algorithm 7
+    /// Backpressure on received votes, checked before anything stores them:
+    /// a vote must be at most `max_future_vote_slots` ahead of our clock
+    /// (once it has started), and its validator may have at most
+    /// `max_votes_per_validator_slot` distinct votes for its slot.
+    fn admit_vote(&self, vote: &Vote) -> Result<(), VoteError> {
+        if let Some((current_slot, _)) = self.last_phase
+            && vote.slot > current_slot + self.config.max_future_vote_slots
+        {
+            return Err(VoteError::TooFarInFuture { slot: vote.slot, current_slot });
+        }
+        let held = self.view.votes.iter().chain(&self.pending_votes).chain(&self.buffered_votes);
+        let mut same_slot = 0;
+        for other in held.filter(|other| other.validator_id == vote.validator_id && other.slot == vote.slot) {
+            if other == vote {
+                return Ok(()); // Duplicate delivery stores nothing new
+            }
+            same_slot += 1;
+        }
+        if same_slot >= self.config.max_votes_per_validator_slot {
+            return Err(VoteError::TooManyForSlot { validator: vote.validator_id, slot: vote.slot });
+        }
+        Ok(())
+    }
 
-    /// Propose a new block for this slot.
-    /// See Algorithm 7, lines 13-16.
-    pub fn propose(&mut self, current_slot: u64) -> Proposal {
-        println!("Node {} PROPOSING for slot {}", self.validator.id, current_slot);
+    /// `validate_vote` against our view and registry, after checking the
+    /// signature if `verify_signatures` is on.
+    fn validate_vote(&self, vote: &Vote) -> Result<(), VoteError> {
+        if self.config.verify_signatures && !signer::verify_vote(vote, &self.validators) {
+            return Err(VoteError::BadSignature { validator: vote.validator_id, slot: vote.slot });
+        }
+        validate_vote(vote, &self.view, &self.validators)
+    }
 
-        let gjc = ffg::greatest_justified_checkpoint(&self.view, &mut self.justification_cache);
-        let head_hash = fork_choice::rlmd_ghost_fork_choice(&self.view, gjc.block_hash, current_slot);
+    /// Make room in the view for `vote` if it is new and the view already
+    /// holds `max_stored_votes`. Only votes that can no longer matter are
+    /// evicted: the view is re-anchored at `ch_fin` by `prune_finalized`.
+    /// Pruning is retried only after `ch_fin` has moved, so a full store
+    /// doesn't rebuild the vote indexes for every vote it refuses. Past the
+    /// cap, votes for our current slot from active validators are still
+    /// taken (`admit_vote` bounds them per validator), so honest votes keep
+    /// finality moving and the next prune frees space; others are refused.
+    /// Call only with a vote that passed `validate_vote`.
+    fn make_room_for_vote(&mut self, vote: &Vote) -> Result<(), VoteError> {
+        let cap = self.config.max_stored_votes;
+        if self.view.votes.len() < cap || self.view.contains_vote(vote) {
+            return Ok(());
+        }
+        let finalized = self.ch_fin_checkpoint();
+        if self.pruned_at.as_ref() != Some(&finalized) {
+            self.pruned_at = Some(finalized);
+            let before = self.view.votes.len();
+            match self.prune_finalized() {
+                Ok(()) => eprintln!("Node {} pruned {} votes below finalized block {}: store at cap of {}",
+                                    self.validator.id, before - self.view.votes.len(), self.ch_fin, cap),
+                Err(e) => eprintln!("Node {} could not prune full vote store: {}", self.validator.id, e),
+            }
+        }
+        let current = self.last_phase.is_some_and(|(slot, _)| vote.slot == slot)
+            && self.validators.is_active(vote.validator_id, vote.slot);
+        if self.view.votes.len() < cap || current {
+            Ok(())
+        } else {
+            Err(VoteError::StoreFull { cap })
+        }
+    }
 
-        // Create new block extending the chosen head
-        let new_block = Block {
-            hash: format!("block_slot_{}_proposer_{}", current_slot, self.validator.id),
-            parent_hash: head_hash,
-            slot: current_slot,
-            proposer_id: self.validator.id,
-            transactions: vec![], // Empty for this simulation
-        };
-        self.view.blocks.insert(new_block.hash.clone(), new_block.clone());
+    /// Recount links and fork-choice weights from the live view's votes, and
+    /// drop cached results, after votes were removed or reinterpreted.
+    fn rebuild_vote_indexes(&mut self) {
+        self.links = ffg::LinkIndex::from_view(&self.view, &self.validators);
+        if self.vote_weights.is_some() {
+            self.vote_weights = Some(fork_choice::VoteWeights::from_view(&self.view, &self.validators));
+        }
+        self.justification_cache.clear();
+        self.finalization_cache.clear();
+    }
 
-        Proposal {
-            chain_head_hash: new_block.hash,
-            view: self.view.clone(), // Share our view with other validators
-            slot: current_slot,
-            proposer_id: self.validator.id,
+    /// Validate a received vote and add it to the view, or park it if it
+    /// names blocks we don't have yet.
+    fn apply_vote(&mut self, vote: Vote) {
+        match self.validate_vote(&vote).and_then(|()| self.make_room_for_vote(&vote)) {
+            Ok(()) => self.accept_vote(vote, self.last_phase.map_or(0, |(slot, _)| slot)),
+            Err(VoteError::UnknownBlock(_)) => self.hold_pending_vote(vote),
+            Err(e) => eprintln!("Node {} rejected vote from {}: {}", self.validator.id, vote.validator_id, e),
         }
     }
 
-    /// Process a proposal from another validator.
-    /// From Algorithm 7, lines 30-31.
-    pub fn on_receive_proposal(&mut self, proposal: &Proposal) {
-        println!("Node {} received proposal for slot {}", self.validator.id, proposal.slot);
-        // Add proposer's blocks and votes to our frozen view
-        for (hash, block) in &proposal.view.blocks {
-            self.frozen_view.blocks.entry(hash.clone()).or_insert(block.clone());
+    /// Apply every vote buffered since the last flush, in arrival order.
+    /// With `buffer_votes` on, received votes reach the view (and so fork
+    /// choice and FFG) only here, so the driver decides when they count.
+    pub fn flush_pending(&mut self) {
+        for vote in std::mem::take(&mut self.buffered_votes) {
+            self.apply_vote(vote);
         }
-        for vote in &proposal.view.votes {
-            self.frozen_view.votes.push(vote.clone());
+    }
+
+    /// Received votes waiting for `flush_pending`.
+    pub fn buffered_vote_count(&self) -> usize {
+        self.buffered_votes.len()
+    }
+
+    /// Record the voter as an equivocator if it already voted for a
+    /// different head in the same slot.
+    fn check_vote_equivocation(&mut self, vote: &Vote) {
+        let first = self.vote_heads.entry((vote.validator_id, vote.slot))
+            .or_insert_with(|| vote.chain_head_hash.clone());
+        if *first != vote.chain_head_hash {
+            self.record_equivocator(vote.validator_id, vote.slot);
         }
     }
 
-    /// Cast our vote for this slot.
-    /// See Algorithm 7, lines 18-22.
-    pub fn vote(&mut self, current_slot: u64) -> Vote {
-        println!("Node {} VOTING for slot {}", self.validator.id, current_slot);
+    /// Add `id` to the known equivocators, flagging the fault threshold
+    /// once more than the configured fraction of validators active in
+    /// `slot` has equivocated.
+    fn record_equivocator(&mut self, id: ValidatorId, slot: u64) {
+        if !self.equivocators.insert(id) || self.fault_threshold_exceeded {
+            return;
+        }
+        let bound = threshold_weight(
+            self.validators.active_count(slot),
+            self.config.fault_threshold_num,
+            self.config.fault_threshold_den,
+        );
+        if self.equivocators.len() as u64 >= bound {
+            eprintln!("Node {} FAULT THRESHOLD EXCEEDED: {} equivocators", self.validator.id, self.equivocators.len());
+            self.fault_threshold_exceeded = true;
+        }
+    }
 
-        let gjc_frozen = ffg::greatest_justified_checkpoint(&self.frozen_view, &mut self.justification_cache);
-        let head_hash = fork_choice::rlmd_ghost_fork_choice(&self.frozen_view, gjc_frozen.block_hash.clone(), current_slot);
-        
-        let head_block = self.frozen_view.blocks.get(&head_hash).unwrap();
+    /// Validators we have caught equivocating, as voters or proposers.
+    pub fn equivocators(&self) -> &BTreeSet<ValidatorId> {
+        &self.equivocators
+    }
 
-        // Update chAva based on k-deep rule
-        let k_deep_prefix = self.get_k_deep_prefix(head_block, KAPPA);
-        self.ch_ava = vec![self.ch_ava.clone(), k_deep_prefix, gjc_frozen.block_hash.clone()]
-            .into_iter()
-            .max_by_key(|h| self.frozen_view.blocks.get(h).unwrap().slot)
-            .unwrap();
+    /// Whether more validators have equivocated than the fault threshold
+    /// tolerates. Past this point safety and liveness are not guaranteed.
+    pub fn fault_threshold_exceeded(&self) -> bool {
+        self.fault_threshold_exceeded
+    }
+
+    /// Add a validated vote, included in slot `included_in`, to the live
+    /// view and its indexes.
+    fn accept_vote(&mut self, vote: Vote, included_in: u64) {
+        self.check_vote_equivocation(&vote);
+        self.links.add_vote(&vote, &self.validators);
+        self.record_vote_history(&vote);
+        self.view.record_inclusion(&vote, included_in);
+        if !self.view.insert_vote(vote.clone()) {
+            return;
+        }
+        self.forget_negative_results();
+        if let Some(weights) = &mut self.vote_weights {
+            weights.add_vote(&vote, &self.view, &self.validators);
+        }
+    }
 
-        // Build FFG vote with source and target checkpoints
-        let source = gjc_frozen;
-        let target = Checkpoint { block_hash: self.ch_ava.clone(), slot: current_slot };
+    /// Drop cached "not justified" and "not finalized" results after the
+    /// live view grows. A new vote or block can justify or finalize a
+    /// checkpoint, but never undo one, so positive results stay valid.
+    fn forget_negative_results(&mut self) {
+        self.justification_cache.retain(|_, &justified| justified);
+        self.finalization_cache.retain(|_, &finalized| finalized);
+    }
 
-        Vote {
-            chain_head_hash: head_hash,
-            source,
-            target,
-            slot: current_slot,
-            validator_id: self.validator.id,
+    /// Park a vote naming blocks we don't have yet; it isn't counted
+    /// anywhere until `retry_pending_votes` finds its blocks.
+    fn hold_pending_vote(&mut self, vote: Vote) {
+        if self.pending_votes.contains(&vote) {
+            return;
         }
+        if self.pending_votes.len() >= self.config.max_stored_votes {
+            let oldest = (0..self.pending_votes.len()).min_by_key(|&i| self.pending_votes[i].slot);
+            if let Some(oldest) = oldest {
+                self.pending_votes.remove(oldest);
+            }
+        }
+        self.pending_votes.push(vote);
     }
 
-    /// Try to fast-confirm blocks with supermajority support.
-    /// From Algorithm 7, lines 24-27.
-    pub fn fast_confirm(&mut self, current_slot: u64) {
-        let mut vote_counts: HashMap<Hash, usize> = HashMap::new();
-        for vote in &self.view.votes {
-            if vote.slot == current_slot {
-                *vote_counts.entry(vote.chain_head_hash.clone()).or_insert(0) += 1;
+    /// Accept pending votes whose blocks have all arrived.
+    fn retry_pending_votes(&mut self) {
+        for vote in std::mem::take(&mut self.pending_votes) {
+            match self.validate_vote(&vote).and_then(|()| self.make_room_for_vote(&vote)) {
+                Ok(()) => self.accept_vote(vote, self.last_phase.map_or(0, |(slot, _)| slot)),
+                Err(VoteError::UnknownBlock(_) | VoteError::StoreFull { .. }) => self.pending_votes.push(vote),
+                Err(_) => {}
             }
         }
-        
-        let n = 100; // Validator count
-        if let Some((fast_cand, _count)) = vote_counts.iter().find(|(_, count)| **count as u64 > (2 * n / 3)) {
-             println!("Node {} FAST CONFIRMING {} in slot {}", self.validator.id, fast_cand, current_slot);
-             self.ch_ava = fast_cand.clone();
-             // TODO: implement full finalization logic with GF(V)
+    }
+
+    /// Votes held back because they name blocks not yet in our view.
+    pub fn pending_vote_count(&self) -> usize {
+        self.pending_votes.len()
+    }
+
+    /// Append a vote to its validator's history, keeping at most
+    /// `vote_history_cap` of the most recent ones.
+    fn record_vote_history(&mut self, vote: &Vote) {
+        let history = self.vote_history.entry(vote.validator_id).or_default();
+        if history.contains(vote) {
+            return;
         }
+        history.push(vote.clone());
+        let excess = history.len().saturating_sub(self.config.vote_history_cap);
+        history.drain(..excess);
     }
 
-    /// Merge our view with frozen view to end the slot.
-    /// Algorithm 7, line 29.
-    pub fn merge(&mut self) {
-        println!("Node {} MERGING view", self.validator.id);
-        self.frozen_view = self.view.clone();
-        // Reset caches for next slot
-        self.justification_cache.clear();
-        self.finalization_cache.clear();
+    /// Votes seen from `id`, oldest first (bounded by `vote_history_cap`).
+    pub fn vote_history(&self, id: ValidatorId) -> &[Vote] {
+        self.vote_history.get(&id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Add a block to the live view. A block whose hash is already taken by a
+    /// different body, or that would fork off below `ch_fin`, is rejected and
+    /// recorded as a conflict, and a block whose slot isn't after its
+    /// parent's is rejected outright. A block whose parent is unknown waits
+    /// in the orphan pool and is inserted, along with any orphans of its
+    /// own, once the parent arrives.
+    /// Returns false if the block was rejected or orphaned.
+    fn insert_block(&mut self, block: Block) -> bool {
+        if let Some(parent) = self.view.blocks.get(&block.parent_hash)
+            && block.slot <= parent.slot
+        {
+            eprintln!("Node {} rejected block {}: slot {} not after parent slot {}",
+                      self.validator.id, block.hash, block.slot, parent.slot);
+            return false;
+        }
+        match self.view.blocks.get(&block.hash) {
+            Some(existing) if *existing != block => {
+                eprintln!("Node {} rejected block {}: conflicts with existing block under the same hash",
+                          self.validator.id, block.hash);
+                self.block_conflicts.push(BlockConflict { existing: existing.clone(), rejected: block });
+                false
+            }
+            Some(_) => true, // Duplicate delivery
+            None if self.conflicts_with_finalized(&block) => {
+                eprintln!("Node {} rejected block {}: slot {} forks off below finalized block {}",
+                          self.validator.id, block.hash, block.slot, self.ch_fin);
+                self.finality_conflicts.push(FinalityConflict { finalized: self.ch_fin.clone(), rejected: block });
+                false
+            }
+            None if !self.view.blocks.contains_key(&block.parent_hash) => {
+                let orphans = self.orphan_pool.entry(block.parent_hash.clone()).or_default();
+                if !orphans.contains(&block) {
+                    orphans.push(block);
+                }
+                false
+            }
+            None => {
+                let hash = block.hash.clone();
+                self.view.blocks.insert(hash.clone(), block);
+                self.forget_negative_results();
+                self.retry_pending_votes();
+                for orphan in self.orphan_pool.remove(&hash).unwrap_or_default() {
+                    self.insert_block(orphan);
+                }
+                true
+            }
+        }
+    }
+
+    /// Blocks waiting in the orphan pool for their parent to arrive.
+    pub fn orphan_count(&self) -> usize {
+        self.orphan_pool.values().map(Vec::len).sum()
+    }
+
+    /// Whether a block new to us sits at or below `ch_fin`'s slot. Every
+    /// block on the finalized chain is already in our view (or trusted
+    /// below the anchor), so such a block can only be on a conflicting fork.
+    fn conflicts_with_finalized(&self, block: &Block) -> bool {
+        let finalized_slot = self.view.blocks.get(&self.ch_fin).map_or(0, |b| b.slot);
+        block.slot <= finalized_slot
+    }
+
+    /// Slots in which this node was online but chose not to vote.
+    pub fn abstentions(&self) -> &[u64] {
+        &self.abstentions
+    }
+
+    /// Blocks rejected for reusing the hash of a different block.
+    pub fn block_conflicts(&self) -> &[BlockConflict] {
+        &self.block_conflicts
+    }
+
+    /// Blocks rejected for conflicting with our finalized chain.
+    pub fn finality_conflicts(&self) -> &[FinalityConflict] {
+        &self.finality_conflicts
+    }
+
+    /// Current fork-choice head: our fork-choice rule (RLMD-GHOST by default)
+    /// run from the greatest justified checkpoint of our live view, never
+    /// leaving the chain of `ch_fin`.
+    ///
+    /// This is the tip of the chain fork choice favours right now. Unlike
+    /// `ch_ava` and `ch_fin` it can be reorged; under honest conditions it
+    /// extends `ch_ava`, which in turn extends `ch_fin`. It is reported as
+    /// is: `max_reorg_depth` only applies where we adopt a head, when
+    /// proposing and voting (see `limit_reorg`).
+    /// Under RLMD-GHOST this reads the weights maintained as votes arrive
+    /// rather than refiltering the view.
+    pub fn current_head(&mut self, current_slot: u64) -> Result<Hash, ForkChoiceError> {
+        let gjc = self.gjc();
+        self.refresh_vote_weights();
+        let head = match &self.vote_weights {
+            Some(weights) => {
+                let start = fork_choice::finality_root(&self.view, gjc.block_hash, &self.ch_fin)?;
+                weights.head(&self.view, &self.validators, &start, current_slot, self.boost_at(current_slot), self.config.tie_break)?
+            }
+            None => fork_choice::head_with_finality(
+                self.fork_choice.as_ref(), &self.view, &self.validators, gjc.block_hash, &self.ch_fin, current_slot, self.boost_at(current_slot),
+            )?,
+        };
+        Ok(head)
+    }
+
+    /// Rebuild the maintained fork-choice weights if votes were written
+    /// into `view` directly, bypassing them.
+    fn refresh_vote_weights(&mut self) {
+        if self.vote_weights.as_ref().is_some_and(|weights| weights.vote_count() != self.view.votes.len()) {
+            self.vote_weights = Some(fork_choice::VoteWeights::from_view(&self.view, &self.validators));
+        }
     }
-    
-    /// Find the block that's k slots back from the head.
-    fn get_k_deep_prefix(&self, head_block: &Block, k: u64) -> Hash {
-        let mut current_block = head_block.clone();
-        // Walk back k slots from the head
-        while current_block.slot > head_block.slot.saturating_sub(k) {
-            if current_block.parent_hash == "null" {
-                break;
+
+    /// Validators whose latest head vote, as counted by fork choice in the
+    /// current slot, names exactly `block`. Votes for its descendants,
+    /// which also support it in GHOST, aren't included. Counted like
+    /// `ffg_vote_weight` and the thresholds, one per validator.
+    pub fn head_vote_weight(&mut self, block: &Hash) -> u64 {
+        let slot = self.last_phase.map_or(0, |(slot, _)| slot);
+        self.refresh_vote_weights();
+        let rebuilt;
+        let weights = match &self.vote_weights {
+            Some(weights) => weights,
+            None => {
+                rebuilt = fork_choice::VoteWeights::from_view(&self.view, &self.validators);
+                &rebuilt
             }
-            current_block = self.frozen_view.blocks.get(&current_block.parent_hash).unwrap().clone();
+        };
+        weights.counted_votes(slot)
+            .filter(|vote| vote.chain_head_hash == *block)
+            .count() as u64
+    }
+
+    /// Validators with an FFG vote for `checkpoint` as target, from any
+    /// source: the unit of `JustificationSupport::weight`. Justification
+    /// needs this many from a single justified source.
+    pub fn ffg_vote_weight(&self, checkpoint: &Checkpoint) -> u64 {
+        self.links.target_voters(checkpoint).map_or(0, |voters| voters.len() as u64)
+    }
+
+    /// How many blocks of our last adopted head's chain switching to `head`
+    /// would abandon, if that is more than `max_reorg_depth` allows. The
+    /// previous head only holds while it still extends `ch_fin`.
+    fn refused_reorg_depth(&self, head: &Hash) -> Option<u64> {
+        let max_depth = self.config.max_reorg_depth?;
+        let last = self.last_head.as_ref()?;
+        let common = self.view.common_ancestor(last, head)?;
+        if !self.view.ancestors(last).any(|b| b.hash == self.ch_fin) {
+            return None;
+        }
+        let depth = self.view.ancestors(last).take_while(|b| b.hash != common).count() as u64;
+        (depth > max_depth).then_some(depth)
+    }
+
+    /// `head`, or our last adopted head if switching to `head` would reorg
+    /// deeper than `max_reorg_depth`. Leaves the adopted head alone.
+    fn limit_reorg(&self, head: Hash) -> Hash {
+        match (self.refused_reorg_depth(&head), &self.last_head) {
+            (Some(_), Some(last)) => last.clone(),
+            _ => head,
+        }
+    }
+
+    /// Adopt `head` as the head we build on and vote for, unless `limit_reorg`
+    /// refuses it; in that case warn and keep the previous head.
+    fn adopt_head(&mut self, head: Hash) -> Hash {
+        if let (Some(depth), Some(last), Some(limit)) = (self.refused_reorg_depth(&head), &self.last_head, self.config.max_reorg_depth) {
+            eprintln!("Node {} REFUSING reorg from {} to {}: {} blocks deep, limit {}",
+                      self.validator.id, last, head, depth, limit);
+            return last.clone();
+        }
+        self.last_head = Some(head.clone());
+        head
+    }
+
+    /// Which validators' filtered votes back our current head in `slot`:
+    /// true if the vote's head is in the head's subtree, false if it sits
+    /// on another fork. Validators with expired votes, or caught
+    /// equivocating, are absent. Votes are filtered as RLMD-GHOST does.
+    pub fn head_support_breakdown(&mut self, slot: u64) -> Result<HashMap<ValidatorId, bool>, ForkChoiceError> {
+        let head = self.current_head(slot)?;
+        Ok(fork_choice::subtree_support(&self.view, &self.validators, &head, slot))
+    }
+
+    /// DOT rendering of our live view with `ch_fin`'s chain in blue and the
+    /// rest of `ch_ava`'s chain in green.
+    pub fn to_dot(&self) -> String {
+        self.view.to_dot_highlighting(&[(&self.ch_ava, "green"), (&self.ch_fin, "blue")])
+    }
+
+    /// Leaf blocks a proposer may build on: those descending from the greatest
+    /// justified checkpoint of our live view, from no later than `current_slot`.
+    /// Fork choice picks its head among these. Falls back to the GJC block
+    /// itself if no leaf qualifies. Sorted by hash.
+    pub fn valid_head_candidates(&mut self, current_slot: u64) -> Vec<Hash> {
+        let gjc = self.gjc();
+        let blocks: Vec<&Block> = self.view.blocks.values().filter(|b| b.slot <= current_slot).collect();
+        let parents: HashSet<&Hash> = blocks.iter().map(|b| &b.parent_hash).collect();
+        let mut candidates: Vec<Hash> = blocks.iter()
+            .filter(|b| !parents.contains(&b.hash))
+            .filter(|b| self.view.ancestors(&b.hash).any(|a| a.hash == gjc.block_hash))
+            .map(|b| b.hash.clone())
+            .collect();
+        if candidates.is_empty() {
+            candidates.push(gjc.block_hash);
+        }
+        candidates.sort();
+        candidates
+    }
+
+    /// Move `ch_ava` forward to `candidate`.
+    /// Only accepts a strict descendant of the current `ch_ava` in our view,
+    /// so the available chain never rolls back or jumps to a sibling fork.
+    /// Returns whether `ch_ava` moved.
+    pub fn advance_ch_ava(&mut self, candidate: Hash) -> bool {
+        let extends_ava = self.extends_ch_ava(&candidate);
+        if extends_ava {
+            self.ch_ava = candidate;
+        }
+        extends_ava
+    }
+
+    /// Whether `candidate` is a strict descendant of `ch_ava` in our view.
+    fn extends_ch_ava(&self, candidate: &Hash) -> bool {
+        *candidate != self.ch_ava && self.view.ancestors(candidate).any(|b| b.hash == self.ch_ava)
+    }
+
+    /// Slots this node's clock is ahead (+) or behind (-) global time.
+    pub fn slot_offset(&self) -> i64 {
+        self.slot_offset
+    }
+
+    /// Override the clock offset, clamped to the configured maximum skew.
+    pub fn set_slot_offset(&mut self, offset: i64) {
+        let max = self.config.max_clock_skew as i64;
+        self.slot_offset = offset.clamp(-max, max);
+    }
+
+    /// The slot this node believes it is at when global time is `global_slot`.
+    pub fn local_slot(&self, global_slot: u64) -> u64 {
+        global_slot.saturating_add_signed(self.slot_offset)
+    }
+
+    /// Don't propose in `slot` even if assigned, as if the proposer were
+    /// offline. The slot then passes without a new block.
+    pub fn withhold_proposal(&mut self, slot: u64) {
+        self.withheld_slots.insert(slot);
+    }
+
+    /// Proposer assigned to `slot` under the configured selection rule.
+    pub fn proposer_for(&self, slot: u64) -> Option<ValidatorId> {
+        self.validators.proposer_for(slot, self.config.proposer_selection)
+    }
+
+    /// The validator set this node counts votes against.
+    pub fn validators(&self) -> &ValidatorRegistry {
+        &self.validators
+    }
+
+    /// Combined stake of every validator we know of. Stake is reported for
+    /// information only; quorums count validators (see `ValidatorRegistry`).
+    pub fn total_stake(&self) -> u64 {
+        self.validators.total_stake()
+    }
+
+    /// Combined stake of the validators active in `slot`.
+    pub fn active_stake(&self, slot: u64) -> u64 {
+        self.validators.active_stake(slot)
+    }
+
+    /// Stake of validator `id`, or 0 if we don't know it.
+    pub fn stake_of(&self, id: ValidatorId) -> u64 {
+        self.validators.stake_of(id)
+    }
+
+    /// Slash validator `id` from `slot` onward; its votes from that slot on
+    /// stop counting. Links are rebuilt since they depend on who is active.
+    pub fn slash(&mut self, id: ValidatorId, slot: u64) {
+        self.validators.slash(id, slot);
+        self.rebuild_vote_indexes();
+    }
+
+    /// Rotate validator `id` to signing with `key` from `from_slot` on; its
+    /// votes for earlier slots still verify under the old key. Rotating our
+    /// own id also switches us to a `LocalSigner` with the new key at once,
+    /// so do that when `from_slot` is the next slot we sign for.
+    pub fn rotate_key(&mut self, id: ValidatorId, key: SigningKey, from_slot: u64) {
+        self.validators.rotate_key(id, key, from_slot);
+        if id == self.validator.id {
+            self.signer = Box::new(LocalSigner::new(key));
+        }
+    }
+
+    /// Protocol parameters this node runs with.
+    pub fn config(&self) -> &ProtocolConfig {
+        &self.config
+    }
+
+    /// How many slots `current_slot` is past `ch_fin`'s block.
+    pub fn slots_since_finalization(&self, current_slot: u64) -> u64 {
+        let finalized_slot = self.view.blocks.get(&self.ch_fin).map_or(0, |b| b.slot);
+        current_slot.saturating_sub(finalized_slot)
+    }
+
+    /// Whether finality has stalled: `ch_fin` is more than
+    /// `finality_stall_threshold` slots behind `current_slot`.
+    pub fn finality_stalled(&self, current_slot: u64) -> bool {
+        self.slots_since_finalization(current_slot) > self.config.finality_stall_threshold
+    }
+
+    /// Snapshot of our consensus state as of now, for APIs and logging.
+    pub fn status(&mut self) -> ConsensusStatus {
+        let slot = self.last_phase.map_or(0, |(slot, _)| slot);
+        let head = self.current_head(slot).ok();
+        let gjc = self.gjc();
+        ConsensusStatus {
+            slot,
+            head,
+            ch_ava: self.ch_ava.clone(),
+            ch_fin: self.ch_fin.clone(),
+            finalized_slot: self.view.blocks.get(&self.ch_fin).map_or(0, |b| b.slot),
+            justified_slot: gjc.slot,
+            gjc,
+            num_blocks: self.view.blocks.len(),
+            num_votes: self.view.votes.len(),
+            equivocators: self.equivocators.clone(),
+        }
+    }
+
+    /// Consensus health in the Prometheus text exposition format, for
+    /// operators to scrape. Every sample is labelled with our validator id.
+    pub fn prometheus_metrics(&self) -> String {
+        let block_slot = |hash: &Hash| self.view.blocks.get(hash).map_or(0, |b| b.slot);
+        let stats = self.cache_stats();
+        let metrics: [(&str, &str, &str, u64); 8] = [
+            ("threesf_current_slot", "gauge", "Slot of the last phase driven by on_tick.",
+             self.last_phase.map_or(0, |(slot, _)| slot)),
+            ("threesf_available_slot", "gauge", "Slot of the available chain head (ch_ava).", block_slot(&self.ch_ava)),
+            ("threesf_finalized_slot", "gauge", "Slot of the finalized chain head (ch_fin).", block_slot(&self.ch_fin)),
+            ("threesf_votes", "gauge", "Votes in the live view.", self.view.votes.len() as u64),
+            ("threesf_blocks", "gauge", "Blocks in the live view.", self.view.blocks.len() as u64),
+            ("threesf_equivocators", "gauge", "Validators caught equivocating.", self.equivocators.len() as u64),
+            ("threesf_cache_hits_total", "counter", "Justification and finalization cache hits.", stats.hits),
+            ("threesf_cache_misses_total", "counter", "Justification and finalization cache misses.", stats.misses),
+        ];
+        let mut out = String::new();
+        for (name, kind, help, value) in metrics {
+            out.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n{}{{node=\"{}\"}} {}\n",
+                                  name, help, name, kind, name, self.validator.id, value));
+        }
+        out
+    }
+
+    /// Combined hit/miss counts of the justification caches (live and
+    /// frozen view) and the finalization cache.
+    /// Counts survive `merge`; call `reset_cache_stats` to start over.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.justification_cache.stats() + self.finalization_cache.stats() + self.frozen_justification_cache.stats()
+    }
+
+    pub fn reset_cache_stats(&mut self) {
+        self.justification_cache.reset_stats();
+        self.finalization_cache.reset_stats();
+        self.frozen_justification_cache.reset_stats();
+    }
+
+    /// Whether `checkpoint` is finalized in our live view.
+    /// Uses the maintained link index, so only justification is recomputed.
+    pub fn is_finalized(&mut self, checkpoint: &Checkpoint) -> Result<bool, ffg::FfgError> {
+        if let Some(&finalized) = self.finalization_cache.get(checkpoint) {
+            return Ok(finalized);
+        }
+        if self.finalized_checkpoint.as_ref() == Some(checkpoint) {
+            return Ok(true);
+        }
+        let finalized = self.view.is_trusted_finalized(checkpoint) || self.links.consecutive_target(checkpoint).is_some()
+            && ffg::is_justified(checkpoint, &self.view, &self.validators, &mut self.justification_cache)?;
+        self.finalization_cache.insert(checkpoint.clone(), finalized);
+        Ok(finalized)
+    }
+
+    /// Move `ch_fin` to `checkpoint`'s block if it is finalized and extends
+    /// the current `ch_fin`. Returns whether `ch_fin` moved; a checkpoint at
+    /// or behind `ch_fin` on its chain is already final and changes nothing.
+    /// A checkpoint on a conflicting branch is refused, even if finalized.
+    /// This is the only way `ch_fin` moves, and the checkpoint stays
+    /// finalized from then on, whatever happens to the votes behind it.
+    pub fn try_finalize(&mut self, checkpoint: &Checkpoint) -> Result<bool, FinalizeError> {
+        if !self.extends_ch_fin(checkpoint)? {
+            return Ok(false);
+        }
+        if !self.is_finalized(checkpoint).map_err(FinalizeError::Ffg)? {
+            return Err(FinalizeError::NotFinalized(checkpoint.clone()));
+        }
+        self.finalized_checkpoint = Some(checkpoint.clone());
+        self.ch_fin = checkpoint.block_hash.clone();
+        Ok(true)
+    }
+
+    /// Whether `checkpoint` is strictly ahead of `ch_fin` on its chain;
+    /// false if it is at or behind `ch_fin`, an error if it conflicts.
+    fn extends_ch_fin(&self, checkpoint: &Checkpoint) -> Result<bool, FinalizeError> {
+        if !self.view.blocks.contains_key(&checkpoint.block_hash) {
+            return Err(FinalizeError::UnknownBlock(checkpoint.block_hash.clone()));
+        }
+        if self.view.ancestors(&self.ch_fin).any(|b| b.hash == checkpoint.block_hash) {
+            return Ok(false);
+        }
+        if !self.view.ancestors(&checkpoint.block_hash).any(|b| b.hash == self.ch_fin) {
+            return Err(FinalizeError::ConflictsWithFinalized {
+                candidate: checkpoint.clone(),
+                ch_fin: self.ch_fin.clone(),
+            });
+        }
+        Ok(true)
+    }
+
+    /// Finalize `checkpoint` without any votes (`testing` feature), so
+    /// consumers of finality can be tested without running the protocol.
+    /// Checked against `ch_fin` as in `try_finalize`: a conflicting
+    /// checkpoint is refused. From then on the checkpoint counts as
+    /// finalized, so later slots keep `ch_fin` on it; `ch_ava` is pulled up
+    /// to it if it falls behind or off its chain.
+    #[cfg(feature = "testing")]
+    pub fn force_finalize(&mut self, checkpoint: &Checkpoint) -> Result<bool, FinalizeError> {
+        if !self.extends_ch_fin(checkpoint)? {
+            return Ok(false);
+        }
+        self.finalized_checkpoint = Some(checkpoint.clone());
+        self.finalization_cache.clear();
+        self.ch_fin = checkpoint.block_hash.clone();
+        if !self.view.ancestors(&self.ch_ava).any(|b| b.hash == self.ch_fin) {
+            self.ch_ava = self.ch_fin.clone();
+        }
+        #[cfg(feature = "tracing")]
+        tracing::info!(checkpoint = %checkpoint, "finalized");
+        Ok(true)
+    }
+
+    /// For each slot from `from_slot` to `to_slot` inclusive, the justified
+    /// checkpoint at that slot in our live view, if any. Candidates are the
+    /// base checkpoint and every checkpoint a vote names; should a slot have
+    /// several justified checkpoints, the greatest one is reported.
+    pub fn justification_report(&mut self, from_slot: u64, to_slot: u64) -> Vec<(u64, Option<Checkpoint>)> {
+        let slots = from_slot..=to_slot;
+        let mut candidates: Vec<Checkpoint> = self.view.votes.iter()
+            .flat_map(|v| [&v.source, &v.target])
+            .chain([&self.view.base_checkpoint()])
+            .filter(|cp| slots.contains(&cp.slot))
+            .cloned()
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+
+        let mut justified: HashMap<u64, Checkpoint> = HashMap::new();
+        for checkpoint in candidates {
+            if ffg::is_justified(&checkpoint, &self.view, &self.validators, &mut self.justification_cache) == Ok(true) {
+                // Sorted ascending, so a later insert for the same slot is greater
+                justified.insert(checkpoint.slot, checkpoint);
+            }
+        }
+        slots.map(|slot| (slot, justified.remove(&slot))).collect()
+    }
+
+    /// Discard history below the checkpoint `ch_fin` was finalized at.
+    /// See `ffg::prune_finalized`; justification and `ch_fin` never regress.
+    pub fn prune_finalized(&mut self) -> Result<(), ffg::FfgError> {
+        let finalized = self.ch_fin_checkpoint();
+        ffg::prune_finalized(&mut self.view, &finalized, &self.validators, &mut self.justification_cache)?;
+        if self.frozen_view.blocks.contains_key(&finalized.block_hash) {
+            ffg::prune_finalized(&mut self.frozen_view, &finalized, &self.validators, &mut self.frozen_justification_cache)?;
+        }
+        self.rebuild_vote_indexes();
+        Ok(())
+    }
+
+    /// The checkpoint `ch_fin` was finalized at: the one `try_finalize`
+    /// last moved it to, or our base checkpoint.
+    fn ch_fin_checkpoint(&self) -> Checkpoint {
+        self.finalized_checkpoint.clone().unwrap_or_else(|| self.view.base_checkpoint())
+    }
+
+    /// Supermajority links this node has observed.
+    pub fn links(&self) -> &ffg::LinkIndex {
+        &self.links
+    }
+
+    /// `GJ(V)`: the greatest justified checkpoint of our live view that
+    /// extends `ch_fin`, using the node's own justification cache. Fork
+    /// choice starts from its block. It may move to a sibling fork above
+    /// `ch_fin`, but never to one conflicting with it.
+    pub fn gjc(&mut self) -> Checkpoint {
+        ffg::greatest_justified_checkpoint_extending(&self.view, &self.validators, &mut self.justification_cache, &self.ch_fin)
+    }
+
+    /// `GF(V)`: the highest finalized checkpoint, or the base checkpoint if
+    /// none. `ch_fin` is this checkpoint's block once the confirm phase has
+    /// run, unless finality was halted on exceeding the fault threshold.
+    /// The checkpoint `ch_fin` was finalized at always counts, so this never
+    /// falls behind `ch_fin`.
+    pub fn greatest_finalized_checkpoint(&mut self) -> Checkpoint {
+        let candidates: Vec<Checkpoint> = self.links.finalization_candidates()
+            .chain(&self.finalized_checkpoint)
+            .cloned()
+            .collect();
+        let finalized: Vec<Checkpoint> = candidates.into_iter()
+            .filter(|cp| self.is_finalized(cp) == Ok(true))
+            .collect();
+        finalized.into_iter()
+            .max_by(|a, b| ffg::checkpoint_rank(a, &self.view).cmp(&ffg::checkpoint_rank(b, &self.view)))
+            .unwrap_or_else(|| self.view.base_checkpoint())
+    }
+
+    /// Evidence of proposers that sent conflicting proposals for one slot.
+    pub fn proposer_equivocations(&self) -> &[ProposerEquivocation] {
+        &self.proposer_equivocations
+    }
+
+    /// Block holding proposer boost and the slot the boost applies in, if any.
+    pub fn proposer_boost(&self) -> Option<(&Hash, u64)> {
+        self.proposer_boost.as_ref().map(|(hash, slot)| (hash, *slot))
+    }
+
+    /// The boosted block, if its boost applies in `current_slot`. Boost is
+    /// temporary: a stale one would keep skewing fork choice indefinitely.
+    fn boost_at(&self, current_slot: u64) -> Option<&Hash> {
+        self.proposer_boost.as_ref()
+            .filter(|(_, boost_slot)| *boost_slot == current_slot)
+            .map(|(hash, _)| hash)
+    }
+
+    /// Drive one sub-slot phase, enforcing Propose → Vote → Confirm → Merge
+    /// within a slot and that a new slot only starts after the previous merge.
+    /// `global_slot` is shifted by this node's clock offset before use.
+    /// Returns the message to broadcast, if the phase produces one, or the
+    /// first error the phase hit.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(node_id = self.validator.id, slot = self.local_slot(global_slot), phase = ?phase)))]
+    pub fn on_tick(&mut self, global_slot: u64, phase: SlotPhase) -> Result<Option<Message>, NodeError> {
+        let slot = self.local_slot(global_slot);
+        let in_order = match (phase, self.last_phase) {
+            (SlotPhase::Propose, None) => true,
+            (SlotPhase::Propose, Some((last, SlotPhase::Merge))) => slot > last,
+            (SlotPhase::Vote, Some((last, SlotPhase::Propose))) |
+            (SlotPhase::Confirm, Some((last, SlotPhase::Vote))) |
+            (SlotPhase::Merge, Some((last, SlotPhase::Confirm))) => slot == last,
+            _ => false,
+        };
+        if !in_order {
+            return Err(PhaseError { slot, phase, last_completed: self.last_phase }.into());
+        }
+        self.last_phase = Some((slot, phase));
+
+        let output = match phase {
+            SlotPhase::Propose if self.proposer_for(slot) == Some(self.validator.id)
+                && !self.withheld_slots.contains(&slot) => {
+                Some(Message::Proposal(self.propose(slot)?))
+            }
+            SlotPhase::Propose => None,
+            SlotPhase::Vote => {
+                if self.frozen_slot != Some(slot) {
+                    self.freeze_view();
+                }
+                self.vote(slot)?.map(Message::Vote)
+            }
+            SlotPhase::Confirm => {
+                self.fast_confirm(slot)?;
+                None
+            }
+            SlotPhase::Merge => {
+                let checked = self.merge();
+                self.take_snapshot(slot);
+                checked.map_err(NodeError::Invariant)?;
+                None
+            }
+        };
+        Ok(output)
+    }
+
+    // Core 3SF protocol phases from Algorithm 7
+
+    /// Propose a new block for this slot.
+    /// See Algorithm 7, lines 13-16.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(node_id = self.validator.id, slot = current_slot, phase = "propose")))]
+    pub fn propose(&mut self, current_slot: u64) -> Result<Proposal, NodeError> {
+        let head_hash = self.current_head(current_slot)
+            .map_err(|error| NodeError::ForkChoice { slot: current_slot, error })?;
+        let head_hash = self.adopt_head(head_hash);
+        Ok(self.propose_on(head_hash, current_slot))
+    }
+
+    /// The proposal `propose` would make for this slot, without adding the
+    /// block to our view or marking its votes as shared. Proposing right
+    /// after, with no new messages in between, yields the same proposal.
+    pub fn preview_proposal(&mut self, current_slot: u64) -> Result<Proposal, NodeError> {
+        let head_hash = self.current_head(current_slot)
+            .map_err(|error| NodeError::ForkChoice { slot: current_slot, error })?;
+        Ok(self.build_proposal(self.limit_reorg(head_hash), current_slot))
+    }
+
+    /// Propose a block for this slot on `head_hash` rather than our
+    /// fork-choice head, e.g. to fork the chain deliberately.
+    pub fn propose_on(&mut self, head_hash: Hash, current_slot: u64) -> Proposal {
+        println!("Node {} PROPOSING for slot {}", self.validator.id, current_slot);
+        let proposal = self.build_proposal(head_hash, current_slot);
+        self.view.blocks.insert(proposal.block.hash.clone(), proposal.block.clone());
+        self.forget_negative_results();
+        self.last_proposal_slot = current_slot;
+        self.blocks_proposed += 1;
+        proposal
+    }
+
+    /// Signed proposal of a new block on `head_hash`, leaving our state untouched.
+    fn build_proposal(&self, head_hash: Hash, current_slot: u64) -> Proposal {
+        // Create new block extending the chosen head
+        let mut new_block = Block {
+            hash: format!("blk_s{}_p{}_n{}", current_slot, self.validator.id, self.blocks_proposed),
+            parent_hash: head_hash,
+            slot: current_slot,
+            proposer_id: self.validator.id,
+            transactions: vec![], // Empty for this simulation
+        };
+        // The hashed form commits to our block counter as the name does
+        if let Some(hasher) = self.config.hash_function {
+            new_block.hash = new_block.compute_hash_with_nonce(&hasher, self.blocks_proposed);
+        }
+
+        // Only share votes others may not have seen since the last proposal
+        let recent_votes = self.view.votes.iter()
+            .filter(|v| v.slot >= self.last_proposal_slot)
+            .cloned()
+            .collect();
+
+        let mut proposal = Proposal {
+            chain_head_hash: new_block.hash.clone(),
+            block: new_block,
+            votes: recent_votes,
+            slot: current_slot,
+            proposer_id: self.validator.id,
+            signature: Signature::new(),
+        };
+        proposal.signature = self.signer.sign_proposal(&proposal);
+        proposal
+    }
+
+    /// Check that `proposal` builds on our greatest justified checkpoint:
+    /// an honest proposer only extends chains descending from the GJC.
+    pub fn validate_proposal(&mut self, proposal: &Proposal) -> Result<(), ProposalError> {
+        let parent = &proposal.block.parent_hash;
+        if !self.view.blocks.contains_key(parent) {
+            return Err(ProposalError::UnknownParent(parent.clone()));
+        }
+        let gjc = self.gjc();
+        if !self.view.ancestors(parent).any(|b| b.hash == gjc.block_hash) {
+            return Err(ProposalError::ConflictsWithJustified { parent: parent.clone(), justified: gjc });
+        }
+        Ok(())
+    }
+
+    /// Process a proposal from another validator. A proposal whose parent we
+    /// lack fails with `MissingBlock` naming the parent.
+    /// From Algorithm 7, lines 30-31.
+    pub fn on_receive_proposal(&mut self, proposal: &Proposal) -> Result<(), NodeError> {
+        println!("Node {} received proposal for slot {}", self.validator.id, proposal.slot);
+        let slot = proposal.slot;
+        match self.validate_proposal(proposal) {
+            Ok(()) => {}
+            Err(ProposalError::UnknownParent(hash)) => return Err(NodeError::MissingBlock { hash, slot }),
+            Err(error) => return Err(NodeError::InvalidProposal { slot, error }),
+        }
+        if !self.insert_block(proposal.block.clone()) {
+            let error = ProposalError::RejectedBlock(proposal.block.hash.clone());
+            return Err(NodeError::InvalidProposal { slot, error });
+        }
+        self.last_proposal_slot = self.last_proposal_slot.max(proposal.slot);
+        self.track_proposer_boost(proposal);
+
+        // The proposal is a received message too, so it only reaches the
+        // frozen view at the next freeze. Its votes are admitted and stored
+        // like gossiped ones: buffered until `flush_pending` with
+        // `buffer_votes` on, and otherwise added to the view, except that
+        // those naming blocks we lack wait in the pending set instead.
+        for vote in &proposal.votes {
+            if let Err(e) = self.admit_vote(vote) {
+                eprintln!("Node {} dropped vote from {} in proposal: {}", self.validator.id, vote.validator_id, e);
+                continue;
+            }
+            if self.config.buffer_votes {
+                self.view.record_inclusion(vote, proposal.slot);
+                if !self.buffered_votes.contains(vote) {
+                    self.buffered_votes.push(vote.clone());
+                }
+                continue;
+            }
+            match self.validate_vote(vote).and_then(|()| self.make_room_for_vote(vote)) {
+                Ok(()) => self.accept_vote(vote.clone(), proposal.slot),
+                Err(VoteError::UnknownBlock(_)) => self.hold_pending_vote(vote.clone()),
+                Err(e) => eprintln!("Node {} rejected vote from {} in proposal: {}", self.validator.id, vote.validator_id, e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Grant proposer boost to the first proposal of a slot, and revoke it
+    /// (recording slashable evidence) if the proposer sends a conflicting one.
+    fn track_proposer_boost(&mut self, proposal: &Proposal) {
+        let key = (proposal.proposer_id, proposal.slot);
+        match self.seen_proposals.get(&key) {
+            None => {
+                self.seen_proposals.insert(key, proposal.block.hash.clone());
+                self.proposer_boost = Some((proposal.block.hash.clone(), proposal.slot));
+            }
+            Some(first) if *first != proposal.block.hash => {
+                println!("Node {} detected proposer {} equivocating in slot {}",
+                         self.validator.id, proposal.proposer_id, proposal.slot);
+                self.proposer_equivocations.push(ProposerEquivocation {
+                    proposer_id: proposal.proposer_id,
+                    slot: proposal.slot,
+                    first_block: first.clone(),
+                    second_block: proposal.block.hash.clone(),
+                });
+                self.record_equivocator(proposal.proposer_id, proposal.slot);
+                // Neither of the conflicting blocks gets boosted
+                if self.proposer_boost.as_ref().is_some_and(|(_, slot)| *slot == proposal.slot) {
+                    self.proposer_boost = None;
+                }
+            }
+            Some(_) => {} // Duplicate delivery of the same proposal
+        }
+    }
+
+    /// Cast our vote for this slot, or `None` to explicitly abstain.
+    /// We abstain when the fork-choice head doesn't descend from the GJC,
+    /// since no vote for that head can be FFG-safe, and refuse with
+    /// `InvalidVote` a vote slashable against one we already cast.
+    /// See Algorithm 7, lines 18-22.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(node_id = self.validator.id, slot = current_slot, phase = "vote")))]
+    pub fn vote(&mut self, current_slot: u64) -> Result<Option<Vote>, NodeError> {
+        self.frozen_justification_cache.sync_state(self.frozen_view.state_hash());
+        let gjc_frozen = ffg::greatest_justified_checkpoint_extending(&self.frozen_view, &self.validators, &mut self.frozen_justification_cache, &self.ch_fin);
+        let head_hash = fork_choice::head_with_finality(
+            self.fork_choice.as_ref(), &self.frozen_view, &self.validators, gjc_frozen.block_hash.clone(), &self.ch_fin, current_slot,
+            self.boost_at(current_slot),
+        ).map_err(|error| NodeError::ForkChoice { slot: current_slot, error })?;
+        let chosen = head_hash;
+        let head_hash = self.limit_reorg(chosen.clone());
+
+        if !self.frozen_view.ancestors(&head_hash).any(|b| b.hash == gjc_frozen.block_hash) {
+            println!("Node {} ABSTAINING in slot {}: head {} conflicts with GJC {}",
+                     self.validator.id, current_slot, head_hash, gjc_frozen.block_hash);
+            self.adopt_head(chosen);
+            self.abstentions.push(current_slot);
+            return Ok(None);
+        }
+
+        // Update chAva based on k-deep rule: take the highest candidate
+        // that extends the current chAva
+        let k_deep_prefix = self.get_k_deep_prefix(&head_hash, KAPPA);
+        let mut candidates = vec![k_deep_prefix, gjc_frozen.block_hash.clone()];
+        candidates.sort_by_key(|h| std::cmp::Reverse(self.frozen_view.blocks.get(h).map_or(0, |b| b.slot)));
+        let ch_ava = candidates.into_iter().find(|candidate| self.extends_ch_ava(candidate));
+
+        // Never sign a vote slashable against one we already cast, and
+        // leave our head and chAva alone if we refuse it
+        let vote = self.unsigned_vote(head_hash, gjc_frozen, ch_ava.as_ref().unwrap_or(&self.ch_ava), current_slot);
+        if let Some(earlier) = self.vote_history(self.validator.id).iter().find(|earlier| !vote.is_consistent_with(earlier)) {
+            return Err(NodeError::InvalidVote(VoteError::Slashable { earlier: Box::new(earlier.clone()) }));
+        }
+        self.adopt_head(chosen);
+        if let Some(ch_ava) = ch_ava {
+            self.ch_ava = ch_ava;
+        }
+        let vote = self.sign_vote(vote);
+        println!("Node {} VOTING for slot {}", self.validator.id, current_slot);
+        Ok(Some(vote))
+    }
+
+    /// Vote for `head_hash` instead of our fork-choice head, e.g. to script
+    /// a split vote or a fork-choice attack. The FFG part is built as in
+    /// `vote`, with our live view's GJC as source; `ch_ava` is left alone.
+    /// The vote is returned, not applied to our own view.
+    pub fn vote_for(&mut self, head_hash: Hash, slot: u64) -> Result<Vote, VoteError> {
+        if !self.view.blocks.contains_key(&head_hash) {
+            return Err(VoteError::UnknownBlock(head_hash));
+        }
+        let gjc = self.gjc();
+        if !self.view.ancestors(&head_hash).any(|b| b.hash == gjc.block_hash) {
+            return Err(VoteError::HeadConflictsWithJustified { head: head_hash, justified: gjc });
+        }
+        Ok(self.signed_vote(head_hash, gjc, slot))
+    }
+
+    /// Build and sign our vote for `head_hash` with FFG source `source`.
+    /// The target is chAva, cut back to where it meets the voted chain if
+    /// they diverge.
+    fn signed_vote(&self, head_hash: Hash, source: Checkpoint, slot: u64) -> Vote {
+        self.sign_vote(self.unsigned_vote(head_hash, source, &self.ch_ava, slot))
+    }
+
+    /// Our vote for `head_hash` with FFG source `source`, as `signed_vote`
+    /// builds it but with `ch_ava` in place of our chAva, and not yet signed.
+    fn unsigned_vote(&self, head_hash: Hash, source: Checkpoint, ch_ava: &Hash, slot: u64) -> Vote {
+        let target_hash = self.view.common_ancestor(ch_ava, &head_hash)
+            .unwrap_or_else(|| source.block_hash.clone());
+        let target = Checkpoint { block_hash: target_hash, slot };
+        Vote {
+            chain_head_hash: head_hash,
+            source,
+            target,
+            slot,
+            validator_id: self.validator.id,
+            signature: Signature::new(),
+        }
+    }
+
+    fn sign_vote(&self, mut vote: Vote) -> Vote {
+        vote.signature = self.signer.sign_vote(&vote);
+        vote
+    }
+
+    /// Try to fast-confirm blocks with supermajority support. Fails with
+    /// `FinalityConflict` if the greatest finalized checkpoint conflicts
+    /// with `ch_fin`, which leaves `ch_fin` where it was.
+    /// From Algorithm 7, lines 24-27.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(node_id = self.validator.id, slot = current_slot, phase = "confirm")))]
+    pub fn fast_confirm(&mut self, current_slot: u64) -> Result<(), NodeError> {
+        let mut vote_counts: HashMap<Hash, usize> = HashMap::new();
+        for vote in &self.view.votes {
+            if vote.slot == current_slot && self.validators.is_active(vote.validator_id, vote.slot) {
+                *vote_counts.entry(vote.chain_head_hash.clone()).or_insert(0) += 1;
+            }
+        }
+
+        let threshold = threshold_weight(
+            self.validators.active_count(current_slot),
+            self.config.availability_threshold_num,
+            self.config.availability_threshold_den,
+        );
+        // Only one head can reach a supermajority unless thresholds are
+        // lowered; even then, pick by count and then hash, not map order
+        let fast_cand = vote_counts.into_iter()
+            .filter(|(_, count)| *count as u64 >= threshold)
+            .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then_with(|| a.cmp(b)))
+            .map(|(hash, _)| hash);
+        if let Some(fast_cand) = fast_cand {
+            // Only move along our own canonical chain: the candidate must lie
+            // on the path to our fork-choice head and extend ch_ava
+            let on_canonical_chain = self.current_head(current_slot)
+                .map(|head| self.limit_reorg(head))
+                .is_ok_and(|head| self.view.ancestors(&head).any(|b| b.hash == fast_cand));
+            if on_canonical_chain && self.advance_ch_ava(fast_cand.clone()) {
+                println!("Node {} FAST CONFIRMING {} in slot {}", self.validator.id, fast_cand, current_slot);
+            } else if fast_cand != self.ch_ava {
+                println!("Node {} NOT fast-confirming {}: off the canonical chain", self.validator.id, fast_cand);
+            }
+        }
+
+        #[cfg(feature = "tracing")]
+        {
+            let gjc = self.gjc();
+            if gjc.slot == current_slot {
+                tracing::info!(checkpoint = %gjc, "justified");
+            }
+        }
+
+        if self.fault_threshold_exceeded && self.config.halt_on_fault_threshold {
+            return Ok(()); // Finality no longer means anything; hold chFin where it is
+        }
+
+        // chFin follows the greatest finalized checkpoint, GF(V)
+        let finalized = self.greatest_finalized_checkpoint();
+        match self.try_finalize(&finalized) {
+            Ok(true) => {
+                #[cfg(feature = "tracing")]
+                tracing::info!(checkpoint = %finalized, "finalized");
+            }
+            Ok(false) => {}
+            Err(e) => return Err(NodeError::FinalityConflict(e)),
+        }
+        Ok(())
+    }
+
+    /// Record our post-merge state for `slot`, if snapshots are enabled.
+    fn take_snapshot(&mut self, slot: u64) {
+        if self.config.snapshot_history == 0 {
+            return;
+        }
+        if self.snapshots.len() == self.config.snapshot_history {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(Snapshot {
+            slot,
+            view: self.view.clone(),
+            ch_ava: self.ch_ava.clone(),
+            ch_fin: self.ch_fin.clone(),
+            finalized_checkpoint: self.finalized_checkpoint.clone(),
+            last_proposal_slot: self.last_proposal_slot,
+            blocks_proposed: self.blocks_proposed,
+            last_head: self.last_head.clone(),
+            seen_proposals: self.seen_proposals.clone(),
+            proposer_equivocations: self.proposer_equivocations.clone(),
+            block_conflicts: self.block_conflicts.clone(),
+            finality_conflicts: self.finality_conflicts.clone(),
+            vote_history: self.vote_history.clone(),
+            vote_heads: self.vote_heads.clone(),
+            equivocators: self.equivocators.clone(),
+            fault_threshold_exceeded: self.fault_threshold_exceeded,
+            abstentions: self.abstentions.clone(),
+            pending_votes: self.pending_votes.clone(),
+            orphan_pool: self.orphan_pool.clone(),
+            buffered_votes: self.buffered_votes.clone(),
+        });
+    }
+
+    /// Roll back to our state at the end of `slot`, as snapshotted by the
+    /// merge phase of `on_tick`. Later snapshots are discarded, and the next
+    /// phase expected is the propose phase of `slot + 1`, so the run can be
+    /// replayed from there: fed the same messages, the replay makes the
+    /// same proposals and votes as the original run.
+    pub fn rewind_to(&mut self, slot: u64) -> Result<(), RewindError> {
+        let Some(index) = self.snapshots.iter().position(|snapshot| snapshot.slot == slot) else {
+            return Err(RewindError { slot, available: self.snapshots.iter().map(|s| s.slot).collect() });
+        };
+        self.snapshots.truncate(index + 1);
+        let snapshot = self.snapshots[index].clone();
+
+        self.frozen_view = snapshot.view.clone();
+        self.view = snapshot.view;
+        self.ch_ava = snapshot.ch_ava;
+        self.ch_fin = snapshot.ch_fin;
+        self.finalized_checkpoint = snapshot.finalized_checkpoint;
+        self.last_proposal_slot = snapshot.last_proposal_slot;
+        self.blocks_proposed = snapshot.blocks_proposed;
+        self.last_head = snapshot.last_head;
+        self.seen_proposals = snapshot.seen_proposals;
+        self.proposer_equivocations = snapshot.proposer_equivocations;
+        self.block_conflicts = snapshot.block_conflicts;
+        self.finality_conflicts = snapshot.finality_conflicts;
+        self.vote_history = snapshot.vote_history;
+        self.vote_heads = snapshot.vote_heads;
+        self.equivocators = snapshot.equivocators;
+        self.fault_threshold_exceeded = snapshot.fault_threshold_exceeded;
+        self.abstentions = snapshot.abstentions;
+        self.pending_votes = snapshot.pending_votes;
+        self.orphan_pool = snapshot.orphan_pool;
+        self.buffered_votes = snapshot.buffered_votes;
+        self.last_phase = Some((slot, SlotPhase::Merge));
+        self.proposer_boost = None;
+        self.frozen_slot = None;
+        self.pruned_at = None;
+        self.rebuild_vote_indexes();
+        Ok(())
+    }
+
+    /// Snapshot the live view as this slot's frozen view, which `vote` reads.
+    /// `on_tick` calls this at the start of the vote phase, once the slot's
+    /// proposal has arrived, unless `freeze_for` already froze the view for
+    /// the slot; blocks, votes and proposals received afterwards don't
+    /// influence this slot's vote.
+    pub fn freeze_view(&mut self) {
+        self.frozen_view = self.view.clone();
+    }
+
+    /// Freeze the view for `global_slot`'s vote ahead of its vote phase,
+    /// which then votes on this snapshot instead of refreezing.
+    pub fn freeze_for(&mut self, global_slot: u64) {
+        self.freeze_view();
+        self.frozen_slot = Some(self.local_slot(global_slot));
+    }
+
+    /// End the slot: drop per-slot state and recheck the chain heads.
+    /// The frozen view is refreshed by `freeze_view` in the next slot.
+    /// Fails if `check_invariants` does; `ch_fin` is never moved to repair
+    /// it, so a conflicting finalized checkpoint halts finality instead.
+    /// Algorithm 7, line 29.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(node_id = self.validator.id, phase = "merge")))]
+    pub fn merge(&mut self) -> Result<(), InvariantError> {
+        println!("Node {} MERGING view", self.validator.id);
+        // Boost only lasts for the slot of its proposal; `boost_at` also
+        // ignores it in later slots in case merge is skipped
+        self.proposer_boost = None;
+        // Expired votes no longer count, however late they came in
+        if let Some((slot, _)) = self.last_phase {
+            self.view.inclusion_slots.retain(|vote, _| vote.slot >= slot.saturating_sub(ETA));
+        }
+        // Carry cached results into the next slot only if the view is
+        // unchanged since they were computed
+        let state = self.view.state_hash();
+        self.justification_cache.sync_state(state.clone());
+        self.finalization_cache.sync_state(state);
+
+        self.repair_ch_ava();
+        self.check_invariants()
+    }
+
+    /// Check that `ch_ava` and `ch_fin` are both in our view, that `ch_fin`
+    /// is a prefix of `ch_ava`, and that `ch_fin` is finalized.
+    pub fn check_invariants(&mut self) -> Result<(), InvariantError> {
+        for hash in [&self.ch_ava, &self.ch_fin] {
+            if !self.view.blocks.contains_key(hash) {
+                return Err(InvariantError::MissingBlock(hash.clone()));
+            }
+        }
+        if !self.view.ancestors(&self.ch_ava).any(|b| b.hash == self.ch_fin) {
+            return Err(InvariantError::FinalizedNotPrefix { ch_fin: self.ch_fin.clone(), ch_ava: self.ch_ava.clone() });
+        }
+        let finalized = self.greatest_finalized_checkpoint();
+        if !self.view.ancestors(&finalized.block_hash).any(|b| b.hash == self.ch_fin) {
+            return Err(InvariantError::NotFinalized(self.ch_fin.clone()));
+        }
+        Ok(())
+    }
+
+    /// Pull `ch_ava` back to `ch_fin` if it no longer extends it. Only
+    /// `ch_ava` is repaired: `ch_fin` moves forward through `try_finalize`
+    /// and nowhere else.
+    fn repair_ch_ava(&mut self) {
+        if !self.view.ancestors(&self.ch_ava).any(|b| b.hash == self.ch_fin) {
+            println!("Node {} resetting ch_ava {} to ch_fin {}", self.validator.id, self.ch_ava, self.ch_fin);
+            self.ch_ava = self.ch_fin.clone();
+        }
+    }
+
+    /// The block exactly `k` parent hops back from `head_hash` in our frozen
+    /// view, or the oldest known ancestor (genesis) if the chain is shorter.
+    /// Counts blocks rather than slots, so skipped slots don't shift the result.
+    pub fn get_k_deep_prefix(&self, head_hash: &Hash, k: u64) -> Hash {
+        self.frozen_view.ancestors(head_hash)
+            .take(k as usize + 1)
+            .last()
+            .map_or_else(|| head_hash.clone(), |block| block.hash.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TieBreak;
+    use crate::hashing::HashFunction;
+    use crate::simulation::{Simulation, SimulationConfig};
+    #[cfg(feature = "tracing")]
+    use std::sync::{Arc, Mutex};
+
+    fn block(hash: &str, parent: &str, slot: u64) -> Block {
+        Block { hash: hash.to_string(), parent_hash: parent.to_string(), slot, proposer_id: 0, transactions: vec![] }
+    }
+
+    fn checkpoint(hash: &str, slot: u64) -> Checkpoint {
+        Checkpoint { block_hash: hash.to_string(), slot }
+    }
+
+    fn vote(validator_id: ValidatorId, head: &str, source: Checkpoint, target: Checkpoint, slot: u64) -> Vote {
+        Vote { chain_head_hash: head.to_string(), source, target, slot, validator_id, signature: Signature::new() }
+    }
+
+    /// Have validators `0..7` of 10 vote `source -> target` with `head`.
+    fn supermajority(node: &mut Node, head: &str, source: Checkpoint, target: Checkpoint) {
+        for id in 0..7 {
+            node.receive_message(None, Some(vote(id, head, source.clone(), target.clone(), target.slot)));
+        }
+    }
+
+    #[test]
+    fn ch_fin_never_moves_backwards() {
+        let mut config = SimulationConfig::default();
+        config.protocol.max_stored_votes = 35;
+        let mut sim = Simulation::new(config);
+        let mut previous = sim.finalized_heads();
+        for slot in 1..=8 {
+            sim.run_slot(slot);
+            for (node, old) in sim.nodes.iter().zip(&previous) {
+                assert!(node.view.ancestors(&node.ch_fin).any(|b| b.hash == *old),
+                        "node {} moved ch_fin from {} to {} in slot {}", node.validator.id, old, node.ch_fin, slot);
+            }
+            previous = sim.finalized_heads();
+        }
+    }
+
+    #[test]
+    fn merge_refuses_conflicting_finality() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        supermajority(&mut node, "a1", checkpoint(&genesis, 0), checkpoint("a1", 1));
+        supermajority(&mut node, "a1", checkpoint("a1", 1), checkpoint("a1", 2));
+        assert_eq!(node.try_finalize(&checkpoint("a1", 1)), Ok(true));
+
+        // A sibling fork above ch_fin's slot gets finalized at a higher slot
+        node.receive_message(Some(block("b2", &genesis, 2)), None);
+        node.receive_message(Some(block("b3", "b2", 3)), None);
+        supermajority(&mut node, "b3", checkpoint(&genesis, 0), checkpoint("b2", 2));
+        supermajority(&mut node, "b3", checkpoint("b2", 2), checkpoint("b3", 3));
+        assert_eq!(node.greatest_finalized_checkpoint(), checkpoint("b2", 2));
+
+        assert_eq!(node.merge(), Err(InvariantError::NotFinalized("a1".to_string())));
+        assert!(matches!(node.fast_confirm(3), Err(NodeError::FinalityConflict(_))));
+        assert_eq!(node.ch_fin, "a1");
+    }
+
+    #[test]
+    fn finalized_checkpoint_survives_losing_its_votes() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        supermajority(&mut node, "a1", checkpoint(&genesis, 0), checkpoint("a1", 1));
+        supermajority(&mut node, "a1", checkpoint("a1", 1), checkpoint("a1", 2));
+        assert_eq!(node.try_finalize(&checkpoint("a1", 1)), Ok(true));
+
+        for id in 0..7 {
+            node.slash(id, 0);
+        }
+        assert_eq!(node.greatest_finalized_checkpoint(), checkpoint("a1", 1));
+        assert_eq!(node.merge(), Ok(()));
+        assert_eq!(node.ch_fin, "a1");
+    }
+
+    #[test]
+    fn head_and_ffg_weights_are_tracked_separately() {
+        let genesis = Block::genesis().hash;
+        let mut validators = ValidatorRegistry::new(0..10);
+        validators.set_stake(0, 32);
+        let mut node = Node::with_validators(0, ProtocolConfig::default(), validators);
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        node.receive_message(Some(block("a2", "a1", 2)), None);
+        // Head votes for a2 whose FFG target is its ancestor a1
+        for id in 0..3 {
+            node.receive_message(None, Some(vote(id, "a2", checkpoint(&genesis, 0), checkpoint("a1", 2), 2)));
+        }
+        node.receive_message(None, Some(vote(3, "a1", checkpoint(&genesis, 0), checkpoint("a1", 2), 2)));
+
+        assert_eq!(node.head_vote_weight(&"a2".to_string()), 3);
+        assert_eq!(node.head_vote_weight(&"a1".to_string()), 1);
+        assert_eq!(node.ffg_vote_weight(&checkpoint("a1", 2)), 4);
+        assert_eq!(node.ffg_vote_weight(&checkpoint("a2", 2)), 0);
+    }
+
+    #[test]
+    fn deep_reorg_is_refused_where_the_head_is_adopted() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::with_config(9, ProtocolConfig { max_reorg_depth: Some(2), ..ProtocolConfig::default() });
+        for (hash, parent, slot) in [("a1", genesis.as_str(), 1), ("a2", "a1", 2), ("a3", "a2", 3), ("c3", "a2", 3)] {
+            node.receive_message(Some(block(hash, parent, slot)), None);
+        }
+        node.receive_message(None, Some(vote(0, "a3", checkpoint(&genesis, 0), checkpoint(&genesis, 3), 3)));
+        assert_eq!(node.propose(4).unwrap().block.parent_hash, "a3");
+
+        // A heavier fork from genesis would abandon all three blocks of a3's chain
+        for (hash, parent, slot) in [("b1", genesis.as_str(), 1), ("b2", "b1", 2), ("b3", "b2", 3)] {
+            node.receive_message(Some(block(hash, parent, slot)), None);
+        }
+        for id in 1..4 {
+            node.receive_message(None, Some(vote(id, "b3", checkpoint(&genesis, 0), checkpoint(&genesis, 4), 4)));
+        }
+        // Reading the head doesn't adopt it, so reading twice changes nothing
+        for _ in 0..2 {
+            assert_eq!(node.status().head.as_deref(), Some("b3"));
+            assert_eq!(node.preview_proposal(5).unwrap().block.parent_hash, "a3");
+        }
+        assert_eq!(node.last_head.as_deref(), Some("a3"));
+        assert_eq!(node.propose(5).unwrap().block.parent_hash, "a3");
+
+        // A one-block reorg to a sibling is within the limit
+        for id in 4..8 {
+            node.receive_message(None, Some(vote(id, "c3", checkpoint(&genesis, 0), checkpoint(&genesis, 5), 5)));
+        }
+        assert_eq!(node.propose(6).unwrap().block.parent_hash, "c3");
+    }
+
+    #[test]
+    fn buffered_votes_count_only_once_flushed() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::with_config(0, ProtocolConfig { buffer_votes: true, ..ProtocolConfig::default() });
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        node.receive_message(Some(block("b1", &genesis, 1)), None);
+        node.receive_message(None, Some(vote(4, "b1", checkpoint(&genesis, 0), checkpoint(&genesis, 1), 1)));
+
+        // Proposal votes are buffered like gossiped ones
+        let proposal = Proposal {
+            chain_head_hash: "p2".to_string(),
+            block: block("p2", "a1", 2),
+            votes: (1..4).map(|id| vote(id, "b1", checkpoint(&genesis, 0), checkpoint(&genesis, 1), 1)).collect(),
+            slot: 2,
+            proposer_id: 1,
+            signature: Signature::new(),
+        };
+        node.on_receive_proposal(&proposal).unwrap();
+        assert_eq!(node.buffered_vote_count(), 4);
+        assert!(node.view.votes.is_empty() && node.frozen_view.votes.is_empty());
+        assert_eq!(node.current_head(3).unwrap(), "p2");
+
+        node.flush_pending();
+        assert_eq!(node.buffered_vote_count(), 0);
+        assert_eq!(node.view.votes.len(), 4);
+        assert_eq!(node.current_head(3).unwrap(), "b1");
+    }
+
+    #[test]
+    fn from_view_picks_the_heavier_fork() {
+        let genesis = Block::genesis().hash;
+        let mut view = View::default();
+        for block in [Block::genesis(), block("a1", &genesis, 1), block("a2", "a1", 2), block("b1", &genesis, 1), block("b2", "b1", 2)] {
+            view.blocks.insert(block.hash.clone(), block);
+        }
+        for id in 0..4 {
+            view.insert_vote(vote(id, "b2", checkpoint(&genesis, 0), checkpoint(&genesis, 2), 2));
+        }
+        view.insert_vote(vote(4, "a2", checkpoint(&genesis, 0), checkpoint(&genesis, 2), 2));
+        let mut node = Node::from_view(view, genesis).unwrap();
+        assert_eq!(node.current_head(2).unwrap(), "b2");
+    }
+
+    #[test]
+    fn from_view_rejects_a_missing_root() {
+        let result = Node::from_view(View::default(), "nowhere".to_string());
+        assert!(matches!(result, Err(NodeError::UnknownRoot(hash)) if hash == "nowhere"));
+    }
+
+    #[test]
+    fn from_view_anchors_at_a_non_genesis_root() {
+        let mut view = View::default();
+        view.blocks.insert("r5".to_string(), block("r5", "pruned", 5));
+        view.blocks.insert("c6".to_string(), block("c6", "r5", 6));
+        let mut node = Node::from_view(view, "r5".to_string()).unwrap();
+        assert_eq!(node.view.base_checkpoint(), checkpoint("r5", 5));
+        assert_eq!(node.ch_fin, "r5");
+        assert_eq!(node.current_head(6).unwrap(), "c6");
+    }
+
+    #[test]
+    fn blocks_from_one_proposer_in_one_slot_get_distinct_hashes() {
+        let genesis = Block::genesis().hash;
+        for hash_function in [None, Some(HashFunction::Sha256), Some(HashFunction::Blake3)] {
+            let mut node = Node::with_config(0, ProtocolConfig { hash_function, ..ProtocolConfig::default() });
+            let first = node.propose_on(genesis.clone(), 1).block;
+            let second = node.propose_on(genesis.clone(), 1).block;
+            assert_ne!(first.hash, second.hash, "{:?}", hash_function);
+            assert!(node.view.blocks.contains_key(&first.hash) && node.view.blocks.contains_key(&second.hash));
+        }
+    }
+
+    #[test]
+    fn capped_vote_store_finalizes_like_uncapped() {
+        let mut capped = SimulationConfig::default();
+        capped.protocol.max_stored_votes = 35;
+        let mut capped = Simulation::new(capped);
+        let mut uncapped = Simulation::new(SimulationConfig::default());
+        for slot in 1..=10 {
+            capped.run_slot(slot);
+            uncapped.run_slot(slot);
+            assert_eq!(capped.finalized_heads(), uncapped.finalized_heads(), "slot {}", slot);
+            assert!(capped.nodes.iter().all(|node| node.view.votes.len() <= 35));
+        }
+        assert!(capped.nodes.iter().all(|node| node.equivocators().is_empty()));
+    }
+
+    #[test]
+    fn full_store_refuses_votes_instead_of_evicting() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::with_config(0, ProtocolConfig { max_stored_votes: 3, ..ProtocolConfig::default() });
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        let votes: Vec<Vote> = (0..4).map(|id| vote(id, "a1", checkpoint(&genesis, 0), checkpoint("a1", 1), 1)).collect();
+        for vote in &votes[..3] {
+            node.receive_message(None, Some(vote.clone()));
+        }
+        // An invalid vote is turned away before it can trigger a prune
+        node.receive_message(None, Some(vote(9999, "a1", checkpoint(&genesis, 0), checkpoint("a1", 1), 1)));
+        assert_eq!(node.pruned_at, None);
+        node.receive_message(None, Some(votes[3].clone()));
+        assert_eq!(node.pruned_at, Some(Checkpoint::genesis()));
+        assert_eq!(node.view.votes, votes[..3]);
+    }
+
+    #[test]
+    fn vote_flood_stays_under_cap() {
+        const CAP: usize = 100;
+        let mut config = SimulationConfig::default();
+        config.protocol.max_stored_votes = CAP;
+        config.protocol.verify_signatures = true;
+        let mut sim = Simulation::new(config);
+        for slot in 1..=3 {
+            sim.run_slot(slot);
+        }
+
+        // Unknown validators, far-future slots, unknown blocks, forged signatures
+        let genesis = Block::genesis().hash;
+        for i in 0..10_000u64 {
+            let head = if i % 2 == 0 { genesis.clone() } else { format!("junk_{}", i) };
+            let slot = 3 + i % 20;
+            let junk = vote(i % 40, &head, checkpoint(&genesis, 0), checkpoint(&genesis, slot), slot);
+            let node = &mut sim.nodes[0];
+            node.receive_message(None, Some(junk));
+            assert!(node.view.votes.len() <= CAP && node.pending_vote_count() <= CAP);
+        }
+
+        for slot in 4..=10 {
+            sim.run_slot(slot);
+        }
+        let flooded = &sim.nodes[0];
+        assert_eq!(flooded.ch_fin, sim.nodes[1].ch_fin);
+        assert!(flooded.view.blocks[&flooded.ch_fin].slot >= 8);
+        assert!(flooded.view.votes.len() <= CAP);
+    }
+
+    fn with_snapshots(history: usize) -> SimulationConfig {
+        let mut config = SimulationConfig::default();
+        config.protocol.snapshot_history = history;
+        config
+    }
+
+    #[test]
+    fn rewind_restores_chain_heads() {
+        let mut sim = Simulation::new(with_snapshots(4));
+        for slot in 1..=3 {
+            sim.run_slot(slot);
+        }
+        let (ch_ava, ch_fin) = (sim.nodes[0].ch_ava.clone(), sim.nodes[0].ch_fin.clone());
+        for slot in 4..=5 {
+            sim.run_slot(slot);
+        }
+        let node = &mut sim.nodes[0];
+        assert_ne!(node.ch_ava, ch_ava);
+        assert_eq!(node.rewind_to(3), Ok(()));
+        assert_eq!((&node.ch_ava, &node.ch_fin), (&ch_ava, &ch_fin));
+        assert_eq!(node.rewind_to(5), Err(RewindError { slot: 5, available: vec![2, 3] }));
+    }
+
+    #[test]
+    fn replay_after_rewind_matches_original_run() {
+        let mut sim = Simulation::new(with_snapshots(8));
+        for slot in 1..=3 {
+            sim.run_slot(slot);
+        }
+        let original: Vec<_> = (4..=6)
+            .map(|slot| (sim.run_slot(slot).votes, sim.finalized_heads()))
+            .collect();
+
+        for node in &mut sim.nodes {
+            node.rewind_to(3).unwrap();
+        }
+        let replayed: Vec<_> = (4..=6)
+            .map(|slot| (sim.run_slot(slot).votes, sim.finalized_heads()))
+            .collect();
+        assert_eq!(replayed, original);
+        assert_eq!(sim.nodes[0].ch_fin, "blk_s4_p3_n0");
+        for node in &sim.nodes {
+            assert!(node.equivocators().is_empty(), "node {} saw equivocators", node.validator.id);
+            assert!(node.proposer_equivocations().is_empty());
+        }
+    }
+
+    #[test]
+    fn head_extends_ch_ava_which_extends_ch_fin() {
+        let mut sim = Simulation::new(SimulationConfig::default());
+        for slot in 1..=5 {
+            sim.run_slot(slot);
+        }
+        let node = &mut sim.nodes[0];
+        let head = node.current_head(5).unwrap();
+        assert_eq!(head, "blk_s5_p4_n0");
+        assert!(node.view.ancestors(&head).any(|b| b.hash == node.ch_ava));
+        assert!(node.view.ancestors(&node.ch_ava).any(|b| b.hash == node.ch_fin));
+        assert_ne!(node.ch_fin, node.ch_ava);
+    }
+
+    #[test]
+    fn conflicting_proposals_are_recorded_and_not_boosted() {
+        let genesis = Block::genesis().hash;
+        let mut receiver = Node::new(0);
+        let proposer_id = receiver.proposer_for(1).unwrap();
+        let mut proposer = Node::new(proposer_id);
+        let first = proposer.propose_on(genesis.clone(), 1);
+        let second = proposer.propose_on(genesis, 1);
+        assert_ne!(first.block.hash, second.block.hash);
+
+        receiver.on_receive_proposal(&first).unwrap();
+        assert_eq!(receiver.boost_at(1), Some(&first.block.hash));
+        receiver.on_receive_proposal(&second).unwrap();
+        assert_eq!(receiver.proposer_equivocations(), [ProposerEquivocation {
+            proposer_id,
+            slot: 1,
+            first_block: first.block.hash.clone(),
+            second_block: second.block.hash.clone(),
+        }]);
+        assert_eq!(receiver.boost_at(1), None);
+
+        // Redelivering either block neither re-records nor restores the boost
+        receiver.on_receive_proposal(&first).unwrap();
+        assert_eq!(receiver.proposer_equivocations().len(), 1);
+        assert_eq!(receiver.boost_at(1), None);
+    }
+
+    #[test]
+    fn on_tick_runs_the_phases_of_a_slot_in_order() {
+        let proposer_id = Node::new(0).proposer_for(1).unwrap();
+        let mut node = Node::new(proposer_id);
+        let proposal = node.on_tick(1, SlotPhase::Propose).unwrap();
+        assert!(matches!(proposal, Some(Message::Proposal(p)) if p.slot == 1 && p.proposer_id == proposer_id));
+        let vote = node.on_tick(1, SlotPhase::Vote).unwrap();
+        assert!(matches!(vote, Some(Message::Vote(v)) if v.slot == 1 && v.validator_id == proposer_id));
+        assert_eq!(node.on_tick(1, SlotPhase::Confirm).unwrap(), None);
+        assert_eq!(node.on_tick(1, SlotPhase::Merge).unwrap(), None);
+        assert!(node.on_tick(2, SlotPhase::Propose).is_ok());
+    }
+
+    #[test]
+    fn on_tick_rejects_phases_out_of_order() {
+        let mut node = Node::new(0);
+        let skipped = node.on_tick(1, SlotPhase::Vote).unwrap_err();
+        assert!(matches!(skipped, NodeError::Phase(PhaseError { slot: 1, phase: SlotPhase::Vote, last_completed: None })));
+
+        node.on_tick(1, SlotPhase::Propose).unwrap();
+        let repeated = node.on_tick(1, SlotPhase::Propose).unwrap_err();
+        assert!(matches!(repeated, NodeError::Phase(PhaseError { last_completed: Some((1, SlotPhase::Propose)), .. })));
+        let next_slot = node.on_tick(2, SlotPhase::Vote).unwrap_err();
+        assert!(matches!(next_slot, NodeError::Phase(PhaseError { slot: 2, .. })));
+
+        // A refused tick leaves the node where it was
+        assert!(node.on_tick(1, SlotPhase::Vote).is_ok());
+    }
+
+    #[test]
+    fn clock_offsets_are_deterministic_and_bounded() {
+        let config = ProtocolConfig { max_clock_skew: 1, ..ProtocolConfig::default() };
+        let offsets: Vec<i64> = (0..6).map(|id| Node::with_config(id, config.clone()).slot_offset()).collect();
+        assert_eq!(offsets, [-1, 0, 1, -1, 0, 1]);
+
+        let mut node = Node::with_config(0, config);
+        node.set_slot_offset(5);
+        assert_eq!(node.slot_offset(), 1);
+        assert_eq!(node.local_slot(3), 4);
+        assert_eq!(Node::new(0).slot_offset(), 0);
+    }
+
+    #[test]
+    fn lagging_vote_counts_until_it_expires() {
+        let config = ProtocolConfig { max_clock_skew: 1, ..ProtocolConfig::default() };
+        let genesis = Block::genesis().hash;
+        let mut observer = Node::with_config(1, config.clone());
+        let mut lagging = Node::with_config(0, config);
+        assert_eq!((observer.slot_offset(), lagging.slot_offset()), (0, -1));
+        for node in [&mut observer, &mut lagging] {
+            node.receive_message(Some(block("a1", &genesis, 1)), None);
+        }
+
+        // Global slot 3 is slot 2 to the lagging node, so that's the slot it votes in
+        lagging.on_tick(3, SlotPhase::Propose).unwrap();
+        let Some(Message::Vote(vote)) = lagging.on_tick(3, SlotPhase::Vote).unwrap() else {
+            panic!("lagging node should vote");
+        };
+        assert_eq!((vote.slot, vote.chain_head_hash.as_str()), (2, "a1"));
+        observer.receive_message(None, Some(vote));
+
+        let support = |node: &Node, slot| fork_choice::subtree_support(&node.view, &node.validators, &"a1".to_string(), slot);
+        assert_eq!(support(&observer, 3).get(&0), Some(&true));
+        assert_eq!(support(&observer, 2 + ETA).get(&0), Some(&true));
+        assert_eq!(support(&observer, 3 + ETA).get(&0), None);
+    }
+
+    #[test]
+    fn fresh_node_gjc_is_genesis() {
+        let mut node = Node::new(0);
+        assert_eq!(node.gjc(), Checkpoint::genesis());
+
+        // Votes whose sources nobody justified don't move it either
+        let genesis = Block::genesis().hash;
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        node.receive_message(Some(block("a2", "a1", 2)), None);
+        supermajority(&mut node, "a2", checkpoint("a1", 1), checkpoint("a2", 2));
+        assert_eq!(node.gjc(), Checkpoint::genesis());
+    }
+
+    #[test]
+    fn second_block_under_a_taken_hash_is_a_conflict() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        let first = block("a1", &genesis, 1);
+        let impostor = Block { proposer_id: 3, ..block("a1", &genesis, 1) };
+        node.receive_message(Some(first.clone()), None);
+        node.receive_message(Some(first.clone()), None);
+        assert!(node.block_conflicts().is_empty());
+
+        node.receive_message(Some(impostor.clone()), None);
+        assert_eq!(node.block_conflicts(), [BlockConflict { existing: first.clone(), rejected: impostor }]);
+        assert_eq!(node.view.blocks["a1"], first);
+    }
+
+    #[test]
+    fn well_supported_sibling_does_not_move_ch_ava() {
+        // genesis - a1 - a2 (justified)
+        //             \ b3
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        for (hash, parent, slot) in [("a1", genesis.as_str(), 1), ("a2", "a1", 2), ("b3", "a1", 3)] {
+            node.receive_message(Some(block(hash, parent, slot)), None);
+        }
+        supermajority(&mut node, "a2", Checkpoint::genesis(), checkpoint("a2", 2));
+        node.fast_confirm(2).unwrap();
+        assert_eq!(node.ch_ava, "a2");
+
+        // Seven current-slot votes for the sibling, but fork choice starts
+        // from the justified a2, so b3 isn't on our canonical chain
+        for id in 0..7 {
+            node.receive_message(None, Some(vote(id, "b3", Checkpoint::genesis(), checkpoint("a1", 1), 3)));
+        }
+        assert_eq!(node.view.votes.iter().filter(|v| v.chain_head_hash == "b3").count(), 7);
+        assert_eq!(node.gjc(), checkpoint("a2", 2));
+        node.fast_confirm(3).unwrap();
+        assert_eq!(node.ch_ava, "a2");
+    }
+
+    #[test]
+    fn vote_history_keeps_each_validators_votes_in_order() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::with_config(0, ProtocolConfig { vote_history_cap: 2, ..ProtocolConfig::default() });
+        for (hash, parent, slot) in [("a1", genesis.as_str(), 1), ("a2", "a1", 2), ("a3", "a2", 3)] {
+            node.receive_message(Some(block(hash, parent, slot)), None);
+        }
+        let first = vote(4, "a1", Checkpoint::genesis(), checkpoint("a1", 1), 1);
+        let second = vote(4, "a2", Checkpoint::genesis(), checkpoint("a2", 2), 2);
+        node.receive_message(None, Some(first.clone()));
+        node.receive_message(None, Some(second.clone()));
+        node.receive_message(None, Some(first.clone()));
+        assert_eq!(node.vote_history(4), [first, second.clone()]);
+        assert!(node.vote_history(5).is_empty());
+
+        // Past the cap, the oldest vote makes way
+        let third = vote(4, "a3", Checkpoint::genesis(), checkpoint("a3", 3), 3);
+        node.receive_message(None, Some(third.clone()));
+        assert_eq!(node.vote_history(4), [second, third]);
+    }
+
+    #[test]
+    fn ch_ava_only_advances_along_its_own_chain() {
+        // genesis - a1 - a2
+        //             \ b2
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        for (hash, parent, slot) in [("a1", genesis.as_str(), 1), ("a2", "a1", 2), ("b2", "a1", 2)] {
+            node.receive_message(Some(block(hash, parent, slot)), None);
+        }
+        assert!(node.advance_ch_ava("a1".to_string()));
+        assert!(node.advance_ch_ava("a2".to_string()));
+        assert_eq!(node.ch_ava, "a2");
+
+        // Back to an ancestor, over to a sibling, or in place: all refused
+        assert!(!node.advance_ch_ava("a1".to_string()));
+        assert!(!node.advance_ch_ava("b2".to_string()));
+        assert!(!node.advance_ch_ava("a2".to_string()));
+        assert!(!node.advance_ch_ava("missing".to_string()));
+        assert_eq!(node.ch_ava, "a2");
+    }
+
+    #[test]
+    fn repeated_queries_hit_the_cache() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        node.receive_message(Some(block("a2", "a1", 2)), None);
+        supermajority(&mut node, "a1", Checkpoint::genesis(), checkpoint("a1", 1));
+        supermajority(&mut node, "a2", checkpoint("a1", 1), checkpoint("a2", 2));
+        node.reset_cache_stats();
+
+        assert_eq!(node.is_finalized(&checkpoint("a1", 1)), Ok(true));
+        let first = node.cache_stats();
+        assert_eq!(first.hits, 0);
+        assert!(first.misses > 0);
+        for _ in 0..3 {
+            assert_eq!(node.is_finalized(&checkpoint("a1", 1)), Ok(true));
+        }
+        assert_eq!(node.cache_stats(), CacheStats { hits: 3, misses: first.misses });
+
+        // Merging keeps counting from there; only a reset clears the counters
+        node.merge().unwrap();
+        let merged = node.cache_stats();
+        assert!(merged.hits >= 3 && merged.misses >= first.misses, "{:?}", merged);
+        node.reset_cache_stats();
+        assert_eq!(node.cache_stats(), CacheStats::default());
+    }
+
+    #[test]
+    fn node_abstains_when_its_head_conflicts_with_the_gjc() {
+        // genesis - a1 - a2 - a3 (our head)
+        //             \ b2 - b3, with b2 justified
+        let genesis = Block::genesis().hash;
+        let mut node = Node::with_config(0, ProtocolConfig { max_reorg_depth: Some(1), ..ProtocolConfig::default() });
+        for (hash, parent, slot) in [("a1", genesis.as_str(), 1), ("a2", "a1", 2), ("a3", "a2", 3)] {
+            node.receive_message(Some(block(hash, parent, slot)), None);
+        }
+        node.freeze_view();
+        assert!(matches!(node.vote(3), Ok(Some(vote)) if vote.chain_head_hash == "a3"));
+
+        node.receive_message(Some(block("b2", "a1", 2)), None);
+        node.receive_message(Some(block("b3", "b2", 3)), None);
+        supermajority(&mut node, "b2", Checkpoint::genesis(), checkpoint("b2", 2));
+        node.freeze_view();
+        // Moving to b3 would reorg two blocks deep, so we keep a3, which
+        // doesn't descend from the GJC: no vote for it can be FFG-safe
+        assert_eq!(node.vote(4).unwrap(), None);
+        assert_eq!(node.abstentions(), [4]);
+    }
+
+    #[test]
+    fn vote_with_target_off_the_voted_chain_is_rejected() {
+        // genesis - a1 - a2
+        //             \ b2
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        for (hash, parent, slot) in [("a1", genesis.as_str(), 1), ("a2", "a1", 2), ("b2", "a1", 2)] {
+            node.receive_message(Some(block(hash, parent, slot)), None);
+        }
+        let off_chain = vote(3, "a2", Checkpoint::genesis(), checkpoint("b2", 2), 2);
+        assert_eq!(validate_vote(&off_chain, &node.view, &node.validators),
+                   Err(VoteError::TargetOffHeadChain { target: "b2".to_string(), head: "a2".to_string() }));
+        node.receive_message(None, Some(off_chain.clone()));
+        assert!(!node.view.contains_vote(&off_chain));
+
+        let on_chain = vote(3, "a2", Checkpoint::genesis(), checkpoint("a1", 2), 2);
+        assert_eq!(validate_vote(&on_chain, &node.view, &node.validators), Ok(()));
+
+        // Our own votes cut a diverging ch_ava back to where it meets the head's chain
+        assert!(node.advance_ch_ava("b2".to_string()));
+        let ours = node.vote_for("a2".to_string(), 3).unwrap();
+        assert_eq!(ours.target, checkpoint("a1", 3));
+    }
+
+    #[test]
+    fn node_from_trusted_checkpoint_finalizes_what_follows() {
+        let anchor = checkpoint("trusted", 10);
+        let anchor_block = block("trusted", "history_we_never_saw", 10);
+        let mut sim = Simulation::new(SimulationConfig::default());
+        for node in sim.nodes.iter_mut() {
+            *node = Node::new(node.validator.id).with_trusted_checkpoint(anchor.clone(), anchor_block.clone());
+            assert_eq!(node.gjc(), anchor);
+            assert_eq!(node.greatest_finalized_checkpoint(), anchor);
+        }
+        for slot in 11..=15 {
+            sim.run_slot(slot);
+        }
+
+        let node = &mut sim.nodes[0];
+        assert_eq!(node.view.blocks[&node.ch_fin].slot, 13);
+        assert!(node.view.ancestors(&node.ch_fin).any(|b| b.hash == "trusted"));
+        // Nothing before the anchor was ever fetched or voted on
+        assert!(node.view.blocks.values().all(|b| b.slot >= 10));
+        assert!(node.view.votes.iter().all(|v| v.source.slot >= 10));
+    }
+
+    #[test]
+    fn pruning_never_regresses_gjc_or_ch_fin() {
+        let mut sim = Simulation::new(SimulationConfig::default());
+        for slot in 1..=12 {
+            sim.run_slot(slot);
+            // Prune every node after every slot, as aggressively as allowed
+            for node in sim.nodes.iter_mut() {
+                let (gjc, ch_fin, finalized) = (node.gjc(), node.ch_fin.clone(), node.greatest_finalized_checkpoint());
+                let votes = node.view.votes.len();
+                node.prune_finalized().unwrap();
+                assert!(slot < 3 || node.view.votes.len() < votes, "slot {}: nothing pruned", slot);
+                assert_eq!(node.gjc(), gjc);
+                assert_eq!(node.ch_fin, ch_fin);
+                assert_eq!(node.greatest_finalized_checkpoint(), finalized);
+                assert!(node.is_finalized(&finalized).unwrap());
+            }
+        }
+        // And finality keeps moving on the pruned views
+        let node = &mut sim.nodes[0];
+        assert_eq!(node.view.blocks[&node.ch_fin].slot, 10);
+    }
+
+    #[test]
+    fn votes_from_unknown_validators_are_ignored() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        for id in (0..6).chain([9999]) {
+            node.receive_message(None, Some(vote(id, "a1", Checkpoint::genesis(), checkpoint("a1", 1), 1)));
+        }
+        // Refused on receipt, so six real votes fall one short everywhere
+        assert!(node.view.votes.iter().all(|v| v.validator_id != 9999));
+        node.fast_confirm(1).unwrap();
+        assert_eq!(node.ch_ava, genesis);
+        assert_eq!(node.gjc(), Checkpoint::genesis());
+
+        // Even if one reaches the view, quorum math doesn't count it
+        let stray = vote(9999, "a1", Checkpoint::genesis(), checkpoint("a1", 1), 1);
+        assert_eq!(validate_vote(&stray, &node.view, &node.validators), Err(VoteError::UnknownValidator(9999)));
+        node.view.insert_vote(stray);
+        let mut cache = JustificationCache::new(16);
+        assert_eq!(ffg::is_justified(&checkpoint("a1", 1), &node.view, &node.validators, &mut cache), Ok(false));
+        node.fast_confirm(1).unwrap();
+        assert_eq!(node.ch_ava, genesis);
+
+        // A seventh real vote is what it takes
+        node.receive_message(None, Some(vote(6, "a1", Checkpoint::genesis(), checkpoint("a1", 1), 1)));
+        node.fast_confirm(1).unwrap();
+        assert_eq!(node.ch_ava, "a1");
+    }
+
+    #[test]
+    fn only_leaves_descending_from_the_gjc_are_valid_heads() {
+        // genesis - a1 - a2 (justified) - a3
+        //             \ b2 - b3
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        for (hash, parent, slot) in [("a1", genesis.as_str(), 1), ("a2", "a1", 2), ("a3", "a2", 3), ("b2", "a1", 2), ("b3", "b2", 3)] {
+            node.receive_message(Some(block(hash, parent, slot)), None);
+        }
+        assert_eq!(node.valid_head_candidates(3), ["a3", "b3"]);
+
+        supermajority(&mut node, "a2", Checkpoint::genesis(), checkpoint("a2", 2));
+        assert_eq!(node.valid_head_candidates(3), ["a3"]);
+        // As of slot 1 no leaf descends from the GJC, so it falls back to the GJC block
+        assert_eq!(node.valid_head_candidates(1), ["a2"]);
+    }
+
+    #[test]
+    fn k_deep_prefix_counts_blocks_not_slots() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        // Contiguous c1..c5, and s2, s5, s9 skipping slots
+        let mut parent = genesis.clone();
+        for slot in 1..=5 {
+            let hash = format!("c{}", slot);
+            node.receive_message(Some(block(&hash, &parent, slot)), None);
+            parent = hash;
+        }
+        for (hash, parent, slot) in [("s2", genesis.as_str(), 2), ("s5", "s2", 5), ("s9", "s5", 9)] {
+            node.receive_message(Some(block(hash, parent, slot)), None);
+        }
+        node.freeze_view();
+        let prefix = |k| node.get_k_deep_prefix(&"c5".to_string(), k);
+        assert_eq!([prefix(0), prefix(1), prefix(2)], ["c5", "c4", "c3"]);
+        assert_eq!(prefix(5), genesis);
+        assert_eq!(prefix(50), genesis);
+
+        let prefix = |k| node.get_k_deep_prefix(&"s9".to_string(), k);
+        assert_eq!([prefix(1), prefix(2)], ["s5", "s2"]);
+        assert_eq!(prefix(3), genesis);
+    }
+
+    #[test]
+    fn votes_before_their_blocks_count_once_the_blocks_arrive() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        supermajority(&mut node, "a1", Checkpoint::genesis(), checkpoint("a1", 1));
+        assert_eq!(node.pending_vote_count(), 7);
+        assert!(node.view.votes.is_empty());
+        node.fast_confirm(1).unwrap();
+        assert_eq!(node.ch_ava, genesis);
+        assert_eq!(node.gjc(), Checkpoint::genesis());
+
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        assert_eq!(node.pending_vote_count(), 0);
+        assert_eq!(node.view.votes.len(), 7);
+        node.fast_confirm(1).unwrap();
+        assert_eq!(node.ch_ava, "a1");
+        assert_eq!(node.gjc(), checkpoint("a1", 1));
+    }
+
+    /// Captures the fields of every span and, for each event, its message
+    /// with the fields of the spans it was emitted in.
+    #[cfg(feature = "tracing")]
+    #[derive(Clone, Default)]
+    struct SpanRecorder {
+        spans: Arc<Mutex<Vec<SpanFields>>>,
+        stack: Arc<Mutex<Vec<u64>>>,
+        events: Arc<Mutex<Vec<RecordedEvent>>>,
+    }
+
+    #[cfg(feature = "tracing")]
+    type SpanFields = HashMap<String, String>;
+
+    #[cfg(feature = "tracing")]
+    struct RecordedEvent {
+        message: String,
+        spans: Vec<SpanFields>, // Outermost first
+    }
+
+    #[cfg(feature = "tracing")]
+    #[derive(Default)]
+    struct Fields(SpanFields);
+
+    #[cfg(feature = "tracing")]
+    impl tracing::field::Visit for Fields {
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+            self.0.insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for SpanRecorder {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut fields = Fields::default();
+            span.record(&mut fields);
+            let mut spans = self.spans.lock().unwrap();
+            spans.push(fields.0);
+            tracing::span::Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, event: &tracing::Event<'_>) {
+            let mut fields = Fields::default();
+            event.record(&mut fields);
+            let spans = self.spans.lock().unwrap();
+            let spans = self.stack.lock().unwrap().iter().map(|&id| spans[id as usize - 1].clone()).collect();
+            let message = fields.0.remove("message").unwrap_or_default();
+            self.events.lock().unwrap().push(RecordedEvent { message, spans });
+        }
+
+        fn enter(&self, span: &tracing::span::Id) {
+            self.stack.lock().unwrap().push(span.into_u64());
+        }
+
+        fn exit(&self, _: &tracing::span::Id) {
+            self.stack.lock().unwrap().pop();
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn justified_event_carries_its_slot_span() {
+        let recorder = SpanRecorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let genesis = Block::genesis().hash;
+            let mut node = Node::new(3);
+            node.receive_message(Some(block("a1", &genesis, 1)), None);
+            node.on_tick(1, SlotPhase::Propose).unwrap();
+            node.on_tick(1, SlotPhase::Vote).unwrap();
+            supermajority(&mut node, "a1", Checkpoint::genesis(), checkpoint("a1", 1));
+            node.on_tick(1, SlotPhase::Confirm).unwrap();
+        });
+
+        let events = recorder.events.lock().unwrap();
+        let justified = events.iter().find(|event| event.message == "justified").expect("no justified event");
+        let field = |name: &str| justified.spans.last().and_then(|span| span.get(name)).map(String::as_str);
+        assert_eq!((field("slot"), field("node_id"), field("phase")), (Some("1"), Some("3"), Some("confirm")));
+        // Nested in the tick that drove the confirm phase
+        assert_eq!(justified.spans.first().and_then(|span| span.get("phase")).map(String::as_str), Some("Confirm"));
+    }
+
+    #[test]
+    fn block_not_after_its_parent_is_refused() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        node.receive_message(Some(block("a2", &genesis, 2)), None);
+        node.receive_message(Some(block("same", "a2", 2)), None);
+        node.receive_message(Some(block("a3", "a2", 3)), None);
+        assert!(!node.view.blocks.contains_key("same"));
+        assert!(node.view.blocks.contains_key("a3"));
+        assert_eq!(node.view.validate_slot_monotonicity(), Ok(()));
+    }
+
+    #[test]
+    fn availability_quorum_moves_ch_ava_but_not_finality() {
+        let config = ProtocolConfig { availability_threshold_num: 1, availability_threshold_den: 2, ..ProtocolConfig::default() };
+        let genesis = Block::genesis().hash;
+        let mut node = Node::with_config(0, config);
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        node.receive_message(Some(block("a2", "a1", 2)), None);
+        // Six of ten clears 1/2 but not 2/3
+        for id in 0..6 {
+            node.receive_message(None, Some(vote(id, "a1", Checkpoint::genesis(), checkpoint("a1", 1), 1)));
+        }
+        node.fast_confirm(1).unwrap();
+        assert_eq!(node.ch_ava, "a1");
+        assert_eq!(node.gjc(), Checkpoint::genesis());
+
+        for id in 0..6 {
+            node.receive_message(None, Some(vote(id, "a2", checkpoint("a1", 1), checkpoint("a2", 2), 2)));
+        }
+        node.fast_confirm(2).unwrap();
+        assert_eq!(node.ch_ava, "a2");
+        assert_eq!(node.ch_fin, genesis);
+        assert_eq!(node.greatest_finalized_checkpoint(), Checkpoint::genesis());
+    }
+
+    #[test]
+    fn justification_report_covers_each_slot_in_range() {
+        // Blocks at slots 1, 2, 4 and 5, with slots 2 and 4 justified
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        for (hash, parent, slot) in [("a1", genesis.as_str(), 1), ("a2", "a1", 2), ("a4", "a2", 4), ("a5", "a4", 5)] {
+            node.receive_message(Some(block(hash, parent, slot)), None);
+        }
+        supermajority(&mut node, "a2", Checkpoint::genesis(), checkpoint("a2", 2));
+        supermajority(&mut node, "a4", checkpoint("a2", 2), checkpoint("a4", 4));
+        // Too few votes to justify a5
+        for id in 0..3 {
+            node.receive_message(None, Some(vote(id, "a5", checkpoint("a4", 4), checkpoint("a5", 5), 5)));
+        }
+        assert_eq!(node.justification_report(1, 5), [
+            (1, None),
+            (2, Some(checkpoint("a2", 2))),
+            (3, None),
+            (4, Some(checkpoint("a4", 4))),
+            (5, None),
+        ]);
+        assert_eq!(node.justification_report(0, 0), [(0, Some(Checkpoint::genesis()))]);
+    }
+
+    #[test]
+    fn vote_arrival_order_does_not_change_the_outcome() {
+        // Two forks with tied support, plus a justifying link to a1
+        let genesis = Block::genesis().hash;
+        let blocks = [block("a1", &genesis, 1), block("a2", "a1", 2), block("b2", "a1", 2)];
+        let mut votes: Vec<Vote> = (0..7).map(|id| vote(id, "a1", Checkpoint::genesis(), checkpoint("a1", 1), 1)).collect();
+        votes.extend((0..4).map(|id| vote(id, "a2", checkpoint("a1", 1), checkpoint("a1", 2), 2)));
+        votes.extend((4..8).map(|id| vote(id, "b2", checkpoint("a1", 1), checkpoint("a1", 2), 2)));
+
+        let outcome = |votes: Vec<Vote>| {
+            let mut node = Node::new(0);
+            for block in &blocks {
+                node.receive_message(Some(block.clone()), None);
+            }
+            for vote in votes {
+                node.receive_message(None, Some(vote));
+            }
+            node.fast_confirm(1).unwrap();
+            node.fast_confirm(2).unwrap();
+            (node.view.votes.clone(), node.gjc(), node.ch_ava.clone(), node.current_head(2).unwrap())
+        };
+        let forward = outcome(votes.clone());
+        votes.reverse();
+        assert_eq!(outcome(votes.clone()), forward);
+        votes.sort_by_key(|vote| (vote.chain_head_hash.clone(), std::cmp::Reverse(vote.validator_id)));
+        assert_eq!(outcome(votes), forward);
+        assert_eq!((forward.1, forward.2), (checkpoint("a1", 2), "a1".to_string()));
+    }
+
+    #[test]
+    fn handle_message_dispatches_each_variant() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        let a1 = block("a1", &genesis, 1);
+        node.handle_message(node.envelope(Message::Block(a1.clone())));
+        assert_eq!(node.view.blocks.get("a1"), Some(&a1));
+
+        let single = vote(0, "a1", Checkpoint::genesis(), checkpoint("a1", 1), 1);
+        node.handle_message(node.envelope(Message::Vote(single.clone())));
+        assert!(node.view.contains_vote(&single));
+
+        let votes: Vec<Vote> = (1..7).map(|id| vote(id, "a1", Checkpoint::genesis(), checkpoint("a1", 1), 1)).collect();
+        let aggregate = AggregatedVote::from_votes(&votes).unwrap();
+        node.handle_message(node.envelope(Message::Aggregate(aggregate)));
+        assert!(votes.iter().all(|vote| node.view.contains_vote(vote)));
+        assert_eq!(node.gjc(), checkpoint("a1", 1));
+
+        let mut proposer = Node::new(node.proposer_for(2).unwrap());
+        proposer.receive_message(Some(a1), None);
+        let proposal = proposer.propose_on("a1".to_string(), 2);
+        node.handle_message(node.envelope(Message::Proposal(proposal.clone())));
+        assert!(node.view.blocks.contains_key(&proposal.block.hash));
+        assert_eq!(node.boost_at(2), Some(&proposal.block.hash));
+
+        // Another network's messages are dropped unread
+        let foreign = block("foreign", &genesis, 3);
+        assert_ne!(node.fork_digest(), [0xff; 4]);
+        node.handle_message(Envelope { fork_digest: [0xff; 4], message: Message::Block(foreign) });
+        assert!(!node.view.blocks.contains_key("foreign"));
+    }
+
+    #[test]
+    fn proposer_boost_applies_only_in_its_slot() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        node.receive_message(None, Some(vote(0, "a1", Checkpoint::genesis(), checkpoint("a1", 1), 1)));
+
+        let proposal = Node::new(node.proposer_for(2).unwrap()).propose_on(genesis, 2);
+        node.on_receive_proposal(&proposal).unwrap();
+        assert_eq!(node.proposer_boost(), Some((&proposal.block.hash, 2)));
+
+        // The boost outweighs the single vote for `a1` during slot 2 only
+        assert_eq!(node.current_head(2).unwrap(), proposal.block.hash);
+        assert_eq!(node.current_head(3).unwrap(), "a1");
+
+        // and is dropped once the slot's view is merged
+        node.merge().unwrap();
+        assert_eq!(node.proposer_boost(), None);
+    }
+
+    #[test]
+    fn votes_after_the_freeze_do_not_sway_this_slots_vote() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(9);
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        node.receive_message(Some(block("b1", &genesis, 1)), None);
+        node.receive_message(None, Some(vote(0, "a1", Checkpoint::genesis(), Checkpoint::genesis(), 1)));
+        node.freeze_view();
+
+        for id in 1..3 {
+            node.receive_message(None, Some(vote(id, "b1", Checkpoint::genesis(), Checkpoint::genesis(), 1)));
+        }
+        assert_eq!(node.current_head(2).unwrap(), "b1");
+        let cast = node.vote(2).unwrap().unwrap();
+        assert_eq!(cast.chain_head_hash, "a1");
+
+        // The late votes count from the next freeze on
+        node.freeze_view();
+        assert_eq!(node.vote(3).unwrap().unwrap().chain_head_hash, "b1");
+    }
+
+    #[test]
+    fn unchanged_view_keeps_cached_results_across_merge() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        node.receive_message(Some(block("a2", "a1", 2)), None);
+        supermajority(&mut node, "a1", Checkpoint::genesis(), checkpoint("a1", 1));
+        supermajority(&mut node, "a2", checkpoint("a1", 1), checkpoint("a2", 2));
+        node.merge().unwrap();
+        assert_eq!(node.is_finalized(&checkpoint("a1", 1)), Ok(true));
+
+        // Nothing arrived since, so the next merge keeps every entry and
+        // its own finality check is answered from them
+        let cached = node.justification_cache.len();
+        node.reset_cache_stats();
+        node.merge().unwrap();
+        assert_eq!(node.justification_cache.len(), cached);
+        assert_eq!(node.cache_stats().misses, 0);
+
+        // A new block changes the state hash and drops them
+        node.receive_message(Some(block("a3", "a2", 3)), None);
+        node.reset_cache_stats();
+        node.merge().unwrap();
+        assert!(node.cache_stats().misses > 0);
+    }
+
+    #[test]
+    fn longest_chain_rule_ignores_votes() {
+        // a1 has the votes, b1 - b2 the length
+        let genesis = Block::genesis().hash;
+        let blocks = [block("a1", &genesis, 1), block("b1", &genesis, 1), block("b2", "b1", 2)];
+        let mut ghost = Node::new(0);
+        let mut longest = Node::new(0).with_fork_choice(fork_choice::LongestChain);
+        for node in [&mut ghost, &mut longest] {
+            for block in &blocks {
+                node.receive_message(Some(block.clone()), None);
+            }
+            for id in 0..3 {
+                node.receive_message(None, Some(vote(id, "a1", Checkpoint::genesis(), Checkpoint::genesis(), 2)));
+            }
+        }
+        assert_eq!(ghost.current_head(3).unwrap(), "a1");
+        assert_eq!(longest.current_head(3).unwrap(), "b2");
+    }
+
+    #[test]
+    fn equivocators_past_the_fault_threshold_halt_finality() {
+        let genesis = Block::genesis().hash;
+        let config = ProtocolConfig { halt_on_fault_threshold: true, ..ProtocolConfig::default() };
+        let mut node = Node::with_validators(0, config, ValidatorRegistry::new(0..10));
+        for block in [block("a1", &genesis, 1), block("a2", "a1", 2), block("a3", "a2", 3), block("c3", "a2", 3)] {
+            node.receive_message(Some(block), None);
+        }
+        supermajority(&mut node, "a1", Checkpoint::genesis(), checkpoint("a1", 1));
+        supermajority(&mut node, "a2", checkpoint("a1", 1), checkpoint("a2", 2));
+        node.fast_confirm(2).unwrap();
+        assert_eq!(node.ch_fin, "a1");
+
+        // 10 / 3 + 1 = 4 validators vote for both a3 and c3 in slot 3
+        for id in 0..4 {
+            node.receive_message(None, Some(vote(id, "a3", checkpoint("a2", 2), checkpoint("a3", 3), 3)));
+            assert!(!node.fault_threshold_exceeded());
+            node.receive_message(None, Some(vote(id, "c3", checkpoint("a2", 2), checkpoint("a2", 3), 3)));
+        }
+        assert_eq!(node.equivocators(), &(0..4).collect::<BTreeSet<_>>());
+        assert!(node.fault_threshold_exceeded());
+
+        // a2 is finalized by the votes, but ch_fin stays put
+        supermajority(&mut node, "a3", checkpoint("a2", 2), checkpoint("a3", 3));
+        assert_eq!(node.greatest_finalized_checkpoint(), checkpoint("a2", 2));
+        node.fast_confirm(3).unwrap();
+        assert_eq!(node.ch_fin, "a1");
+    }
+
+    #[test]
+    fn proposal_building_below_the_gjc_is_rejected() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        supermajority(&mut node, "a1", Checkpoint::genesis(), checkpoint("a1", 1));
+        assert_eq!(node.gjc(), checkpoint("a1", 1));
+
+        // The proposer missed a1 and builds a sibling on genesis
+        let proposal = Node::new(node.proposer_for(2).unwrap()).propose_on(genesis.clone(), 2);
+        let expected = ProposalError::ConflictsWithJustified { parent: genesis, justified: checkpoint("a1", 1) };
+        assert_eq!(node.validate_proposal(&proposal), Err(expected.clone()));
+        match node.on_receive_proposal(&proposal) {
+            Err(NodeError::InvalidProposal { slot: 2, error }) => assert_eq!(error, expected),
+            other => panic!("expected an invalid proposal, got {:?}", other),
+        }
+        assert!(!node.view.blocks.contains_key(&proposal.block.hash));
+        assert_eq!(node.boost_at(2), None);
+        assert!(expected.to_string().contains("does not descend from justified checkpoint"));
+    }
+
+    #[test]
+    fn finalizing_a_sibling_of_ch_fin_is_refused() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        for block in [block("a1", &genesis, 1), block("a2", "a1", 2), block("b1", &genesis, 1), block("b2", "b1", 2)] {
+            node.receive_message(Some(block), None);
+        }
+        supermajority(&mut node, "a2", Checkpoint::genesis(), checkpoint("a1", 1));
+        supermajority(&mut node, "a2", checkpoint("a1", 1), checkpoint("a2", 2));
+        assert_eq!(node.try_finalize(&checkpoint("a1", 1)), Ok(true));
+
+        // Even finalized by its own votes, which takes validators 3..7
+        // voting slashably, the sibling b1 can't replace a1
+        for id in 3..10 {
+            node.receive_message(None, Some(vote(id, "b2", Checkpoint::genesis(), checkpoint("b1", 3), 3)));
+            node.receive_message(None, Some(vote(id, "b2", checkpoint("b1", 3), checkpoint("b2", 4), 4)));
+        }
+        assert_eq!(node.is_finalized(&checkpoint("b1", 3)), Ok(true));
+        assert_eq!(node.try_finalize(&checkpoint("b1", 3)), Err(FinalizeError::ConflictsWithFinalized {
+            candidate: checkpoint("b1", 3),
+            ch_fin: "a1".to_string(),
+        }));
+        assert_eq!(node.ch_fin, "a1");
+        // A checkpoint ch_fin already covers is no error, just no move
+        assert_eq!(node.try_finalize(&Checkpoint::genesis()), Ok(false));
+    }
+
+    #[test]
+    fn greatest_finalized_checkpoint_matches_ch_fin() {
+        assert_eq!(Node::new(0).greatest_finalized_checkpoint(), Checkpoint::genesis());
+
+        let mut sim = Simulation::new(SimulationConfig { num_slots: 6, ..SimulationConfig::default() });
+        sim.run();
+        for node in &mut sim.nodes {
+            let finalized = node.greatest_finalized_checkpoint();
+            assert_eq!(finalized.block_hash, node.ch_fin);
+            assert_ne!(finalized, Checkpoint::genesis());
+            // Finality only follows justification, and ch_ava sits on top of both
+            let gjc = node.gjc();
+            assert!(gjc.slot > finalized.slot);
+            assert!(node.view.ancestors(&gjc.block_hash).any(|b| b.hash == node.ch_fin));
+            assert!(node.view.ancestors(&node.ch_ava).any(|b| b.hash == node.ch_fin));
+        }
+    }
+
+    #[test]
+    fn vote_for_scripts_a_split_vote() {
+        let genesis = Block::genesis().hash;
+        let forks = [block("a1", &genesis, 1), block("b1", &genesis, 1)];
+        let mut observer = Node::new(0);
+        for fork in &forks {
+            observer.receive_message(Some(fork.clone()), None);
+        }
+        for id in 0..10 {
+            let mut voter = Node::new(id);
+            for fork in &forks {
+                voter.receive_message(Some(fork.clone()), None);
+            }
+            let head = if id % 2 == 0 { "a1" } else { "b1" };
+            let vote = voter.vote_for(head.to_string(), 2).unwrap();
+            assert_eq!((vote.chain_head_hash.as_str(), &vote.source), (head, &Checkpoint::genesis()));
+            observer.receive_message(None, Some(vote));
+        }
+        let weights = fork_choice::weight_tree(&observer.view, &observer.validators, &genesis, 2).unwrap();
+        assert_eq!((weights["a1"], weights["b1"]), (5, 5));
+
+        assert_eq!(observer.vote_for("missing".to_string(), 2), Err(VoteError::UnknownBlock("missing".to_string())));
+        supermajority(&mut observer, "a1", Checkpoint::genesis(), checkpoint("a1", 3));
+        assert_eq!(observer.vote_for("b1".to_string(), 3), Err(VoteError::HeadConflictsWithJustified {
+            head: "b1".to_string(),
+            justified: checkpoint("a1", 3),
+        }));
+    }
+
+    #[test]
+    fn prometheus_metrics_expose_consensus_health() {
+        let mut sim = Simulation::new(SimulationConfig { num_slots: 6, ..SimulationConfig::default() });
+        sim.run();
+        let node = &sim.nodes[3];
+        let metrics = node.prometheus_metrics();
+        for name in ["threesf_current_slot", "threesf_available_slot", "threesf_finalized_slot", "threesf_votes",
+                     "threesf_blocks", "threesf_equivocators", "threesf_cache_hits_total", "threesf_cache_misses_total"] {
+            assert!(metrics.contains(&format!("# HELP {} ", name)), "{} lacks HELP", name);
+            assert!(metrics.contains(&format!("# TYPE {} ", name)), "{} lacks TYPE", name);
+        }
+        assert!(metrics.contains("# TYPE threesf_finalized_slot gauge\n"));
+
+        // Every sample line is `name{labels} value`
+        let finalized: u64 = metrics.lines()
+            .filter(|line| !line.starts_with('#'))
+            .find_map(|line| line.strip_prefix("threesf_finalized_slot{node=\"3\"} "))
+            .expect("finalized-slot sample")
+            .parse()
+            .unwrap();
+        assert_eq!(finalized, node.view.blocks[&node.ch_fin].slot);
+        assert!(finalized > 0);
+    }
+
+    #[test]
+    fn blocks_conflicting_with_ch_fin_are_not_merged() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        node.receive_message(Some(block("a2", "a1", 2)), None);
+        supermajority(&mut node, "a2", Checkpoint::genesis(), checkpoint("a1", 1));
+        supermajority(&mut node, "a2", checkpoint("a1", 1), checkpoint("a2", 2));
+        assert_eq!(node.try_finalize(&checkpoint("a1", 1)), Ok(true));
+
+        // A stale peer syncs us its sibling of the finalized block
+        let stale = block("b1", &genesis, 1);
+        node.receive_message(Some(stale.clone()), None);
+        assert!(!node.view.blocks.contains_key("b1"));
+        assert_eq!(node.finality_conflicts(), [FinalityConflict { finalized: "a1".to_string(), rejected: stale }]);
+
+        // and proposes on genesis, below everything we finalized
+        let proposal = Node::new(node.proposer_for(1).unwrap()).propose_on(genesis, 1);
+        assert!(node.on_receive_proposal(&proposal).is_err());
+        assert!(!node.view.blocks.contains_key(&proposal.block.hash));
+        assert!(!node.frozen_view.blocks.contains_key(&proposal.block.hash));
+        assert_eq!(node.ch_fin, "a1");
+    }
+
+    #[test]
+    fn orphans_join_the_view_once_their_parent_arrives() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        // Grandchild first, then child (twice): both wait on a1
+        node.receive_message(Some(block("a3", "a2", 3)), None);
+        node.receive_message(Some(block("a2", "a1", 2)), None);
+        node.receive_message(Some(block("a2", "a1", 2)), None);
+        assert_eq!(node.orphan_count(), 2);
+        assert!(!node.view.blocks.contains_key("a2") && !node.view.blocks.contains_key("a3"));
+
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        assert_eq!(node.orphan_count(), 0);
+        assert!(["a1", "a2", "a3"].iter().all(|hash| node.view.blocks.contains_key(*hash)));
+        assert_eq!(node.view.ancestors(&"a3".to_string()).count(), 4);
+    }
+
+    #[test]
+    fn head_support_breakdown_marks_exactly_the_majority() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        node.receive_message(Some(block("b1", &genesis, 1)), None);
+        let head_vote = |id, head: &str, slot| vote(id, head, Checkpoint::genesis(), Checkpoint::genesis(), slot);
+        for id in 0..5 {
+            node.receive_message(None, Some(head_vote(id, "a1", 3)));
+        }
+        for id in 5..8 {
+            node.receive_message(None, Some(head_vote(id, "b1", 3)));
+        }
+        // 8's vote expires by slot 7; 9 votes for both forks
+        node.receive_message(None, Some(head_vote(8, "b1", 1)));
+        node.receive_message(None, Some(head_vote(9, "a1", 3)));
+        node.receive_message(None, Some(head_vote(9, "b1", 3)));
+
+        assert_eq!(node.current_head(7).unwrap(), "a1");
+        let breakdown = node.head_support_breakdown(7).unwrap();
+        let expected: HashMap<ValidatorId, bool> = (0..8).map(|id| (id, id < 5)).collect();
+        assert_eq!(breakdown, expected);
+    }
+
+    #[test]
+    fn maintained_weights_give_the_from_scratch_head() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        assert!(node.vote_weights.is_some());
+        for block in [block("a1", &genesis, 1), block("a2", "a1", 2), block("b1", &genesis, 1), block("b2", "b1", 2), block("c2", "a1", 2)] {
+            node.receive_message(Some(block), None);
+        }
+        // Validators pile onto one fork, move, equivocate and let votes expire
+        let updates = [
+            (0, "a2", 1), (1, "a2", 1), (2, "b1", 1), (3, "b2", 2), (4, "b2", 2), (5, "b2", 2),
+            (0, "c2", 3), (1, "b2", 3), (6, "a1", 3), (6, "c2", 3), (2, "a2", 4), (7, "c2", 5),
+            (8, "a2", 6), (9, "a2", 6), (3, "c2", 7),
+        ];
+        let from_scratch = |node: &mut Node, slot| {
+            let gjc = node.gjc();
+            fork_choice::rlmd_ghost_fork_choice_with_finality(&node.view, &node.validators, gjc.block_hash, &node.ch_fin, slot, None)
+        };
+        let mut heads = BTreeSet::new();
+        for (id, head, slot) in updates {
+            node.receive_message(None, Some(vote(id, head, Checkpoint::genesis(), Checkpoint::genesis(), slot)));
+            for current_slot in slot..slot + ETA + 2 {
+                let head = node.current_head(current_slot).unwrap();
+                assert_eq!(Ok(head.clone()), from_scratch(&mut node, current_slot), "after {}'s vote, in slot {}", id, current_slot);
+                heads.insert(head);
+            }
+        }
+        assert!(heads.len() > 2, "{:?}", heads);
+    }
+
+    #[test]
+    fn gjc_follows_justification_across_forks_above_ch_fin_only() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        for block in [block("a1", &genesis, 1), block("a2", "a1", 2), block("b2", "a1", 2), block("c1", &genesis, 1)] {
+            node.receive_message(Some(block), None);
+        }
+        supermajority(&mut node, "a2", Checkpoint::genesis(), checkpoint("a1", 1));
+        supermajority(&mut node, "a2", checkpoint("a1", 1), checkpoint("a2", 2));
+        assert_eq!(node.try_finalize(&checkpoint("a1", 1)), Ok(true));
+        assert_eq!(node.gjc(), checkpoint("a2", 2));
+
+        // Justification moving to a sibling fork above a1 is legitimate
+        supermajority(&mut node, "b2", checkpoint("a1", 1), checkpoint("b2", 3));
+        assert_eq!(node.gjc(), checkpoint("b2", 3));
+
+        // while a fork leaving the chain below a1 is ignored, however high
+        supermajority(&mut node, "c1", Checkpoint::genesis(), checkpoint("c1", 4));
+        let greatest = ffg::greatest_justified_checkpoint(&node.view, &node.validators, &mut JustificationCache::new(16));
+        assert_eq!(greatest, checkpoint("c1", 4));
+        assert_eq!(node.gjc(), checkpoint("b2", 3));
+        assert_eq!(node.current_head(4).unwrap(), "b2");
+    }
+
+    #[test]
+    fn preview_matches_the_proposal_that_follows() {
+        let genesis = Block::genesis().hash;
+        let proposer_id = Node::new(0).proposer_for(2).unwrap();
+        for hash_function in [None, Some(HashFunction::Sha256)] {
+            let mut node = Node::with_config(proposer_id, ProtocolConfig { hash_function, ..ProtocolConfig::default() });
+            node.receive_message(Some(block("a1", &genesis, 1)), None);
+            for id in 0..3 {
+                node.receive_message(None, Some(vote(id, "a1", Checkpoint::genesis(), Checkpoint::genesis(), 1)));
+            }
+            let blocks = node.view.blocks.len();
+
+            let preview = node.preview_proposal(2).unwrap();
+            assert_eq!(node.view.blocks.len(), blocks);
+            assert_eq!(node.preview_proposal(2).unwrap(), preview);
+            assert_eq!(preview.block.parent_hash, "a1");
+            assert_eq!(preview.votes.len(), 3);
+
+            let proposal = node.propose(2).unwrap();
+            assert_eq!(proposal, preview, "{:?}", hash_function);
+            assert!(node.view.blocks.contains_key(&proposal.block.hash));
+        }
+    }
+
+    #[test]
+    fn messages_cross_only_between_matching_fork_digests() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        let peer = Node::with_config(1, ProtocolConfig { tie_break: TieBreak::LowestHash, ..ProtocolConfig::default() });
+        let stranger = Node::with_config(2, ProtocolConfig { finality_threshold_num: 3, finality_threshold_den: 4, ..ProtocolConfig::default() });
+        // Local policy doesn't split the network; consensus parameters do
+        assert_eq!(peer.fork_digest(), node.fork_digest());
+        assert_ne!(stranger.fork_digest(), node.fork_digest());
+        assert_ne!(fork_digest(&"other_genesis".to_string(), &ProtocolConfig::default()), node.fork_digest());
+
+        node.handle_message(stranger.envelope(Message::Block(block("s1", &genesis, 1))));
+        assert!(!node.view.blocks.contains_key("s1"));
+        node.handle_message(peer.envelope(Message::Block(block("p1", &genesis, 1))));
+        assert!(node.view.blocks.contains_key("p1"));
+    }
+
+    #[test]
+    fn status_reflects_a_short_honest_run() {
+        let mut sim = Simulation::new(SimulationConfig { num_slots: 6, ..SimulationConfig::default() });
+        sim.run();
+        let node = &mut sim.nodes[4];
+        let status = node.status();
+        assert_eq!(status.slot, 6);
+        assert_eq!(status.head, Some(node.current_head(6).unwrap()));
+        assert_eq!((&status.ch_ava, &status.ch_fin), (&node.ch_ava, &node.ch_fin));
+        assert_eq!(status.finalized_slot, node.view.blocks[&node.ch_fin].slot);
+        assert!(status.finalized_slot > 0);
+        assert_eq!(status.gjc, node.gjc());
+        assert_eq!(status.justified_slot, status.gjc.slot);
+        assert!(status.justified_slot > status.finalized_slot);
+        assert_eq!((status.num_blocks, status.num_votes), (node.view.blocks.len(), node.view.votes.len()));
+        assert!(status.equivocators.is_empty());
+
+        #[cfg(feature = "scenario")]
+        {
+            let json = serde_json::to_value(&status).unwrap();
+            assert_eq!(json["ch_fin"], status.ch_fin.as_str());
+            assert_eq!(json["finalized_slot"], status.finalized_slot);
+        }
+    }
+
+    #[test]
+    fn withheld_votes_stall_finality_past_the_threshold() {
+        let genesis = Block::genesis().hash;
+        let config = ProtocolConfig { finality_stall_threshold: 3, ..ProtocolConfig::default() };
+        let mut node = Node::with_config(0, config);
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        node.receive_message(Some(block("a2", "a1", 2)), None);
+        supermajority(&mut node, "a2", Checkpoint::genesis(), checkpoint("a1", 1));
+        supermajority(&mut node, "a2", checkpoint("a1", 1), checkpoint("a2", 2));
+        node.fast_confirm(2).unwrap();
+        assert_eq!(node.ch_fin, "a1");
+
+        // Only a minority votes from here on, so a1 stays the last finalized block
+        let stalled: Vec<bool> = (2..8).map(|slot| {
+            node.receive_message(None, Some(vote(0, "a2", checkpoint("a1", 1), checkpoint("a2", slot), slot)));
+            node.fast_confirm(slot).unwrap();
+            node.finality_stalled(slot)
+        }).collect();
+        assert_eq!(node.ch_fin, "a1");
+        assert_eq!(node.slots_since_finalization(7), 6);
+        assert_eq!(stalled, [false, false, false, true, true, true]);
+    }
+
+    #[test]
+    fn node_operations_fail_with_the_matching_error() {
+        let genesis = Block::genesis().hash;
+
+        // A proposal on a block we never saw
+        let mut node = Node::new(0);
+        let mut proposer = Node::new(node.proposer_for(2).unwrap());
+        proposer.receive_message(Some(block("unseen", &genesis, 1)), None);
+        let proposal = proposer.propose_on("unseen".to_string(), 2);
+        assert_eq!(node.on_receive_proposal(&proposal),
+                   Err(NodeError::MissingBlock { hash: "unseen".to_string(), slot: 2 }));
+
+        // A vote that would double-vote against one we already cast, which
+        // leaves the head and chAva it would have moved us to unadopted
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        node.receive_message(Some(block("a2", "a1", 2)), None);
+        supermajority(&mut node, "a1", Checkpoint::genesis(), checkpoint("a1", 1));
+        let earlier = vote(0, "a2", Checkpoint::genesis(), Checkpoint { block_hash: genesis.clone(), slot: 2 }, 2);
+        node.receive_message(None, Some(earlier.clone()));
+        node.freeze_view();
+        assert_eq!(node.vote(2), Err(NodeError::InvalidVote(VoteError::Slashable { earlier: Box::new(earlier) })));
+        assert_eq!(node.ch_ava, genesis);
+        assert_eq!(node.last_head, None);
+
+        // Fork choice and the merge-time checks over a view missing genesis
+        let id = (0..10).find(|&id| Node::new(0).proposer_for(1) != Some(id)).unwrap();
+        let mut node = Node::new(id);
+        node.on_tick(1, SlotPhase::Propose).unwrap();
+        node.on_tick(1, SlotPhase::Vote).unwrap();
+        node.on_tick(1, SlotPhase::Confirm).unwrap();
+        node.view.blocks.remove(&genesis);
+        assert!(matches!(node.propose(1), Err(NodeError::ForkChoice { slot: 1, .. })));
+        let merged = node.on_tick(1, SlotPhase::Merge);
+        assert_eq!(merged, Err(NodeError::Invariant(InvariantError::MissingBlock(genesis.clone()))));
+        assert!(merged.unwrap_err().to_string().contains(&genesis));
+    }
+
+    #[test]
+    fn votes_with_out_of_order_slots_are_rejected() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        for (hash, parent, slot) in [("a1", genesis.as_str(), 1), ("a2", "a1", 2)] {
+            node.receive_message(Some(block(hash, parent, slot)), None);
+        }
+        // Target from the future of the vote itself
+        let future_target = vote(3, "a2", Checkpoint::genesis(), checkpoint("a2", 2), 1);
+        // Source justified after the target it links to
+        let backwards_link = vote(4, "a2", checkpoint("a2", 2), checkpoint("a1", 1), 2);
+        for (bad, (source_slot, target_slot, slot)) in [(future_target, (0, 2, 1)), (backwards_link, (2, 1, 2))] {
+            assert_eq!(validate_vote(&bad, &node.view, &node.validators),
+                       Err(VoteError::SlotOrder { source_slot, target_slot, slot }));
+            node.receive_message(None, Some(bad.clone()));
+            assert!(!node.view.contains_vote(&bad));
+        }
+
+        let in_order = vote(3, "a2", checkpoint("a1", 1), checkpoint("a2", 2), 2);
+        assert_eq!(validate_vote(&in_order, &node.view, &node.validators), Ok(()));
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn force_finalize_refuses_checkpoints_off_ch_fin() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(0);
+        for (hash, parent, slot) in [("a1", genesis.as_str(), 1), ("a2", "a1", 2), ("b1", genesis.as_str(), 1)] {
+            node.receive_message(Some(block(hash, parent, slot)), None);
+        }
+        assert_eq!(node.force_finalize(&checkpoint("a1", 1)), Ok(true));
+        assert_eq!(node.ch_fin, "a1");
+        assert_eq!(node.ch_ava, "a1");
+
+        assert_eq!(node.force_finalize(&checkpoint("b1", 1)), Err(FinalizeError::ConflictsWithFinalized {
+            candidate: checkpoint("b1", 1),
+            ch_fin: "a1".to_string(),
+        }));
+        assert_eq!(node.force_finalize(&Checkpoint::genesis()), Ok(false));
+        assert_eq!(node.force_finalize(&checkpoint("c1", 1)), Err(FinalizeError::UnknownBlock("c1".to_string())));
+        assert_eq!(node.ch_fin, "a1");
+
+        // With no votes at all, a later slot keeps ch_fin where it was forced
+        node.fast_confirm(2).unwrap();
+        assert_eq!(node.ch_fin, "a1");
+        assert_eq!(node.force_finalize(&checkpoint("a2", 2)), Ok(true));
+    }
+
+    #[test]
+    fn proposal_after_the_freeze_does_not_sway_this_slots_vote() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(9);
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        node.receive_message(None, Some(vote(0, "a1", Checkpoint::genesis(), Checkpoint::genesis(), 1)));
+        node.freeze_view();
+
+        let proposal = Node::new(node.proposer_for(2).unwrap()).propose_on(genesis, 2);
+        node.on_receive_proposal(&proposal).unwrap();
+        assert_eq!(node.current_head(2).unwrap(), proposal.block.hash);
+        assert!(!node.frozen_view.blocks.contains_key(&proposal.block.hash));
+        assert_eq!(node.vote(2).unwrap().unwrap().chain_head_hash, "a1");
+
+        // The next freeze picks the proposal up
+        node.freeze_view();
+        assert!(node.frozen_view.blocks.contains_key(&proposal.block.hash));
+    }
+
+    #[test]
+    fn cached_results_follow_votes_arriving_mid_slot() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(9);
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        node.receive_message(Some(block("a2", "a1", 2)), None);
+        supermajority(&mut node, "a1", Checkpoint::genesis(), checkpoint("a1", 1));
+        for id in 0..6 {
+            node.receive_message(None, Some(vote(id, "a2", checkpoint("a1", 1), checkpoint("a2", 2), 2)));
+        }
+        assert_eq!(node.is_finalized(&checkpoint("a1", 1)), Ok(false));
+        node.freeze_view();
+
+        // The quorum-completing vote lands after the freeze; the vote cast
+        // on the frozen view must not leave its answers in the live cache
+        node.receive_message(None, Some(vote(6, "a2", checkpoint("a1", 1), checkpoint("a2", 2), 2)));
+        assert_eq!(node.vote(3).unwrap().unwrap().source, checkpoint("a1", 1));
+        assert_eq!(node.is_finalized(&checkpoint("a1", 1)), Ok(true));
+        assert_eq!(node.gjc(), checkpoint("a2", 2));
+    }
+
+    #[test]
+    fn gjc_moves_as_soon_as_the_quorum_completes() {
+        let genesis = Block::genesis().hash;
+        let mut node = Node::new(9);
+        node.receive_message(Some(block("a1", &genesis, 1)), None);
+        for id in 0..6 {
+            node.receive_message(None, Some(vote(id, "a1", Checkpoint::genesis(), checkpoint("a1", 1), 1)));
+        }
+        assert_eq!(node.gjc(), Checkpoint::genesis());
+
+        // Same slot, no merge in between
+        node.receive_message(None, Some(vote(6, "a1", Checkpoint::genesis(), checkpoint("a1", 1), 1)));
+        assert_eq!(node.gjc(), checkpoint("a1", 1));
+        let mut fresh = Node::from_view(node.view.clone(), genesis).unwrap();
+        assert_eq!(fresh.gjc(), node.gjc());
+    }
+
+    #[test]
+    fn full_store_still_takes_current_votes_and_finality_frees_it() {
+        const CAP: usize = 30;
+        let mut config = SimulationConfig::default();
+        config.protocol.max_stored_votes = CAP;
+        let mut sim = Simulation::new(config);
+        for slot in 1..=3 {
+            sim.run_slot(slot);
+        }
+
+        // Unsigned but otherwise valid votes above ch_fin fill the store,
+        // so pruning can't free anything until finality moves
+        let node = &mut sim.nodes[0];
+        let (head, gjc) = (node.current_head(3).unwrap(), node.gjc());
+        for id in 0..10 {
+            for slot in 3..=6 {
+                node.receive_message(None, Some(vote(id, &head, gjc.clone(), checkpoint(&head, slot), slot)));
+            }
+        }
+        assert!(sim.nodes[0].view.votes.len() >= CAP);
+
+        for slot in 4..=10 {
+            sim.run_slot(slot);
         }
-        current_block.hash
+        let flooded = &sim.nodes[0];
+        assert_eq!(flooded.ch_fin, sim.nodes[1].ch_fin);
+        assert!(flooded.view.blocks[&flooded.ch_fin].slot >= 8);
+        assert!(flooded.view.votes.len() <= CAP + 10);
     }
 }
@@ -0,0 +1,94 @@
+//! Shareable scenario files for reproducing a simulation run exactly.
+//! A scenario is a `SimulationConfig` stored as JSON: validator count and
+//! stakes, seed (and so the proposer schedule), offline and late proposers,
+//! network layout, and protocol parameters. Enabled by the `scenario` feature.
+
+use std::fmt;
+use std::path::Path;
+use crate::simulation::{Simulation, SimulationConfig};
+
+/// A complete simulation setup. Fields missing from a file take their
+/// default, so a scenario only needs to spell out what it changes.
+pub type Scenario = SimulationConfig;
+
+/// Failure to load a scenario file.
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScenarioError::Io(e) => write!(f, "cannot read scenario: {}", e),
+            ScenarioError::Parse(e) => write!(f, "malformed scenario: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+/// Parse a scenario from JSON.
+pub fn from_json(json: &str) -> Result<Scenario, ScenarioError> {
+    serde_json::from_str(json).map_err(ScenarioError::Parse)
+}
+
+/// Render a scenario as pretty-printed JSON, listing every field.
+pub fn to_json(scenario: &Scenario) -> String {
+    serde_json::to_string_pretty(scenario).expect("scenario fields always serialize")
+}
+
+/// Read a scenario from a JSON file.
+pub fn load_scenario(path: impl AsRef<Path>) -> Result<Scenario, ScenarioError> {
+    let json = std::fs::read_to_string(path).map_err(ScenarioError::Io)?;
+    from_json(&json)
+}
+
+/// Run every slot of `scenario` and return the finished simulation, for
+/// inspecting its finalized heads, event log or nodes.
+pub fn run_scenario(scenario: Scenario) -> Simulation {
+    let mut sim = Simulation::new(scenario);
+    sim.run();
+    sim
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::AdversaryBehavior;
+
+    #[test]
+    fn scenario_round_trips_and_reproduces_its_run() {
+        let scenario = Scenario {
+            num_validators: 12,
+            num_slots: 10,
+            seed: Some(5),
+            stakes: [(0, 32), (1, 64)].into(),
+            gossip_fanout: Some(3),
+            skipped_slots: [4].into(),
+            late_proposals: [(6, [2, 3].into())].into(),
+            adversaries: [(11, AdversaryBehavior::Balancing)].into(),
+            ..Scenario::default()
+        };
+        let loaded = from_json(&to_json(&scenario)).unwrap();
+        assert_eq!(loaded, scenario);
+
+        let (first, second) = (run_scenario(scenario), run_scenario(loaded));
+        assert_eq!(first.finalized_heads(), second.finalized_heads());
+        assert_eq!(first.event_log(), second.event_log());
+        assert!(first.finalized_heads().iter().any(|head| *head != crate::types::Block::genesis().hash));
+    }
+
+    #[test]
+    fn partial_files_take_defaults_and_bad_ones_fail() {
+        let path = std::env::temp_dir().join(format!("threesf-scenario-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{ "num_slots": 3 }"#).unwrap();
+        let loaded = load_scenario(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), Scenario { num_slots: 3, ..Scenario::default() });
+
+        assert!(matches!(from_json("{ \"num_slots\": \"many\" }"), Err(ScenarioError::Parse(_))));
+        assert!(matches!(load_scenario(path), Err(ScenarioError::Io(_))));
+    }
+}
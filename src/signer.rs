@@ -0,0 +1,150 @@
+//! Pluggable signing for the messages a node produces.
+//! The node decides what to vote for or propose; a `Signer` only signs the
+//! finished contents, so keys can live in an HSM or remote signer.
+
+use std::hash::{DefaultHasher, Hash as _, Hasher};
+use crate::types::*;
+use crate::validators::ValidatorRegistry;
+
+/// Secret a `LocalSigner` signs with.
+pub type SigningKey = u64;
+
+/// Produces signatures over a message's signing root.
+/// Called once per vote or proposal the node emits.
+pub trait Signer: Send {
+    fn sign_vote(&self, vote: &Vote) -> Signature;
+    fn sign_proposal(&self, proposal: &Proposal) -> Signature;
+}
+
+/// In-process signer keyed by a secret number. The "signature" is a keyed
+/// digest of the signing root: deterministic, but not cryptographic.
+#[derive(Debug, Clone)]
+pub struct LocalSigner {
+    secret: SigningKey,
+}
+
+impl LocalSigner {
+    pub fn new(secret: SigningKey) -> Self {
+        LocalSigner { secret }
+    }
+
+    fn sign(&self, signing_root: &Hash) -> Signature {
+        let mut hasher = DefaultHasher::new();
+        (self.secret, signing_root).hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+impl Signer for LocalSigner {
+    fn sign_vote(&self, vote: &Vote) -> Signature {
+        self.sign(&vote.signing_root())
+    }
+
+    fn sign_proposal(&self, proposal: &Proposal) -> Signature {
+        self.sign(&proposal.signing_root())
+    }
+}
+
+/// Whether `vote` carries a `LocalSigner` signature under the key its
+/// validator had registered for the vote's slot. Keyed digests can only be
+/// checked by re-signing, so this needs the key itself.
+pub fn verify_vote(vote: &Vote, validators: &ValidatorRegistry) -> bool {
+    let key = validators.key_at(vote.validator_id, vote.slot);
+    LocalSigner::new(key).sign_vote(vote) == vote.signature
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::config::ProtocolConfig;
+    use crate::node::Node;
+
+    /// Signs with fixed strings, keeping a copy of everything it signed.
+    #[derive(Default, Clone)]
+    struct MockSigner {
+        votes: Arc<Mutex<Vec<Vote>>>,
+        proposals: Arc<Mutex<Vec<Proposal>>>,
+    }
+
+    impl Signer for MockSigner {
+        fn sign_vote(&self, vote: &Vote) -> Signature {
+            self.votes.lock().unwrap().push(vote.clone());
+            "mock vote signature".to_string()
+        }
+
+        fn sign_proposal(&self, proposal: &Proposal) -> Signature {
+            self.proposals.lock().unwrap().push(proposal.clone());
+            "mock proposal signature".to_string()
+        }
+    }
+
+    #[test]
+    fn node_hands_finished_contents_to_its_signer() {
+        let signer = MockSigner::default();
+        let proposer_id = Node::new(0).proposer_for(1).unwrap();
+        let mut node = Node::new(proposer_id).with_signer(signer.clone());
+
+        let Some(Message::Proposal(proposal)) = node.on_tick(1, SlotPhase::Propose).unwrap() else {
+            panic!("node should propose");
+        };
+        let Some(Message::Vote(vote)) = node.on_tick(1, SlotPhase::Vote).unwrap() else {
+            panic!("node should vote");
+        };
+        assert_eq!(proposal.signature, "mock proposal signature");
+        assert_eq!(vote.signature, "mock vote signature");
+
+        // The signer saw exactly what was sent, apart from the signature itself
+        let signed_proposals = signer.proposals.lock().unwrap();
+        assert_eq!(*signed_proposals, [Proposal { signature: Signature::new(), ..proposal.clone() }]);
+        let signed_votes = signer.votes.lock().unwrap();
+        assert_eq!(*signed_votes, [Vote { signature: Signature::new(), ..vote.clone() }]);
+        assert_eq!(signed_votes[0].signing_root(), vote.signing_root());
+        assert_eq!((vote.validator_id, vote.slot, vote.chain_head_hash.as_str()), (proposer_id, 1, proposal.block.hash.as_str()));
+    }
+
+    #[test]
+    fn local_signatures_verify_only_under_the_signing_key() {
+        let validators = ValidatorRegistry::new(0..4);
+        let genesis = Checkpoint::genesis();
+        let mut vote = Vote {
+            chain_head_hash: genesis.block_hash.clone(), source: genesis.clone(), target: genesis,
+            slot: 0, validator_id: 2, signature: Signature::new(),
+        };
+        vote.signature = LocalSigner::new(validators.key_at(2, 0)).sign_vote(&vote);
+        assert!(verify_vote(&vote, &validators));
+        assert!(!verify_vote(&Vote { validator_id: 3, ..vote.clone() }, &validators));
+        assert!(!verify_vote(&Vote { slot: 1, ..vote }, &validators));
+    }
+
+    #[test]
+    fn votes_under_a_rotated_out_key_still_verify() {
+        let mut validators = ValidatorRegistry::new(0..4);
+        validators.rotate_key(2, 77, 5);
+        validators.rotate_key(2, 88, 9);
+        let genesis = Checkpoint::genesis();
+        let signed = |key: SigningKey, slot: u64| {
+            let mut vote = Vote {
+                chain_head_hash: genesis.block_hash.clone(), source: genesis.clone(), target: genesis.clone(),
+                slot, validator_id: 2, signature: Signature::new(),
+            };
+            vote.signature = LocalSigner::new(key).sign_vote(&vote);
+            vote
+        };
+        // Each key covers exactly the slots it was active in
+        for (key, slots) in [(2, 0..5), (77, 5..9), (88, 9..12)] {
+            for slot in 0..12 {
+                assert_eq!(verify_vote(&signed(key, slot), &validators), slots.contains(&slot), "key {} slot {}", key, slot);
+            }
+        }
+
+        // A node checking signatures keeps the old vote as history
+        let config = ProtocolConfig { verify_signatures: true, ..ProtocolConfig::default() };
+        let mut node = Node::with_validators(0, config, validators);
+        let old = signed(2, 3);
+        node.receive_message(None, Some(old.clone()));
+        node.receive_message(None, Some(signed(2, 6)));
+        assert!(node.view.contains_vote(&old));
+        assert_eq!(node.view.votes.len(), 1);
+    }
+}
@@ -0,0 +1,881 @@
+//! Deterministic multi-node simulation driver.
+//! All randomness (proposer selection, gossip peer choice) flows from one
+//! seed, so the same seed always reproduces the same run.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::ops::Range;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use crate::cache::JustificationCache;
+use crate::config::{fork_digest, ProposerSelection, ProtocolConfig};
+use crate::ffg;
+use crate::fork_choice;
+use crate::gossip::{GossipNetwork, DEFAULT_RELAY_ROUNDS};
+use crate::node::{InvariantError, Node, NodeError};
+use crate::types::*;
+use crate::validators::ValidatorRegistry;
+
+/// Parameters for a simulation run. Together they pin a run down
+/// exactly, so with the `scenario` feature they double as a shareable
+/// scenario file. When deserializing, missing fields take their default.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
+pub struct SimulationConfig {
+    pub num_validators: u64,
+    pub num_slots: u64,
+    /// Seed for all randomness; `None` keeps round-robin proposers.
+    pub seed: Option<u64>,
+    /// Stake of each listed validator; the rest have stake 1. Informational
+    /// only, like all stake: quorums count validators.
+    pub stakes: BTreeMap<ValidatorId, u64>,
+    /// Relay votes over gossip with this fanout instead of all-to-all.
+    pub gossip_fanout: Option<usize>,
+    /// Split the network in two for a range of slots.
+    pub partition: Option<Partition>,
+    /// Slots whose proposer stays offline, leaving the slot empty.
+    pub skipped_slots: BTreeSet<u64>,
+    /// Per slot, nodes the proposal reaches late: only after they froze
+    /// their view and voted, so they vote for the previous head.
+    pub late_proposals: BTreeMap<u64, BTreeSet<ValidatorId>>,
+    /// Validators following a scripted attack instead of the protocol.
+    pub adversaries: BTreeMap<ValidatorId, AdversaryBehavior>,
+    pub protocol: ProtocolConfig,
+}
+
+/// Scripted misbehaviour for a simulated validator. Adversaries collude:
+/// they share what the first of them sees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AdversaryBehavior {
+    /// Balancing attack on fork choice: as proposer, fork off the parent of
+    /// the head; as voter, once the slot's honest votes are in, vote for
+    /// whichever of the two heaviest forks is lighter, keeping them level.
+    Balancing,
+}
+
+/// A network split: `group` and everyone else can't reach each other
+/// during `slots`. When it heals, nodes sync everything they missed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Partition {
+    pub group: BTreeSet<ValidatorId>,
+    pub slots: Range<u64>,
+}
+
+impl Partition {
+    /// Whether `a` and `b` can exchange messages in `slot`.
+    pub fn connected(&self, a: ValidatorId, b: ValidatorId, slot: u64) -> bool {
+        !self.slots.contains(&slot) || self.group.contains(&a) == self.group.contains(&b)
+    }
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        SimulationConfig {
+            num_validators: crate::constants::NUM_VALIDATORS,
+            num_slots: 8,
+            seed: None,
+            stakes: BTreeMap::new(),
+            gossip_fanout: None,
+            partition: None,
+            skipped_slots: BTreeSet::new(),
+            late_proposals: BTreeMap::new(),
+            adversaries: BTreeMap::new(),
+            protocol: ProtocolConfig::default(),
+        }
+    }
+}
+
+impl SimulationConfig {
+    /// Check that honest validators are strictly more than the finality
+    /// threshold (2/3 by default) of the validator set, as the protocol's
+    /// safety and liveness proofs assume. Validators are counted, not
+    /// weighed by stake, just as justification and fork choice count them.
+    /// Scenarios expecting a safe run should pass this before running;
+    /// adversaries past the bound can break safety by design.
+    pub fn validate_fault_assumption(&self) -> Result<(), FaultError> {
+        let adversaries = self.adversaries.keys().filter(|&&id| id < self.num_validators).count() as u64;
+        let honest = self.num_validators - adversaries;
+        let (num, den) = (self.protocol.finality_threshold_num, self.protocol.finality_threshold_den);
+        if honest * den > self.num_validators * num {
+            Ok(())
+        } else {
+            Err(FaultError { adversaries, validators: self.num_validators })
+        }
+    }
+}
+
+/// A scenario has too many adversaries for the protocol's fault
+/// assumption to hold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultError {
+    pub adversaries: u64,
+    pub validators: u64,
+}
+
+impl fmt::Display for FaultError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} of {} validators are adversaries, leaving honest validators without a supermajority",
+               self.adversaries, self.validators)
+    }
+}
+
+impl std::error::Error for FaultError {}
+
+/// What happened during one simulated slot.
+#[derive(Debug, Clone)]
+pub struct SlotReport {
+    pub slot: u64,
+    pub proposer: Option<ValidatorId>,
+    pub proposals: Vec<Proposal>,
+    pub votes: Vec<Vote>,
+    pub fast_confirmations: usize, // Nodes whose ch_ava moved in the confirm phase
+}
+
+/// A proposed block that took longer than allowed to finalize.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlowFinality {
+    pub block: Hash,
+    pub proposed_in: u64,
+    pub finalized_in: Option<u64>, // None if not finalized yet
+}
+
+impl fmt::Display for SlowFinality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.finalized_in {
+            Some(slot) => write!(f, "block {} proposed in slot {} finalized only in slot {}",
+                                 self.block, self.proposed_in, slot),
+            None => write!(f, "block {} proposed in slot {} is not finalized", self.block, self.proposed_in),
+        }
+    }
+}
+
+/// Average finality latency, in slots from proposal, of one run under 3SF
+/// and under the Casper FFG baseline, from `Simulation::compare_with_casper`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyComparison {
+    pub epoch_length: u64,
+    /// Proposed blocks each rule finalized by the end of the run.
+    pub three_sf_finalized: usize,
+    pub casper_ffg_finalized: usize,
+    /// Blocks both rules finalized; the averages are taken over these.
+    pub blocks: usize,
+    pub three_sf: f64,
+    pub casper_ffg: f64,
+}
+
+impl fmt::Display for LatencyComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "3SF finalized {} blocks, Casper FFG with {}-slot epochs {}",
+               self.three_sf_finalized, self.epoch_length, self.casper_ffg_finalized)?;
+        if self.blocks > 0 {
+            write!(f, "; over the {} both finalized, 3SF took {:.2} slots on average and Casper FFG {:.2}",
+                   self.blocks, self.three_sf, self.casper_ffg)?;
+        }
+        Ok(())
+    }
+}
+
+/// How two nodes' finalized chains relate, from `compare_finality`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinalityComparison {
+    /// One finalized chain is a prefix of the other: at worst a node is behind.
+    pub consistent: bool,
+    /// The latest block both have finalized, with its slot.
+    pub common: Option<(Hash, u64)>,
+    /// The first finalized blocks after `common` that differ, as (a's, b's).
+    /// Present exactly when the chains conflict: a safety violation.
+    pub divergence: Option<(Hash, Hash)>,
+}
+
+/// Compare the finalized chains of `a` and `b`, each read from its own view.
+pub fn compare_finality(a: &Node, b: &Node) -> FinalityComparison {
+    // Oldest first
+    let chain = |node: &Node| -> Vec<Block> {
+        let mut chain: Vec<Block> = node.view.ancestors(&node.ch_fin).cloned().collect();
+        chain.reverse();
+        chain
+    };
+    let (chain_a, chain_b) = (chain(a), chain(b));
+    let Some(start_b) = chain_a.iter().find_map(|block| chain_b.iter().position(|other| other.hash == block.hash)) else {
+        return FinalityComparison { consistent: false, common: None, divergence: None };
+    };
+    let start_a = chain_a.iter().position(|block| block.hash == chain_b[start_b].hash)
+        .expect("start block came from chain_a");
+
+    // Walk both chains forward from their first shared block
+    let shared = chain_a[start_a..].iter().zip(&chain_b[start_b..])
+        .take_while(|(x, y)| x.hash == y.hash)
+        .count();
+    let common = &chain_a[start_a + shared - 1];
+    let divergence = chain_a.get(start_a + shared).zip(chain_b.get(start_b + shared))
+        .map(|(x, y)| (x.hash.clone(), y.hash.clone()));
+    FinalityComparison {
+        consistent: divergence.is_none(),
+        common: Some((common.hash.clone(), common.slot)),
+        divergence,
+    }
+}
+
+/// Whether a set of nodes agree on their greatest justified checkpoint,
+/// from `gjc_agreement`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GjcAgreement {
+    /// The GJC every node holds, if they all hold the same one.
+    pub agreed: Option<Checkpoint>,
+    /// The nodes holding each distinct GJC; more than one entry on divergence.
+    pub holders: BTreeMap<Checkpoint, BTreeSet<ValidatorId>>,
+}
+
+/// Compare the GJC of every node in `nodes`, each read from its own view.
+/// Under an honest majority, nodes should agree once delivery settles.
+pub fn gjc_agreement(nodes: &mut [Node]) -> GjcAgreement {
+    let mut holders: BTreeMap<Checkpoint, BTreeSet<ValidatorId>> = BTreeMap::new();
+    for node in nodes.iter_mut() {
+        holders.entry(node.gjc()).or_default().insert(node.validator.id);
+    }
+    let agreed = match holders.len() {
+        1 => holders.keys().next().cloned(),
+        _ => None,
+    };
+    GjcAgreement { agreed, holders }
+}
+
+/// A set of nodes driven slot by slot from a single seeded RNG.
+pub struct Simulation {
+    pub nodes: Vec<Node>,
+    config: SimulationConfig,
+    rng: StdRng,
+    event_log: Vec<String>,
+    // Every proposed block with its slot, in proposal order
+    proposed: Vec<(u64, Hash)>,
+    // Slot by whose end some node had finalized each proposed block
+    finalized_in: HashMap<Hash, u64>,
+    last_slot: u64,
+    // Shared by every node, which all run the same genesis and config
+    fork_digest: ForkDigest,
+}
+
+impl Simulation {
+    /// Set up one node per validator, all sharing the same proposer rule.
+    pub fn new(mut config: SimulationConfig) -> Self {
+        let seed = config.seed.unwrap_or(0);
+        if let Some(seed) = config.seed {
+            config.protocol.proposer_selection = ProposerSelection::Seeded(seed);
+        }
+        let mut validators = ValidatorRegistry::new(0..config.num_validators);
+        for (&id, &stake) in &config.stakes {
+            validators.set_stake(id, stake);
+        }
+        let nodes = (0..config.num_validators)
+            .map(|id| {
+                let mut node = Node::with_validators(id, config.protocol.clone(), validators.clone());
+                if config.adversaries.contains_key(&id) {
+                    node.validator.status = ValidatorStatus::Adversary;
+                }
+                node
+            })
+            .collect();
+        Simulation {
+            fork_digest: fork_digest(&Block::genesis().hash, &config.protocol),
+            nodes,
+            config,
+            rng: StdRng::seed_from_u64(seed),
+            event_log: Vec::new(),
+            proposed: Vec::new(),
+            finalized_in: HashMap::new(),
+            last_slot: 0,
+        }
+    }
+
+    /// Drive every node through the four phases of `slot`.
+    pub fn run_slot(&mut self, slot: u64) -> SlotReport {
+        let proposer = self.nodes.first().and_then(|node| node.proposer_for(slot));
+        if self.config.partition.as_ref().is_some_and(|p| p.slots.end == slot) {
+            self.sync_all();
+            self.event_log.push(format!("slot {} partition healed", slot));
+        }
+
+        if self.config.skipped_slots.contains(&slot)
+            && let Some(node) = self.nodes.iter_mut().find(|node| Some(node.validator.id) == proposer)
+        {
+            node.withhold_proposal(slot);
+        }
+        // An adversarial proposer builds its own block instead
+        let adversary_proposer = proposer.filter(|id| {
+            self.config.adversaries.contains_key(id) && !self.config.skipped_slots.contains(&slot)
+        });
+        if let Some(node) = adversary_proposer.and_then(|id| self.node_mut(id)) {
+            node.withhold_proposal(slot);
+        }
+
+        // PROPOSE: every other reachable node receives each proposal directly,
+        // except nodes it reaches late, which get it after voting
+        let mut proposals: Vec<Proposal> = self.tick_all(slot, SlotPhase::Propose).into_iter()
+            .filter_map(|msg| match msg {
+                Message::Proposal(p) => Some(p),
+                _ => None,
+            })
+            .collect();
+        if let Some(id) = adversary_proposer {
+            proposals.extend(self.adversary_proposal(id, slot));
+        }
+        let late = self.config.late_proposals.get(&slot).cloned().unwrap_or_default();
+        self.deliver_proposals(&proposals, slot, |id| !late.contains(&id));
+
+        // VOTE: broadcast all-to-all, or relay over gossip. Gossip can't
+        // respect a partition, so partitioned slots always broadcast directly.
+        let partitioned = self.config.partition.as_ref().is_some_and(|p| p.slots.contains(&slot));
+        let votes: Vec<Vote> = self.tick_all(slot, SlotPhase::Vote).into_iter()
+            .filter_map(|msg| match msg {
+                Message::Vote(v) => Some(v),
+                _ => None,
+            })
+            .collect();
+        let votes = self.adversary_votes(slot, votes);
+        self.deliver_proposals(&proposals, slot, |id| late.contains(&id));
+        match self.config.gossip_fanout {
+            Some(fanout) if !partitioned => {
+                let mut network = GossipNetwork::new(self.nodes.len(), fanout, self.rng.random());
+                for vote in &votes {
+                    network.publish(vote.validator_id as usize, Envelope { fork_digest: self.fork_digest, message: Message::Vote(vote.clone()) });
+                }
+                network.run_until_quiet(&mut self.nodes, 4 * DEFAULT_RELAY_ROUNDS);
+            }
+            _ => {
+                for node in self.nodes.iter_mut() {
+                    for vote in &votes {
+                        if connected(&self.config.partition, vote.validator_id, node.validator.id, slot) {
+                            node.handle_message(Envelope { fork_digest: self.fork_digest, message: Message::Vote(vote.clone()) });
+                        }
+                    }
+                }
+            }
+        }
+
+        // CONFIRM; nodes buffering votes see this slot's votes from here on
+        for node in self.nodes.iter_mut() {
+            node.flush_pending();
+        }
+        let old_ch_ava: Vec<_> = self.nodes.iter().map(|node| node.ch_ava.clone()).collect();
+        self.tick_all(slot, SlotPhase::Confirm);
+        let fast_confirmations = self.nodes.iter().zip(&old_ch_ava)
+            .filter(|(node, old)| node.ch_ava != **old)
+            .count();
+
+        // MERGE
+        self.tick_all(slot, SlotPhase::Merge);
+        self.proposed.extend(proposals.iter().map(|p| (slot, p.block.hash.clone())));
+        self.record_finality(slot);
+
+        self.event_log.push(format!(
+            "slot {} proposer {:?} proposals {} votes {} fast_confirmations {}",
+            slot, proposer, proposals.len(), votes.len(), fast_confirmations
+        ));
+        for node in &self.nodes {
+            self.event_log.push(format!(
+                "slot {} node {} ch_ava {} ch_fin {}",
+                slot, node.validator.id, node.ch_ava, node.ch_fin
+            ));
+        }
+
+        SlotReport { slot, proposer, proposals, votes, fast_confirmations }
+    }
+
+    /// Run every configured slot, returning each slot's report.
+    pub fn run(&mut self) -> Vec<SlotReport> {
+        (1..=self.config.num_slots).map(|slot| self.run_slot(slot)).collect()
+    }
+
+    /// One line per slot summary and per node state, in run order.
+    pub fn event_log(&self) -> &[String] {
+        &self.event_log
+    }
+
+    /// Proposed blocks not finalized by any node within `max_delay` slots
+    /// of their proposal. Blocks proposed too recently to judge are skipped.
+    /// With honest validators and instant delivery, `slow_finality(3)`
+    /// should always be empty: that is 3-slot finality.
+    pub fn slow_finality(&self, max_delay: u64) -> Vec<SlowFinality> {
+        self.proposed.iter()
+            .filter_map(|(slot, block)| {
+                let finalized_in = self.finalized_in.get(block).copied();
+                let on_time = match finalized_in {
+                    Some(finalized) => finalized <= slot + max_delay,
+                    None => self.last_slot <= slot + max_delay,
+                };
+                (!on_time).then(|| SlowFinality { block: block.clone(), proposed_in: *slot, finalized_in })
+            })
+            .collect()
+    }
+
+    /// Replay the run's chain under plain Casper FFG with epochs of
+    /// `epoch_length` slots, returning the slot each block finalized in.
+    ///
+    /// Takes node 0's canonical chain as given and has every validator
+    /// attest once per epoch, in the slot matching its id modulo the epoch
+    /// length. An attestation's FFG target is the current epoch's boundary
+    /// checkpoint, and its source the latest justified one. Justification
+    /// is decided by `ffg::is_justified` over the attestations so far; an
+    /// epoch's checkpoint finalizes once it is justified and linked to the
+    /// next epoch's, as in the two-epoch rule. Attestations are idealized:
+    /// each sees its slot's block at once and counts the same slot, so with
+    /// one-slot epochs the baseline beats 3SF; real epochs are far longer.
+    pub fn casper_ffg_finality(&mut self, epoch_length: u64) -> HashMap<Hash, u64> {
+        let epoch_length = epoch_length.max(1);
+        let node = &mut self.nodes[0];
+        let head = node.current_head(self.last_slot).expect("fork choice over our own view must succeed");
+        let chain: Vec<Block> = node.view.ancestors(&head).cloned().collect(); // Newest first
+        let validators = node.validators().clone();
+        let mut view = View {
+            blocks: chain.iter().map(|block| (block.hash.clone(), block.clone())).collect(),
+            votes: Vec::new(),
+            anchor: node.view.anchor.clone(),
+            pinned_justified: node.view.pinned_justified.clone(),
+            pinned_finalized: node.view.pinned_finalized.clone(),
+            inclusion_slots: HashMap::new(),
+        };
+        let block_at = |slot: u64| chain.iter().find(|block| block.slot <= slot).map_or_else(|| head.clone(), |block| block.hash.clone());
+        let boundary = |epoch: u64| Checkpoint { block_hash: block_at(epoch * epoch_length), slot: epoch * epoch_length };
+
+        let mut cache = JustificationCache::new(node.config().justification_cache_capacity);
+        let (mut justified_epoch, mut finalized_epoch) = (0, 0);
+        let mut finalized_in = HashMap::new();
+        for slot in 1..=self.last_slot {
+            let epoch = slot / epoch_length;
+            if epoch > 0 {
+                let committee = validators.active_at(slot).into_iter().filter(|id| id % epoch_length == slot % epoch_length);
+                for validator_id in committee {
+                    view.insert_vote(Vote {
+                        chain_head_hash: block_at(slot),
+                        source: boundary(justified_epoch),
+                        target: boundary(epoch),
+                        slot,
+                        validator_id,
+                        signature: Signature::new(),
+                    });
+                }
+            }
+
+            cache.clear();
+            if let Some(e) = (justified_epoch + 1..=epoch).rev()
+                .find(|&e| ffg::is_justified(&boundary(e), &view, &validators, &mut cache) == Ok(true))
+            {
+                justified_epoch = e;
+            }
+            let links = ffg::LinkIndex::from_view(&view, &validators);
+            let Some(e) = (finalized_epoch + 1..justified_epoch).rev()
+                .find(|&e| links.has_link(&boundary(e), &boundary(e + 1)))
+            else {
+                continue;
+            };
+            finalized_epoch = e;
+            for block in view.ancestors(&boundary(e).block_hash) {
+                finalized_in.entry(block.hash.clone()).or_insert(slot);
+            }
+        }
+        finalized_in
+    }
+
+    /// Compare this run's finality latency against the Casper FFG baseline
+    /// of `casper_ffg_finality`, over blocks proposed in the run that both
+    /// finalized.
+    pub fn compare_with_casper(&mut self, epoch_length: u64) -> LatencyComparison {
+        let casper = self.casper_ffg_finality(epoch_length);
+        let latencies: Vec<(u64, u64)> = self.proposed.iter()
+            .filter_map(|(slot, block)| {
+                let three_sf = self.finalized_in.get(block)?;
+                let casper_ffg = casper.get(block)?;
+                Some((three_sf - slot, casper_ffg - slot))
+            })
+            .collect();
+        let average = |total: u64| total as f64 / latencies.len().max(1) as f64;
+        LatencyComparison {
+            epoch_length: epoch_length.max(1),
+            three_sf_finalized: self.proposed.iter().filter(|(_, block)| self.finalized_in.contains_key(block)).count(),
+            casper_ffg_finalized: self.proposed.iter().filter(|(_, block)| casper.contains_key(block)).count(),
+            blocks: latencies.len(),
+            three_sf: average(latencies.iter().map(|&(three_sf, _)| three_sf).sum()),
+            casper_ffg: average(latencies.iter().map(|&(_, casper_ffg)| casper_ffg).sum()),
+        }
+    }
+
+    /// Note which proposed blocks some node has finalized by the end of `slot`.
+    fn record_finality(&mut self, slot: u64) {
+        self.last_slot = slot;
+        for (_, block) in &self.proposed {
+            if !self.finalized_in.contains_key(block)
+                && self.nodes.iter().any(|node| node.view.ancestors(&node.ch_fin).any(|b| b.hash == *block))
+            {
+                self.finalized_in.insert(block.clone(), slot);
+            }
+        }
+    }
+
+    /// Each node's finalized head, indexed by node.
+    pub fn finalized_heads(&self) -> Vec<Hash> {
+        self.nodes.iter().map(|node| node.ch_fin.clone()).collect()
+    }
+
+    /// A pair of finalized heads held by different nodes where neither
+    /// extends the other, i.e. a safety violation.
+    pub fn conflicting_finalization(&self) -> Option<(Hash, Hash)> {
+        for a in &self.nodes {
+            for b in &self.nodes {
+                if !compare_finality(a, b).consistent {
+                    return Some((a.ch_fin.clone(), b.ch_fin.clone()));
+                }
+            }
+        }
+        None
+    }
+
+    /// Deliver every block and vote any node holds to every other node,
+    /// as nodes would after reconnecting.
+    fn sync_all(&mut self) {
+        let mut blocks: Vec<Block> = self.nodes.iter()
+            .flat_map(|node| node.view.blocks.values().cloned())
+            .collect();
+        blocks.sort_by(|a, b| a.slot.cmp(&b.slot).then_with(|| a.hash.cmp(&b.hash)));
+        blocks.dedup_by(|a, b| a.hash == b.hash);
+        let mut votes: Vec<Vote> = self.nodes.iter()
+            .flat_map(|node| node.view.votes.iter().cloned())
+            .collect();
+        votes.sort_by_key(|vote| (vote.slot, vote.validator_id));
+        votes.dedup();
+
+        for node in self.nodes.iter_mut() {
+            for block in &blocks {
+                if !node.view.blocks.contains_key(&block.hash) {
+                    node.handle_message(Envelope { fork_digest: self.fork_digest, message: Message::Block(block.clone()) });
+                }
+            }
+            for vote in &votes {
+                if !node.view.votes.contains(vote) {
+                    node.handle_message(Envelope { fork_digest: self.fork_digest, message: Message::Vote(vote.clone()) });
+                }
+            }
+        }
+    }
+
+    /// Hand each proposal to every other reachable node `recipient` accepts.
+    fn deliver_proposals(&mut self, proposals: &[Proposal], slot: u64, recipient: impl Fn(ValidatorId) -> bool) {
+        for proposal in proposals {
+            for node in self.nodes.iter_mut() {
+                if node.validator.id != proposal.proposer_id
+                    && recipient(node.validator.id)
+                    && connected(&self.config.partition, proposal.proposer_id, node.validator.id, slot)
+                {
+                    node.handle_message(Envelope { fork_digest: self.fork_digest, message: Message::Proposal(proposal.clone()) });
+                }
+            }
+        }
+    }
+
+    fn node_mut(&mut self, id: ValidatorId) -> Option<&mut Node> {
+        self.nodes.iter_mut().find(|node| node.validator.id == id)
+    }
+
+    /// The block adversary `id` proposes in `slot`. A balancing adversary
+    /// forks off the parent of its head, where that still extends the GJC.
+    fn adversary_proposal(&mut self, id: ValidatorId, slot: u64) -> Option<Proposal> {
+        let node = self.node_mut(id)?;
+        let head = node.current_head(slot).ok()?;
+        let gjc = node.gjc();
+        let parent = node.view.blocks.get(&head)
+            .map(|block| block.parent_hash.clone())
+            .filter(|parent| node.view.ancestors(parent).any(|b| b.hash == gjc.block_hash))
+            .unwrap_or(head);
+        Some(node.propose_on(parent, slot))
+    }
+
+    /// Replace the adversaries' votes in this slot's `votes`. Balancing
+    /// adversaries, having seen the honest votes, each vote for the lighter
+    /// of the two heaviest forks; with no fork to balance they vote honestly.
+    fn adversary_votes(&mut self, slot: u64, votes: Vec<Vote>) -> Vec<Vote> {
+        let adversaries: BTreeSet<ValidatorId> = self.config.adversaries.keys().copied().collect();
+        let (mut honest, adversarial): (Vec<Vote>, Vec<Vote>) = votes.into_iter()
+            .partition(|vote| !adversaries.contains(&vote.validator_id));
+        let Some(mut forks) = adversaries.first().and_then(|&id| self.heaviest_forks(id, slot, &honest, &adversaries)) else {
+            honest.extend(adversarial);
+            return honest;
+        };
+        for vote in adversarial {
+            forks.sort();
+            let (weight, tip) = &mut forks[0];
+            let node = self.node_mut(vote.validator_id).expect("votes come from simulated nodes");
+            match node.vote_for(tip.clone(), slot) {
+                Ok(balancing) => {
+                    *weight += 1;
+                    honest.push(balancing);
+                }
+                Err(_) => honest.push(vote),
+            }
+        }
+        honest
+    }
+
+    /// The two heaviest forks above `observer`'s GJC, as (weight, tip)
+    /// pairs, counting `honest` votes and no earlier adversary votes.
+    /// `None` if the chain above the GJC doesn't fork.
+    fn heaviest_forks(
+        &mut self,
+        observer: ValidatorId,
+        slot: u64,
+        honest: &[Vote],
+        adversaries: &BTreeSet<ValidatorId>,
+    ) -> Option<[(u64, Hash); 2]> {
+        let node = self.node_mut(observer)?;
+        let gjc = node.gjc();
+        let mut view = node.view.clone();
+        view.votes.retain(|vote| !adversaries.contains(&vote.validator_id));
+        for vote in honest {
+            view.insert_vote(vote.clone());
+        }
+        let weights = fork_choice::weight_tree(&view, node.validators(), &gjc.block_hash, slot).ok()?;
+        let weight = |hash: &Hash| weights.get(hash).copied().unwrap_or(0);
+
+        // Walk up the single chain above the GJC to the first fork
+        let mut current = gjc.block_hash;
+        loop {
+            let mut children: Vec<&Hash> = view.blocks.values()
+                .filter(|b| b.parent_hash == current)
+                .map(|b| &b.hash)
+                .collect();
+            children.sort_by_key(|hash| std::cmp::Reverse((weight(hash), *hash)));
+            match children[..] {
+                [] => return None,
+                [only] => current = only.clone(),
+                [first, second, ..] => {
+                    let tip = |root: &Hash| fork_choice::rlmd_ghost_fork_choice(&view, node.validators(), root.clone(), slot).ok();
+                    return Some([(weight(first), tip(first)?), (weight(second), tip(second)?)]);
+                }
+            }
+        }
+    }
+
+    /// Drive every node through one phase, collecting what they broadcast.
+    fn tick_all(&mut self, slot: u64, phase: SlotPhase) -> Vec<Message> {
+        let mut messages = Vec::new();
+        for node in &mut self.nodes {
+            match node.on_tick(slot, phase) {
+                Ok(msg) => messages.extend(msg),
+                Err(NodeError::Phase(e)) => panic!("simulation drives phases in order: {}", e),
+                Err(e @ (NodeError::FinalityConflict(_) | NodeError::Invariant(InvariantError::NotFinalized(_)))) =>
+                    eprintln!("Node {} SAFETY VIOLATION: {}", node.validator.id, e),
+                Err(e) => eprintln!("Node {}: {}", node.validator.id, e),
+            }
+        }
+        messages
+    }
+}
+
+fn connected(partition: &Option<Partition>, a: ValidatorId, b: ValidatorId, slot: u64) -> bool {
+    partition.as_ref().is_none_or(|p| p.connected(a, b, slot))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_adversaries(num_validators: u64, adversaries: u64) -> SimulationConfig {
+        SimulationConfig {
+            num_validators,
+            adversaries: (0..adversaries).map(|id| (id, AdversaryBehavior::Balancing)).collect(),
+            ..SimulationConfig::default()
+        }
+    }
+
+    #[test]
+    fn fault_assumption_needs_honest_supermajority() {
+        assert_eq!(with_adversaries(100, 33).validate_fault_assumption(), Ok(()));
+        assert_eq!(with_adversaries(100, 34).validate_fault_assumption(),
+                   Err(FaultError { adversaries: 34, validators: 100 }));
+    }
+
+    #[test]
+    fn fault_assumption_counts_validators_not_stake() {
+        // Quorums count heads, so adversary stake doesn't change the verdict
+        let mut config = with_adversaries(100, 33);
+        config.stakes = (0..33).map(|id| (id, 10)).collect();
+        assert_eq!(config.validate_fault_assumption(), Ok(()));
+    }
+
+    #[test]
+    fn proposals_stay_bounded_as_the_chain_grows() {
+        let config = SimulationConfig::default();
+        let validators = config.num_validators as usize;
+        let mut sim = Simulation::new(config);
+        for slot in 1..=40 {
+            let report = sim.run_slot(slot);
+            // Only votes since the previous proposal ride along, never the whole view
+            for proposal in &report.proposals {
+                assert!(proposal.votes.len() <= validators,
+                        "slot {} proposal carried {} votes", slot, proposal.votes.len());
+            }
+        }
+        // Finality keeps pace: the chain is finalized up to two slots back
+        let node = &mut sim.nodes[0];
+        let finalized = node.greatest_finalized_checkpoint();
+        assert_eq!(finalized.block_hash, node.ch_fin);
+        assert_eq!(node.view.blocks[&node.ch_fin].slot, 38);
+    }
+
+    /// Event log, per-node ch_fin and proposers of a 12-slot gossip run.
+    fn seeded_outcome(seed: u64) -> (Vec<String>, Vec<Hash>, Vec<Option<ValidatorId>>) {
+        let mut sim = Simulation::new(SimulationConfig {
+            num_slots: 12,
+            seed: Some(seed),
+            gossip_fanout: Some(2),
+            ..SimulationConfig::default()
+        });
+        let proposers = sim.run().iter().map(|report| report.proposer).collect();
+        let ch_fins = sim.nodes.iter().map(|node| node.ch_fin.clone()).collect();
+        (sim.event_log().to_vec(), ch_fins, proposers)
+    }
+
+    #[test]
+    fn same_seed_reproduces_the_run() {
+        let (log, ch_fins, proposers) = seeded_outcome(11);
+        assert_eq!(seeded_outcome(11), (log.clone(), ch_fins.clone(), proposers.clone()));
+        assert!(ch_fins.iter().all(|ch_fin| *ch_fin != Block::genesis().hash));
+        // The seed, not a fixed schedule, picks proposers
+        assert_ne!(seeded_outcome(12).2, proposers);
+    }
+
+    #[test]
+    fn finality_proceeds_past_a_skipped_slot() {
+        let mut sim = Simulation::new(SimulationConfig { num_slots: 8, skipped_slots: [3].into(), ..SimulationConfig::default() });
+        let reports = sim.run();
+        // Nobody proposes in slot 3, so everyone votes for the slot-2 block again
+        assert!(reports[2].proposals.is_empty());
+        assert_eq!(reports[2].votes.len(), 10);
+        assert!(reports[2].votes.iter().all(|vote| vote.chain_head_hash == reports[1].proposals[0].block.hash));
+
+        assert!(sim.slow_finality(3).is_empty());
+        let node = &mut sim.nodes[0];
+        assert_eq!(node.view.blocks[&node.ch_fin].slot, 6);
+        assert!(node.view.blocks.values().all(|block| block.slot != 3));
+    }
+
+    #[test]
+    fn late_block_splits_the_vote_then_heads_converge() {
+        // Slot 3's proposer, validator 2, always has its own block
+        let late: BTreeSet<ValidatorId> = (5..9).collect();
+        let mut sim = Simulation::new(SimulationConfig {
+            num_slots: 8,
+            late_proposals: [(3, late.clone())].into(),
+            ..SimulationConfig::default()
+        });
+        let reports = sim.run();
+        // Nodes that froze before the slot-3 block arrived vote for its parent
+        let (previous, late_block) = (&reports[1].proposals[0].block.hash, &reports[2].proposals[0].block.hash);
+        for vote in &reports[2].votes {
+            let expected = if late.contains(&vote.validator_id) { previous } else { late_block };
+            assert_eq!(&vote.chain_head_hash, expected, "validator {}", vote.validator_id);
+        }
+
+        // Once everyone has the block they agree on the head and finalize it
+        let heads: BTreeSet<Hash> = sim.nodes.iter_mut().map(|node| node.current_head(8).unwrap()).collect();
+        assert_eq!(heads.len(), 1);
+        assert!(sim.nodes.iter().all(|node| node.view.ancestors(&node.ch_fin).any(|block| &block.hash == late_block)));
+    }
+
+    /// Node 0 holding `chain` on top of genesis, one block per slot, with
+    /// every block but the last finalized by 7-of-10 links between them.
+    fn node_finalizing(chain: &[&str]) -> Node {
+        let mut node = Node::new(0);
+        let mut source = Checkpoint::genesis();
+        for (slot, hash) in (1..).zip(chain) {
+            let block = Block { hash: hash.to_string(), parent_hash: source.block_hash.clone(), slot, proposer_id: 0, transactions: vec![] };
+            node.receive_message(Some(block), None);
+            let target = Checkpoint { block_hash: hash.to_string(), slot };
+            for validator_id in 0..7 {
+                let vote = Vote {
+                    chain_head_hash: hash.to_string(), source: source.clone(), target: target.clone(),
+                    slot, validator_id, signature: Signature::new(),
+                };
+                node.receive_message(None, Some(vote));
+            }
+            node.try_finalize(&source).unwrap();
+            source = target;
+        }
+        node
+    }
+
+    #[test]
+    fn node_behind_is_a_consistent_prefix() {
+        let ahead = node_finalizing(&["a1", "a2", "a3"]);
+        let behind = node_finalizing(&["a1", "a2"]);
+        assert_eq!((ahead.ch_fin.as_str(), behind.ch_fin.as_str()), ("a2", "a1"));
+        let comparison = compare_finality(&ahead, &behind);
+        assert_eq!(comparison, FinalityComparison { consistent: true, common: Some(("a1".to_string(), 1)), divergence: None });
+        assert_eq!(compare_finality(&behind, &ahead), comparison);
+    }
+
+    #[test]
+    fn conflicting_finalized_blocks_are_a_divergence() {
+        let a = node_finalizing(&["a1", "a2", "a3"]);
+        let b = node_finalizing(&["a1", "b2", "b3"]);
+        assert_eq!(compare_finality(&a, &b), FinalityComparison {
+            consistent: false,
+            common: Some(("a1".to_string(), 1)),
+            divergence: Some(("a2".to_string(), "b2".to_string())),
+        });
+    }
+
+    #[test]
+    fn finality_survives_a_balancing_minority() {
+        let config = SimulationConfig { num_slots: 20, ..with_adversaries(10, 3) };
+        assert_eq!(config.validate_fault_assumption(), Ok(()));
+        let mut sim = Simulation::new(config);
+        sim.run();
+        // The adversaries' proposals fork off blocks that end up orphaned
+        let node = &mut sim.nodes[9];
+        let head = node.current_head(20).unwrap();
+        let canonical: BTreeSet<Hash> = node.view.ancestors(&head).map(|b| b.hash.clone()).collect();
+        let forked: Vec<&Block> = node.view.blocks.values().filter(|b| !canonical.contains(&b.hash)).collect();
+        assert!(!forked.is_empty());
+        assert!(forked.iter().all(|b| b.proposer_id < 3), "{:?}", forked);
+
+        // Finality stalls around those slots but is back to full pace by the end
+        assert_eq!(sim.conflicting_finalization(), None);
+        for node in sim.nodes.iter().filter(|node| node.validator.id >= 3) {
+            let finalized_slot = node.view.blocks[&node.ch_fin].slot;
+            assert!(finalized_slot >= 18, "node {} finalized only up to slot {}", node.validator.id, finalized_slot);
+        }
+    }
+
+    #[test]
+    fn cut_off_node_disagrees_on_the_gjc_until_it_catches_up() {
+        let mut sim = Simulation::new(SimulationConfig {
+            partition: Some(Partition { group: [9].into(), slots: 4..6 }),
+            ..SimulationConfig::default()
+        });
+        for slot in 1..=3 {
+            sim.run_slot(slot);
+        }
+        let settled = gjc_agreement(&mut sim.nodes);
+        assert!(settled.agreed.is_some(), "{:?}", settled.holders);
+
+        // Node 9 misses slots 4 and 5, so the others justify past it
+        for slot in 4..=5 {
+            sim.run_slot(slot);
+        }
+        let split = gjc_agreement(&mut sim.nodes);
+        assert_eq!(split.agreed, None);
+        assert_eq!(split.holders.len(), 2);
+        assert!(split.holders.values().any(|holders| *holders == BTreeSet::from([9])));
+
+        // Once it syncs and sees a slot's votes, everyone agrees again
+        for slot in 6..=7 {
+            sim.run_slot(slot);
+        }
+        let healed = gjc_agreement(&mut sim.nodes);
+        assert_eq!(healed.holders.len(), 1);
+        assert!(healed.agreed.unwrap().slot > settled.agreed.unwrap().slot);
+    }
+}
@@ -1,23 +1,41 @@
 //! Core data structures for the 3SF protocol.
 //! Blocks, checkpoints, votes, and other fundamental types.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash as _, Hasher};
+use crate::hashing::{Hasher as _, Sha256Hasher};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 // Type shortcuts
 pub type Hash = String;
 pub type ValidatorId = u64;
+pub type Signature = String;
+/// Digest of a network's genesis and consensus parameters; see `config::fork_digest`.
+pub type ForkDigest = [u8; 4];
+
+/// Shortened hash for logs: the first 12 characters, then `…`.
+pub fn short_hash(hash: &str) -> String {
+    match hash.char_indices().nth(12) {
+        Some((cut, _)) => format!("{}…", &hash[..cut]),
+        None => hash.to_string(),
+    }
+}
 
 // Main data structures
 
 /// Transaction placeholder for this simulation.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Transaction {
     pub id: u64,
 }
 
 /// A blockchain block identified by its hash.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Block {
     pub hash: Hash,
     pub parent_hash: Hash,
@@ -38,34 +56,95 @@ impl Block {
         }
     }
 
+    /// Hash of the block's contents (everything but `hash` itself) under
+    /// `hasher`. Committing to the parent hash chains the hashes together.
+    pub fn compute_hash(&self, hasher: &dyn crate::hashing::Hasher) -> Hash {
+        hasher.hash(&self.content_bytes())
+    }
+
+    /// Hash of the block's contents followed by `nonce`, telling apart
+    /// blocks whose contents coincide, such as two empty blocks one
+    /// proposer builds on the same parent in the same slot.
+    pub fn compute_hash_with_nonce(&self, hasher: &dyn crate::hashing::Hasher, nonce: u64) -> Hash {
+        let mut bytes = self.content_bytes();
+        bytes.extend(nonce.to_le_bytes());
+        hasher.hash(&bytes)
+    }
+
+    fn content_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend((self.parent_hash.len() as u64).to_le_bytes());
+        bytes.extend(self.parent_hash.as_bytes());
+        bytes.extend(self.slot.to_le_bytes());
+        bytes.extend(self.proposer_id.to_le_bytes());
+        bytes.extend((self.transactions.len() as u64).to_le_bytes());
+        for transaction in &self.transactions {
+            bytes.extend(transaction.id.to_le_bytes());
+        }
+        bytes
+    }
+
     /// Check if this block is an ancestor of another block.
-    /// Walks the chain backwards through the view.
+    /// Walks the chain backwards through the view. If the walk hits a block
+    /// missing from the view, ancestry can't be established and this
+    /// conservatively answers false; use `try_is_ancestor_of` to tell the
+    /// two apart.
     pub fn is_ancestor_of(&self, other: &Block, view: &View) -> bool {
+        self.try_is_ancestor_of(other, view).unwrap_or(false)
+    }
+
+    /// Fallible ancestry check for partial views.
+    /// Returns `Err(missing_hash)` if the walk hits a block not in the view.
+    pub fn try_is_ancestor_of(&self, other: &Block, view: &View) -> Result<bool, Hash> {
         let mut current_hash = other.parent_hash.clone();
         while current_hash != "null" {
             if current_hash == self.hash {
-                return true;
+                return Ok(true);
             }
-            let parent_block = view.blocks.get(&current_hash)
-                .expect("Parent block must be in view for ancestry check");
+            let parent_block = view.blocks.get(&current_hash).ok_or(current_hash)?;
             current_hash = parent_block.parent_hash.clone();
         }
-        false
+        Ok(false)
+    }
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (slot {}, proposer {})", short_hash(&self.hash), self.slot, self.proposer_id)
     }
 }
 
 /// A checkpoint: (block_hash, slot) pair.
 /// See Section 3 for details.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Checkpoint {
     pub block_hash: Hash,
     pub slot: u64,
 }
 
+impl Checkpoint {
+    /// The genesis checkpoint, justified and finalized by definition.
+    pub fn genesis() -> Self {
+        Checkpoint { block_hash: Block::genesis().hash, slot: 0 }
+    }
+
+    pub fn is_genesis(&self) -> bool {
+        *self == Self::genesis()
+    }
+}
+
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, slot {})", short_hash(&self.block_hash), self.slot)
+    }
+}
+
 impl Ord for Checkpoint {
-    /// Checkpoint ordering by slot number (Section 4).
+    /// Checkpoint ordering by slot number (Section 4), with the block hash
+    /// breaking ties so the order is total and consistent with `Eq`.
     fn cmp(&self, other: &Self) -> Ordering {
-        self.slot.cmp(&other.slot)
+        self.slot.cmp(&other.slot).then_with(|| self.block_hash.cmp(&other.block_hash))
     }
 }
 
@@ -77,31 +156,436 @@ impl PartialOrd for Checkpoint {
 
 /// A validator's vote message for a slot.
 /// Covers both head votes and FFG votes (Section 3 & 6).
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Vote {
     pub chain_head_hash: Hash,
     pub source: Checkpoint, // FFG vote source
     pub target: Checkpoint, // FFG vote target
     pub slot: u64,
     pub validator_id: ValidatorId,
+    pub signature: Signature, // Voter's signature over the other fields
+}
+
+impl Vote {
+    /// Digest of everything the signature covers: all fields but the signature.
+    pub fn signing_root(&self) -> Hash {
+        let mut hasher = DefaultHasher::new();
+        (&self.chain_head_hash, &self.source, &self.target, self.slot, self.validator_id).hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+
+    /// Whether this FFG vote surrounds `other`: its source is strictly older
+    /// and its target strictly newer. Equal slots on either end don't count.
+    /// A validator casting two votes where one surrounds the other is slashable.
+    pub fn surrounds(&self, other: &Vote) -> bool {
+        self.source.slot < other.source.slot && other.target.slot < self.target.slot
+    }
+
+    /// Whether the same validator could cast both votes without being
+    /// slashable: false for a double vote (same target slot, different
+    /// target) or a surround pair, in either direction. Votes from
+    /// different validators never conflict.
+    pub fn is_consistent_with(&self, other: &Vote) -> bool {
+        if self.validator_id != other.validator_id {
+            return true;
+        }
+        let double_vote = self.target.slot == other.target.slot && self.target != other.target;
+        !double_vote && !self.surrounds(other) && !other.surrounds(self)
+    }
+}
+
+impl fmt::Display for Vote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "validator {} slot {}: head {}, FFG {} -> {}",
+               self.validator_id, self.slot, short_hash(&self.chain_head_hash), self.source, self.target)
+    }
+}
+
+/// Votes from several validators that agree on head, FFG source and target,
+/// and slot, sent as one message. Each signer keeps its own signature, so the
+/// individual votes can be recovered and checked one by one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AggregatedVote {
+    pub chain_head_hash: Hash,
+    pub source: Checkpoint,
+    pub target: Checkpoint,
+    pub slot: u64,
+    pub signers: Vec<(ValidatorId, Signature)>,
+}
+
+impl AggregatedVote {
+    /// Aggregate `votes`, or `None` if there are none or they disagree on
+    /// anything but the voter.
+    pub fn from_votes(votes: &[Vote]) -> Option<Self> {
+        let first = votes.first()?;
+        let agrees = |v: &Vote| v.chain_head_hash == first.chain_head_hash
+            && v.source == first.source && v.target == first.target && v.slot == first.slot;
+        if !votes.iter().all(agrees) {
+            return None;
+        }
+        Some(AggregatedVote {
+            chain_head_hash: first.chain_head_hash.clone(),
+            source: first.source.clone(),
+            target: first.target.clone(),
+            slot: first.slot,
+            signers: votes.iter().map(|v| (v.validator_id, v.signature.clone())).collect(),
+        })
+    }
+
+    /// The individual votes this aggregate stands for.
+    pub fn votes(&self) -> impl Iterator<Item = Vote> + '_ {
+        self.signers.iter().map(|(validator_id, signature)| Vote {
+            chain_head_hash: self.chain_head_hash.clone(),
+            source: self.source.clone(),
+            target: self.target.clone(),
+            slot: self.slot,
+            validator_id: *validator_id,
+            signature: signature.clone(),
+        })
+    }
 }
 
 /// Block proposal from a slot's designated proposer.
 /// From Section 6, Algorithm 7, line 16.
-#[derive(Debug, Clone)]
+///
+/// Rather than the proposer's whole view, only the new block and the votes
+/// seen since the previous proposal are shipped, so the message size stays
+/// bounded by the validator count instead of growing with chain length.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Proposal {
     pub chain_head_hash: Hash,
-    pub view: View, // Proposer's current view
+    pub block: Block,      // The newly proposed block
+    pub votes: Vec<Vote>,  // Votes received since the last proposal
     pub slot: u64,
     pub proposer_id: ValidatorId,
+    pub signature: Signature, // Proposer's signature over the other fields
+}
+
+impl Proposal {
+    /// Digest of everything the signature covers: all fields but the signature.
+    pub fn signing_root(&self) -> Hash {
+        let mut hasher = DefaultHasher::new();
+        (&self.chain_head_hash, &self.block, self.slot, self.proposer_id).hash(&mut hasher);
+        for vote in &self.votes {
+            vote.signing_root().hash(&mut hasher);
+        }
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+/// A single message exchanged between nodes over the network.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Message {
+    Block(Block),
+    Vote(Vote),
+    Proposal(Proposal),
+    Aggregate(AggregatedVote),
+}
+
+/// A message as sent over the network, stamped with the sender's fork
+/// digest. Receivers drop envelopes from a network other than their own.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Envelope {
+    pub fork_digest: ForkDigest,
+    pub message: Message,
+}
+
+/// Sub-slot phases of Algorithm 7, in the order they run within a slot.
+///
+/// The view is frozen at the Propose → Vote boundary, after the slot's
+/// proposal has had time to arrive. Anything received after that only
+/// reaches the live view, which fast confirmation and the next slot use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SlotPhase {
+    Propose, // 0Δ: the proposer broadcasts its block and recent votes
+    Vote,    // 1Δ: freeze the view, then vote from the frozen view
+    Confirm, // 2Δ: fast-confirm and update chFin from the live view
+    Merge,   // 3Δ: end the slot, resetting per-slot state
+}
+
+/// Slashable evidence of a proposer signing two blocks for one slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProposerEquivocation {
+    pub proposer_id: ValidatorId,
+    pub slot: u64,
+    pub first_block: Hash,
+    pub second_block: Hash,
+}
+
+/// Two different block bodies received under the same hash.
+/// The first one seen is kept; the second is rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockConflict {
+    pub existing: Block,
+    pub rejected: Block,
+}
+
+/// A block received at or below our finalized slot that isn't on the
+/// finalized chain, e.g. from a stale or long-range-attacking peer.
+/// Accepting it could only revert finality, so it is rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinalityConflict {
+    pub finalized: Hash,
+    pub rejected: Block,
 }
 
 /// A validator's view of the network state.
 /// See Section 2.1.
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct View {
     pub blocks: HashMap<Hash, Block>,
     pub votes: Vec<Vote>,
+    /// Trusted weak-subjectivity checkpoint the view starts from.
+    /// `None` means the view starts from genesis.
+    pub anchor: Option<Checkpoint>,
+    /// Checkpoints at or below the anchor that were justified when the view
+    /// was pruned to it. Their votes may be gone, so they stay justified by
+    /// record; nothing below the anchor is justified by ancestry alone.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub pinned_justified: HashSet<Checkpoint>,
+    /// The pinned justified checkpoints that were also finalized.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub pinned_finalized: HashSet<Checkpoint>,
+    /// Slot each vote was first included in the view, by proposal or on
+    /// receipt. Votes without an entry count as included in their own slot.
+    /// Not serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub inclusion_slots: HashMap<Vote, u64>,
+}
+
+impl View {
+    /// Insert `vote` at its place in canonical order: by validator, then
+    /// slot, then the vote's contents. Views holding the same votes then
+    /// iterate them identically whatever order they arrived in. Returns
+    /// false, leaving the view unchanged, if the vote is already present.
+    pub fn insert_vote(&mut self, vote: Vote) -> bool {
+        let range = self.canonical_range(&vote);
+        if self.votes[range.clone()].contains(&vote) {
+            return false;
+        }
+        self.votes.insert(range.end, vote);
+        true
+    }
+
+    /// Whether `vote` is in the view, found by binary search in canonical order.
+    pub fn contains_vote(&self, vote: &Vote) -> bool {
+        self.votes[self.canonical_range(vote)].contains(vote)
+    }
+
+    /// Positions of the votes sharing `vote`'s place in canonical order.
+    fn canonical_range(&self, vote: &Vote) -> std::ops::Range<usize> {
+        let key = |v: &Vote| (v.validator_id, v.slot, v.chain_head_hash.clone(), v.source.clone(), v.target.clone());
+        let vote_key = key(vote);
+        let start = self.votes.partition_point(|v| key(v) < vote_key);
+        let end = self.votes.partition_point(|v| key(v) <= vote_key);
+        start..end
+    }
+
+    /// Note that `vote` was included in `slot`, keeping the earliest slot
+    /// if it was included before.
+    pub fn record_inclusion(&mut self, vote: &Vote, slot: u64) {
+        let included = self.inclusion_slots.entry(vote.clone()).or_insert(slot);
+        *included = (*included).min(slot);
+    }
+
+    /// Slot `vote` was first included in, or its own slot if unrecorded.
+    pub fn inclusion_slot(&self, vote: &Vote) -> u64 {
+        self.inclusion_slots.get(vote).copied().unwrap_or(vote.slot)
+    }
+
+    /// A view bootstrapped from a trusted checkpoint instead of genesis.
+    /// Only the anchor block is known; its history is taken on trust.
+    pub fn from_anchor(anchor: Checkpoint, anchor_block: Block) -> Self {
+        assert_eq!(anchor.block_hash, anchor_block.hash, "anchor checkpoint must name the anchor block");
+        let mut view = View::default();
+        view.blocks.insert(anchor_block.hash.clone(), anchor_block);
+        view.anchor = Some(anchor);
+        view
+    }
+
+    /// The checkpoint treated as justified and finalized by definition:
+    /// the trusted anchor, or genesis.
+    pub fn base_checkpoint(&self) -> Checkpoint {
+        self.anchor.clone().unwrap_or_else(Checkpoint::genesis)
+    }
+
+    /// Every block's own checkpoint (the block at its slot), sorted by slot
+    /// then hash and without duplicates: the candidates justification and
+    /// finalization are evaluated over.
+    pub fn all_checkpoints(&self) -> Vec<Checkpoint> {
+        let mut checkpoints: Vec<Checkpoint> = self.blocks.values()
+            .map(|block| Checkpoint { block_hash: block.hash.clone(), slot: block.slot })
+            .collect();
+        checkpoints.sort_by(|a, b| a.slot.cmp(&b.slot).then_with(|| a.block_hash.cmp(&b.block_hash)));
+        checkpoints.dedup();
+        checkpoints
+    }
+
+    /// Whether `checkpoint` is justified without votes: the base checkpoint,
+    /// or one pinned as justified when the view was pruned.
+    pub fn is_trusted(&self, checkpoint: &Checkpoint) -> bool {
+        *checkpoint == self.base_checkpoint() || self.pinned_justified.contains(checkpoint)
+    }
+
+    /// Whether `checkpoint` is finalized without votes: the base checkpoint,
+    /// or one pinned as finalized when the view was pruned.
+    pub fn is_trusted_finalized(&self, checkpoint: &Checkpoint) -> bool {
+        *checkpoint == self.base_checkpoint() || self.pinned_finalized.contains(checkpoint)
+    }
+
+    /// Iterate from `start` back through its ancestors to genesis.
+    /// Stops early (without panicking) if a block is missing from the view.
+    pub fn ancestors(&self, start: &Hash) -> Ancestors<'_> {
+        Ancestors { view: self, next: start.clone() }
+    }
+
+    /// Check that every block's slot is strictly greater than its parent's.
+    /// Returns the offending block hashes, sorted. Blocks whose parent isn't
+    /// in the view can't be checked and are skipped.
+    pub fn validate_slot_monotonicity(&self) -> Result<(), Vec<Hash>> {
+        let mut offending: Vec<Hash> = self.blocks.values()
+            .filter(|block| self.blocks.get(&block.parent_hash).is_some_and(|parent| block.slot <= parent.slot))
+            .map(|block| block.hash.clone())
+            .collect();
+        if offending.is_empty() {
+            return Ok(());
+        }
+        offending.sort();
+        Err(offending)
+    }
+
+    /// GraphViz DOT rendering of the block tree: one node per block labeled
+    /// with its slot and proposer, and an edge from each parent to its child.
+    /// Blocks whose parent isn't in the view are drawn dashed.
+    pub fn to_dot(&self) -> String {
+        self.to_dot_highlighting(&[])
+    }
+
+    /// Like `to_dot`, additionally coloring the chain ending at each given
+    /// head (head hash, GraphViz color). Later entries win where chains overlap.
+    pub fn to_dot_highlighting(&self, chains: &[(&Hash, &str)]) -> String {
+        let mut colors: HashMap<&Hash, &str> = HashMap::new();
+        for (head, color) in chains {
+            for block in self.ancestors(head) {
+                colors.insert(&block.hash, color);
+            }
+        }
+
+        let mut blocks: Vec<&Block> = self.blocks.values().collect();
+        blocks.sort_by(|a, b| a.slot.cmp(&b.slot).then_with(|| a.hash.cmp(&b.hash)));
+
+        let mut dot = String::from("digraph blocks {\n    rankdir=LR;\n    node [shape=box];\n");
+        for block in &blocks {
+            let mut attrs = format!("label=\"{}\\nslot {}, proposer {}\"", short_hash(&block.hash), block.slot, block.proposer_id);
+            if let Some(color) = colors.get(&block.hash) {
+                attrs.push_str(&format!(", color={color}, style=bold"));
+            }
+            if !self.blocks.contains_key(&block.parent_hash) && block.parent_hash != Block::genesis().parent_hash {
+                attrs.push_str(", style=dashed");
+            }
+            dot.push_str(&format!("    \"{}\" [{}];\n", block.hash, attrs));
+        }
+        for block in &blocks {
+            if self.blocks.contains_key(&block.parent_hash) {
+                dot.push_str(&format!("    \"{}\" -> \"{}\";\n", block.parent_hash, block.hash));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Lowest common ancestor of two blocks (genesis in the worst case).
+    /// If one block is an ancestor of the other, that block is returned.
+    /// `None` if either block is unknown or their chains never meet in this view.
+    pub fn common_ancestor(&self, a: &Hash, b: &Hash) -> Option<Hash> {
+        let a_chain: HashSet<&Hash> = self.ancestors(a).map(|block| &block.hash).collect();
+        self.ancestors(b)
+            .find(|block| a_chain.contains(&block.hash))
+            .map(|block| block.hash.clone())
+    }
+
+    /// Deterministic digest of the view's contents: SHA-256 over a fixed
+    /// little-endian, length-prefixed encoding of every block (hash and
+    /// contents), every vote, and the anchor and pinned checkpoints.
+    /// Blocks and votes are sorted (and votes deduplicated) first, so two
+    /// views holding the same data hash equally regardless of insertion
+    /// order, on any platform or toolchain.
+    pub fn state_hash(&self) -> Hash {
+        fn put_hash(bytes: &mut Vec<u8>, hash: &str) {
+            bytes.extend((hash.len() as u64).to_le_bytes());
+            bytes.extend(hash.as_bytes());
+        }
+        fn put_checkpoint(bytes: &mut Vec<u8>, checkpoint: &Checkpoint) {
+            put_hash(bytes, &checkpoint.block_hash);
+            bytes.extend(checkpoint.slot.to_le_bytes());
+        }
+        fn put_checkpoints(bytes: &mut Vec<u8>, checkpoints: &HashSet<Checkpoint>) {
+            let mut checkpoints: Vec<&Checkpoint> = checkpoints.iter().collect();
+            checkpoints.sort();
+            bytes.extend((checkpoints.len() as u64).to_le_bytes());
+            for checkpoint in checkpoints {
+                put_checkpoint(bytes, checkpoint);
+            }
+        }
+
+        let mut blocks: Vec<&Block> = self.blocks.values().collect();
+        blocks.sort_by(|a, b| a.hash.cmp(&b.hash));
+        let mut votes: Vec<_> = self.votes.iter()
+            .map(|v| (v.validator_id, v.slot, &v.chain_head_hash, &v.source, &v.target))
+            .collect();
+        votes.sort();
+        votes.dedup();
+
+        let mut bytes = Vec::new();
+        bytes.extend((blocks.len() as u64).to_le_bytes());
+        for block in blocks {
+            put_hash(&mut bytes, &block.hash);
+            bytes.extend(block.content_bytes());
+        }
+        bytes.extend((votes.len() as u64).to_le_bytes());
+        for (validator_id, slot, head, source, target) in votes {
+            bytes.extend(validator_id.to_le_bytes());
+            bytes.extend(slot.to_le_bytes());
+            put_hash(&mut bytes, head);
+            put_checkpoint(&mut bytes, source);
+            put_checkpoint(&mut bytes, target);
+        }
+        match &self.anchor {
+            Some(anchor) => {
+                bytes.push(1);
+                put_checkpoint(&mut bytes, anchor);
+            }
+            None => bytes.push(0),
+        }
+        put_checkpoints(&mut bytes, &self.pinned_justified);
+        put_checkpoints(&mut bytes, &self.pinned_finalized);
+        Sha256Hasher.hash(&bytes)
+    }
+}
+
+/// Iterator over a block and its ancestors, created by [`View::ancestors`].
+pub struct Ancestors<'a> {
+    view: &'a View,
+    next: Hash,
+}
+
+impl<'a> Iterator for Ancestors<'a> {
+    type Item = &'a Block;
+
+    fn next(&mut self) -> Option<&'a Block> {
+        if self.next == "null" {
+            return None;
+        }
+        let block = self.view.blocks.get(&self.next)?;
+        self.next = block.parent_hash.clone();
+        Some(block)
+    }
 }
 
 /// Validator status options.
@@ -118,3 +602,211 @@ pub struct Validator {
     pub id: ValidatorId,
     pub status: ValidatorStatus,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(hash: &str, parent: &str, slot: u64) -> Block {
+        Block { hash: hash.to_string(), parent_hash: parent.to_string(), slot, proposer_id: 0, transactions: vec![] }
+    }
+
+    fn checkpoint(hash: &str, slot: u64) -> Checkpoint {
+        Checkpoint { block_hash: hash.to_string(), slot }
+    }
+
+    fn vote(validator_id: ValidatorId, source: Checkpoint, target: Checkpoint, slot: u64) -> Vote {
+        Vote { chain_head_hash: target.block_hash.clone(), source, target, slot, validator_id, signature: Signature::new() }
+    }
+
+    #[test]
+    fn state_hash_ignores_insertion_order_but_not_content() {
+        let genesis = Block::genesis();
+        let blocks = [genesis.clone(), block("a", &genesis.hash, 1), block("b", "a", 2)];
+        let votes = [
+            vote(0, Checkpoint::genesis(), checkpoint("a", 1), 1),
+            vote(1, Checkpoint::genesis(), checkpoint("a", 1), 1),
+            vote(0, checkpoint("a", 1), checkpoint("b", 2), 2),
+        ];
+        let mut forward = View::default();
+        let mut backward = View::default();
+        for block in &blocks {
+            forward.blocks.insert(block.hash.clone(), block.clone());
+        }
+        for block in blocks.iter().rev() {
+            backward.blocks.insert(block.hash.clone(), block.clone());
+        }
+        for vote in &votes {
+            forward.insert_vote(vote.clone());
+        }
+        for vote in votes.iter().rev() {
+            backward.insert_vote(vote.clone());
+        }
+        assert_eq!(forward.state_hash(), backward.state_hash());
+
+        backward.insert_vote(vote(2, Checkpoint::genesis(), checkpoint("a", 1), 1));
+        assert_ne!(forward.state_hash(), backward.state_hash());
+
+        // A different block under the same hash is a different state
+        let mut conflicting = forward.clone();
+        conflicting.blocks.insert("b".to_string(), Block { proposer_id: 7, ..block("b", "a", 2) });
+        assert_ne!(forward.state_hash(), conflicting.state_hash());
+    }
+
+    #[test]
+    fn state_hash_is_sha256_of_a_fixed_encoding() {
+        // Empty block and vote lists, no anchor, no pinned checkpoints:
+        // 33 zero bytes, whatever the platform or toolchain
+        assert_eq!(View::default().state_hash(), "7f9c9e31ac8256ca2f258583df262dbc7d6f68f2a03043d5c99a4ae5a7396ce9");
+    }
+
+    #[test]
+    fn ancestors_walk_back_to_genesis() {
+        let genesis = Block::genesis();
+        let mut view = View::default();
+        view.blocks.insert(genesis.hash.clone(), genesis.clone());
+        let mut parent = genesis.hash.clone();
+        for slot in 1..=4 {
+            let hash = format!("b{}", slot);
+            view.blocks.insert(hash.clone(), block(&hash, &parent, slot));
+            parent = hash;
+        }
+        let hashes: Vec<&str> = view.ancestors(&"b4".to_string()).map(|b| b.hash.as_str()).collect();
+        assert_eq!(hashes, ["b4", "b3", "b2", "b1", genesis.hash.as_str()]);
+        assert_eq!(view.ancestors(&"b4".to_string()).nth(2).map(|b| b.slot), Some(2));
+        assert!(view.ancestors(&"b4".to_string()).any(|b| b.hash == "b1"));
+
+        // A gap ends the walk instead of panicking
+        view.blocks.remove("b2");
+        let hashes: Vec<&str> = view.ancestors(&"b4".to_string()).map(|b| b.hash.as_str()).collect();
+        assert_eq!(hashes, ["b4", "b3"]);
+        assert_eq!(view.ancestors(&"missing".to_string()).count(), 0);
+    }
+
+    #[test]
+    fn common_ancestor_of_forked_heads() {
+        // genesis - a - b - c
+        //                \ d - e
+        //             \ f
+        let genesis = Block::genesis();
+        let mut view = View::default();
+        for block in [block("a", &genesis.hash, 1), block("b", "a", 2), block("c", "b", 3),
+                      block("d", "b", 3), block("e", "d", 4), block("f", "a", 2)] {
+            view.blocks.insert(block.hash.clone(), block);
+        }
+        view.blocks.insert(genesis.hash.clone(), genesis.clone());
+        let lca = |a: &str, b: &str| view.common_ancestor(&a.to_string(), &b.to_string());
+
+        assert_eq!(lca("c", "e"), Some("b".to_string()));
+        assert_eq!(lca("e", "f"), Some("a".to_string()));
+        assert_eq!(lca("f", "f"), Some("f".to_string()));
+        // An ancestor is its own common ancestor with a descendant
+        assert_eq!(lca("b", "e"), Some("b".to_string()));
+        assert_eq!(lca("e", "b"), Some("b".to_string()));
+        assert_eq!(lca(&genesis.hash, "c"), Some(genesis.hash.clone()));
+        assert_eq!(lca("c", "missing"), None);
+    }
+
+    #[test]
+    fn display_truncates_long_hashes() {
+        let long = "0123456789abcdef0123";
+        assert_eq!(checkpoint(long, 4).to_string(), "(0123456789ab…, slot 4)");
+        assert_eq!(checkpoint("a1", 4).to_string(), "(a1, slot 4)");
+
+        let vote = Vote { chain_head_hash: long.to_string(), ..vote(3, checkpoint("a1", 1), checkpoint(long, 4), 4) };
+        assert_eq!(vote.to_string(), "validator 3 slot 4: head 0123456789ab…, FFG (a1, slot 1) -> (0123456789ab…, slot 4)");
+        assert_eq!(Block { proposer_id: 2, ..block(long, "a1", 4) }.to_string(), "0123456789ab… (slot 4, proposer 2)");
+
+        // Debug still shows everything
+        assert!(format!("{:?}", checkpoint(long, 4)).contains(long));
+    }
+
+    #[test]
+    fn dot_has_an_edge_per_known_parent() {
+        // genesis - a - b - c
+        //             \ d
+        // plus orphan o, whose parent we never saw
+        let genesis = Block::genesis();
+        let mut view = View::default();
+        for block in [genesis.clone(), block("a", &genesis.hash, 1), block("b", "a", 2), block("c", "b", 3),
+                      block("d", "a", 2), block("o", "unseen", 4)] {
+            view.blocks.insert(block.hash.clone(), block);
+        }
+        let dot = view.to_dot();
+        assert!(dot.starts_with("digraph blocks {") && dot.ends_with("}\n"));
+        assert_eq!(dot.matches(" -> ").count(), 4);
+        assert!(dot.contains("\"a\" -> \"d\";"));
+        assert!(dot.contains("\"o\" [label=\"o\\nslot 4, proposer 0\", style=dashed];"));
+
+        let highlighted = view.to_dot_highlighting(&[(&"c".to_string(), "green"), (&"a".to_string(), "blue")]);
+        assert!(highlighted.contains("\"c\" [label=\"c\\nslot 3, proposer 0\", color=green, style=bold];"));
+        assert!(highlighted.contains("\"a\" [label=\"a\\nslot 1, proposer 0\", color=blue, style=bold];"));
+        assert!(!highlighted.contains("\"d\" [label=\"d\\nslot 2, proposer 0\", color"));
+    }
+
+    /// Validator 0's FFG vote from slot `source` to slot `target`.
+    fn ffg(source: u64, target: u64) -> Vote {
+        vote(0, checkpoint("s", source), checkpoint("t", target), target)
+    }
+
+    #[test]
+    fn surrounds_is_strict_on_both_ends() {
+        assert!(ffg(1, 6).surrounds(&ffg(2, 5)));
+        assert!(!ffg(2, 5).surrounds(&ffg(1, 6)));
+        // Disjoint or overlapping spans don't surround
+        assert!(!ffg(1, 3).surrounds(&ffg(4, 5)));
+        assert!(!ffg(1, 4).surrounds(&ffg(2, 5)));
+        // Sharing either endpoint is not a surround
+        assert!(!ffg(1, 6).surrounds(&ffg(1, 5)));
+        assert!(!ffg(1, 6).surrounds(&ffg(2, 6)));
+        assert!(!ffg(1, 6).surrounds(&ffg(1, 6)));
+    }
+
+    #[test]
+    fn slot_monotonicity_flags_blocks_not_after_their_parent() {
+        let genesis = Block::genesis();
+        let mut view = View::default();
+        for block in [genesis.clone(), block("a", &genesis.hash, 1), block("b", "a", 3), block("orphan", "unseen", 0)] {
+            view.blocks.insert(block.hash.clone(), block);
+        }
+        assert_eq!(view.validate_slot_monotonicity(), Ok(()));
+
+        view.blocks.insert("same".to_string(), block("same", "b", 3));
+        view.blocks.insert("earlier".to_string(), block("earlier", "b", 2));
+        assert_eq!(view.validate_slot_monotonicity(), Err(vec!["earlier".to_string(), "same".to_string()]));
+    }
+
+    #[test]
+    fn consistency_flags_double_votes_and_surrounds() {
+        // Consecutive links and an identical vote are fine
+        assert!(ffg(1, 2).is_consistent_with(&ffg(2, 3)));
+        assert!(ffg(1, 2).is_consistent_with(&ffg(1, 2)));
+        assert!(ffg(1, 4).is_consistent_with(&ffg(2, 5)));
+
+        // Double vote: two targets for slot 3
+        let other_target = vote(0, checkpoint("s", 1), checkpoint("u", 3), 3);
+        assert!(!ffg(1, 3).is_consistent_with(&other_target));
+        assert!(!other_target.is_consistent_with(&ffg(1, 3)));
+
+        // Surround, either way round
+        assert!(!ffg(1, 6).is_consistent_with(&ffg(2, 5)));
+        assert!(!ffg(2, 5).is_consistent_with(&ffg(1, 6)));
+
+        // The same pair from two validators conflicts with nothing
+        assert!(ffg(1, 6).is_consistent_with(&Vote { validator_id: 1, ..ffg(2, 5) }));
+    }
+
+    #[test]
+    fn all_checkpoints_lists_each_block_at_its_slot() {
+        let genesis = Block::genesis();
+        let mut view = View::default();
+        for block in [genesis.clone(), block("a", &genesis.hash, 1), block("c", "a", 2), block("b", "a", 2), block("d", &genesis.hash, 3)] {
+            view.blocks.insert(block.hash.clone(), block);
+        }
+        // Redelivering a block adds no checkpoint
+        view.blocks.insert("a".to_string(), block("a", &genesis.hash, 1));
+        assert_eq!(view.all_checkpoints(), [
+            Checkpoint::genesis(), checkpoint("a", 1), checkpoint("b", 2), checkpoint("c", 2), checkpoint("d", 3),
+        ]);
+    }
+}
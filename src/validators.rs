@@ -0,0 +1,243 @@
+//! Validator set membership over time.
+//! Tracks which validators are active at each slot so quorum sizes and
+//! vote eligibility follow activations and exits.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use rand::rngs::StdRng;
+use rand::seq::IteratorRandom;
+use rand::SeedableRng;
+use crate::config::{threshold_weight, ProposerSelection};
+use crate::signer::SigningKey;
+use crate::types::ValidatorId;
+
+/// The validator set plus its activation/exit schedule.
+/// A validator is active from its activation slot (inclusive) until its
+/// exit slot (exclusive). Genesis validators activate at slot 0.
+/// Every validator has a stake of 1 unless set otherwise. Stake is
+/// informational only: justification, fork choice and every threshold
+/// count validators, one each, whatever their stake.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidatorRegistry {
+    activation_slot: HashMap<ValidatorId, u64>,
+    exit_slot: HashMap<ValidatorId, u64>,
+    stake: HashMap<ValidatorId, u64>, // Only non-default stakes are stored
+    slashed: HashSet<ValidatorId>,
+    supermajority: (u64, u64), // FFG quorum fraction (num, den), 2/3 by default
+    keys: HashMap<ValidatorId, ValidatorKeys>, // Only validators with non-default keys
+}
+
+/// A validator's signing keys over time: the key in use now, and each key
+/// it rotated out with the slot the rotation took effect. Votes are tied to
+/// the validator id, not the key, so history signed under an old key stays
+/// valid after rotation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidatorKeys {
+    pub current: SigningKey,
+    /// `(key, until_slot)`, oldest first; each key signed for slots before
+    /// `until_slot` and from the previous entry's `until_slot` on.
+    pub previous: Vec<(SigningKey, u64)>,
+}
+
+impl Default for ValidatorRegistry {
+    fn default() -> Self {
+        ValidatorRegistry {
+            activation_slot: HashMap::new(),
+            exit_slot: HashMap::new(),
+            stake: HashMap::new(),
+            slashed: HashSet::new(),
+            supermajority: (2, 3),
+            keys: HashMap::new(),
+        }
+    }
+}
+
+impl ValidatorRegistry {
+    /// Registry whose validators are all active from genesis.
+    pub fn new(genesis: impl IntoIterator<Item = ValidatorId>) -> Self {
+        let mut registry = ValidatorRegistry::default();
+        for id in genesis {
+            registry.activation_slot.insert(id, 0);
+        }
+        registry
+    }
+
+    /// Add activation and exit schedules, keyed by slot.
+    pub fn with_schedule(
+        mut self,
+        activations: HashMap<u64, Vec<ValidatorId>>,
+        exits: HashMap<u64, Vec<ValidatorId>>,
+    ) -> Self {
+        for (slot, ids) in activations {
+            for id in ids {
+                self.schedule_activation(id, slot);
+            }
+        }
+        for (slot, ids) in exits {
+            for id in ids {
+                self.schedule_exit(id, slot);
+            }
+        }
+        self
+    }
+
+    /// Whether `id` is in the registry at all, whatever its schedule.
+    pub fn contains(&self, id: ValidatorId) -> bool {
+        self.activation_slot.contains_key(&id)
+    }
+
+    /// Use `num/den` instead of 2/3 as the FFG supermajority fraction.
+    pub fn with_supermajority(mut self, num: u64, den: u64) -> Self {
+        self.supermajority = (num, den);
+        self
+    }
+
+    /// Smallest vote weight strictly above the supermajority fraction of
+    /// validators active at `slot`.
+    pub fn supermajority_threshold(&self, slot: u64) -> u64 {
+        let (num, den) = self.supermajority;
+        threshold_weight(self.active_count(slot), num, den)
+    }
+
+    /// Activate `id` from `slot` onward (the earliest scheduled slot wins).
+    pub fn schedule_activation(&mut self, id: ValidatorId, slot: u64) {
+        let entry = self.activation_slot.entry(id).or_insert(slot);
+        *entry = (*entry).min(slot);
+    }
+
+    /// Deactivate `id` from `slot` onward (the earliest scheduled slot wins).
+    pub fn schedule_exit(&mut self, id: ValidatorId, slot: u64) {
+        let entry = self.exit_slot.entry(id).or_insert(slot);
+        *entry = (*entry).min(slot);
+    }
+
+    /// Set the stake of `id`.
+    pub fn set_stake(&mut self, id: ValidatorId, stake: u64) {
+        self.stake.insert(id, stake);
+    }
+
+    /// Stake of `id`, or 0 if it isn't registered.
+    pub fn stake_of(&self, id: ValidatorId) -> u64 {
+        if !self.contains(id) {
+            return 0;
+        }
+        self.stake.get(&id).copied().unwrap_or(1)
+    }
+
+    /// Combined stake of every registered validator, whatever its schedule.
+    pub fn total_stake(&self) -> u64 {
+        self.activation_slot.keys().map(|&id| self.stake_of(id)).sum()
+    }
+
+    /// Combined stake of the validators active in `slot`. Exited and
+    /// slashed validators don't count.
+    pub fn active_stake(&self, slot: u64) -> u64 {
+        self.active_at(slot).into_iter().map(|id| self.stake_of(id)).sum()
+    }
+
+    /// Slash `id` for an offence seen in `slot`: it is forcibly exited from
+    /// that slot onward, so its earlier votes still count.
+    pub fn slash(&mut self, id: ValidatorId, slot: u64) {
+        self.slashed.insert(id);
+        self.schedule_exit(id, slot);
+    }
+
+    /// Signing keys of `id`. Validators start out with their id as key,
+    /// matching a node's default `LocalSigner`.
+    pub fn keys_of(&self, id: ValidatorId) -> ValidatorKeys {
+        self.keys.get(&id).cloned()
+            .unwrap_or(ValidatorKeys { current: id, previous: Vec::new() })
+    }
+
+    /// Switch `id` to signing with `key` from `from_slot` on. Its current
+    /// key stays valid for the slots before.
+    pub fn rotate_key(&mut self, id: ValidatorId, key: SigningKey, from_slot: u64) {
+        let keys = self.keys.entry(id).or_insert(ValidatorKeys { current: id, previous: Vec::new() });
+        let old = std::mem::replace(&mut keys.current, key);
+        keys.previous.push((old, from_slot));
+    }
+
+    /// The key `id` signs with in `slot`.
+    pub fn key_at(&self, id: ValidatorId, slot: u64) -> SigningKey {
+        let Some(keys) = self.keys.get(&id) else {
+            return id;
+        };
+        keys.previous.iter()
+            .find(|&&(_, until)| slot < until)
+            .map_or(keys.current, |&(key, _)| key)
+    }
+
+    /// Whether `id` has been slashed.
+    pub fn is_slashed(&self, id: ValidatorId) -> bool {
+        self.slashed.contains(&id)
+    }
+
+    /// Whether `id` may vote in `slot`.
+    pub fn is_active(&self, id: ValidatorId, slot: u64) -> bool {
+        let activated = self.activation_slot.get(&id).is_some_and(|&from| from <= slot);
+        let exited = self.exit_slot.get(&id).is_some_and(|&until| until <= slot);
+        activated && !exited
+    }
+
+    /// Validators active in `slot`.
+    pub fn active_at(&self, slot: u64) -> BTreeSet<ValidatorId> {
+        self.activation_slot.keys()
+            .copied()
+            .filter(|&id| self.is_active(id, slot))
+            .collect()
+    }
+
+    /// Size of the active set in `slot`, the quorum denominator.
+    pub fn active_count(&self, slot: u64) -> u64 {
+        self.activation_slot.keys().filter(|&&id| self.is_active(id, slot)).count() as u64
+    }
+
+    /// The validator assigned to propose in `slot`, if any are active.
+    pub fn proposer_for(&self, slot: u64, selection: ProposerSelection) -> Option<ValidatorId> {
+        let active = self.active_at(slot);
+        match selection {
+            ProposerSelection::RoundRobin => {
+                let index = slot.saturating_sub(1) % active.len().max(1) as u64;
+                active.into_iter().nth(index as usize)
+            }
+            ProposerSelection::Seeded(seed) => {
+                let mut rng = StdRng::seed_from_u64(seed ^ slot.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+                active.into_iter().choose(&mut rng)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slashing_drops_active_stake_by_the_validators_stake() {
+        let mut validators = ValidatorRegistry::new(0..4);
+        validators.set_stake(2, 32);
+        assert_eq!(validators.total_stake(), 35);
+        assert_eq!(validators.active_stake(5), 35);
+
+        validators.slash(2, 5);
+        assert_eq!(validators.active_stake(4), 35);
+        assert_eq!(validators.active_stake(5), 3);
+        assert_eq!(validators.total_stake(), 35);
+    }
+
+    #[test]
+    fn exited_and_unknown_validators_hold_no_active_stake() {
+        let mut validators = ValidatorRegistry::new(0..3);
+        validators.schedule_exit(0, 2);
+        assert_eq!(validators.active_stake(1), 3);
+        assert_eq!(validators.active_stake(2), 2);
+        assert_eq!(validators.stake_of(7), 0);
+    }
+
+    #[test]
+    fn stake_does_not_weigh_in_thresholds() {
+        let mut validators = ValidatorRegistry::new(0..10);
+        let threshold = validators.supermajority_threshold(0);
+        validators.set_stake(0, 1000);
+        assert_eq!(validators.supermajority_threshold(0), threshold);
+    }
+}
@@ -0,0 +1,126 @@
+//! Compact binary wire format for network messages.
+//! Uses bincode's standard (varint) encoding; enabled by the `wire` feature.
+
+use std::fmt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::types::*;
+
+/// Failure to decode a message from its wire bytes.
+#[derive(Debug)]
+pub struct WireError(bincode::error::DecodeError);
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "malformed wire message: {}", self.0)
+    }
+}
+
+impl std::error::Error for WireError {}
+
+fn encode<T: Serialize>(value: &T) -> Vec<u8> {
+    bincode::serde::encode_to_vec(value, bincode::config::standard())
+        .expect("in-memory encoding of protocol types cannot fail")
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, WireError> {
+    let (value, _) = bincode::serde::decode_from_slice(bytes, bincode::config::standard())
+        .map_err(WireError)?;
+    Ok(value)
+}
+
+impl Vote {
+    /// Encode this vote for the network.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode(self)
+    }
+
+    /// Decode a vote received from the network.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, WireError> {
+        decode(bytes)
+    }
+}
+
+impl Block {
+    /// Encode this block for the network.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode(self)
+    }
+
+    /// Decode a block received from the network.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, WireError> {
+        decode(bytes)
+    }
+}
+
+impl Proposal {
+    /// Encode this proposal for the network.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode(self)
+    }
+
+    /// Decode a proposal received from the network.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, WireError> {
+        decode(bytes)
+    }
+}
+
+impl Envelope {
+    /// Encode this envelope, fork digest first, for the network.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode(self)
+    }
+
+    /// Decode an envelope received from the network.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, WireError> {
+        decode(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProtocolConfig;
+    use crate::hashing::HashFunction;
+    use crate::node::Node;
+
+    /// A vote as a node casts it after a slot of SHA-256-hashed blocks.
+    fn typical_vote() -> (Node, Vote) {
+        let config = ProtocolConfig { hash_function: Some(HashFunction::Sha256), ..ProtocolConfig::default() };
+        let proposer_id = Node::new(0).proposer_for(1).unwrap();
+        let mut node = Node::with_config(proposer_id, config);
+        node.on_tick(1, SlotPhase::Propose).unwrap();
+        let Some(Message::Vote(vote)) = node.on_tick(1, SlotPhase::Vote).unwrap() else {
+            panic!("node should vote");
+        };
+        (node, vote)
+    }
+
+    #[test]
+    fn vote_round_trips_compactly() {
+        let (_, vote) = typical_vote();
+        let bytes = vote.to_bytes();
+        assert!(bytes.len() < 200, "vote encodes to {} bytes", bytes.len());
+        assert_eq!(Vote::from_bytes(&bytes).unwrap(), vote);
+    }
+
+    #[test]
+    fn block_proposal_and_envelope_round_trip() {
+        let (mut node, vote) = typical_vote();
+        let block = node.view.blocks[&vote.chain_head_hash].clone();
+        assert_eq!(Block::from_bytes(&block.to_bytes()).unwrap(), block);
+
+        let proposal = node.preview_proposal(2).unwrap();
+        assert_eq!(Proposal::from_bytes(&proposal.to_bytes()).unwrap(), proposal);
+
+        let envelope = Envelope { fork_digest: node.fork_digest(), message: Message::Vote(vote) };
+        assert_eq!(Envelope::from_bytes(&envelope.to_bytes()).unwrap(), envelope);
+    }
+
+    #[test]
+    fn truncated_bytes_are_an_error() {
+        let (_, vote) = typical_vote();
+        let bytes = vote.to_bytes();
+        assert!(Vote::from_bytes(&bytes[..bytes.len() / 2]).is_err());
+    }
+}
@@ -0,0 +1,16 @@
+//! 3SF against plain Casper FFG on the same honest chain: 3SF finalizes
+//! each block within a few slots, Casper only once its epochs justify.
+
+use threeSF::simulation::{Simulation, SimulationConfig};
+
+#[test]
+fn three_sf_finalizes_faster_than_casper_ffg() {
+    for epoch_length in [2, 4, 8] {
+        let mut sim = Simulation::new(SimulationConfig { num_slots: 32, ..SimulationConfig::default() });
+        sim.run();
+        let comparison = sim.compare_with_casper(epoch_length);
+        assert!(comparison.blocks > 0, "nothing finalized under both rules: {}", comparison);
+        assert!(comparison.three_sf < comparison.casper_ffg, "{}", comparison);
+        assert!(comparison.three_sf_finalized >= comparison.casper_ffg_finalized, "{}", comparison);
+    }
+}
@@ -0,0 +1,48 @@
+//! Liveness recovery: a network partition stalls (or halves) finality
+//! while it lasts, never finalizes conflicting blocks, and once it heals
+//! finality resumes within a few slots.
+
+use threeSF::simulation::{Partition, Simulation, SimulationConfig};
+
+/// Each node's greatest finalized slot after every slot of a run
+/// partitioning off `group` during `slots`, with the finished simulation.
+fn run_partitioned(group: std::ops::Range<u64>, slots: std::ops::Range<u64>) -> (Vec<Vec<u64>>, Simulation) {
+    let mut sim = Simulation::new(SimulationConfig {
+        num_slots: 16,
+        partition: Some(Partition { group: group.collect(), slots }),
+        ..SimulationConfig::default()
+    });
+    let finalized = (1..=16).map(|slot| {
+        sim.run_slot(slot);
+        sim.nodes.iter_mut().map(|node| node.greatest_finalized_checkpoint().slot).collect()
+    }).collect();
+    (finalized, sim)
+}
+
+#[test]
+fn even_split_stalls_then_finality_resumes() {
+    // Neither half of 10 validators has a supermajority
+    let (finalized, sim) = run_partitioned(0..5, 4..7);
+    assert_eq!(sim.conflicting_finalization(), None);
+    let before = finalized[2].clone();
+    for slot in 4..=6 {
+        assert_eq!(finalized[slot - 1], before, "finality moved in partitioned slot {}", slot);
+    }
+    // Healed in slot 7: every node finalizes past the partition at once,
+    // then keeps finalizing the previous slot
+    for slot in 7..=16u64 {
+        assert!(finalized[slot as usize - 1].iter().all(|&f| f == slot - 1), "slot {}: {:?}", slot, finalized[slot as usize - 1]);
+    }
+    assert!(sim.slow_finality(3).iter().all(|slow| slow.proposed_in < 7));
+}
+
+#[test]
+fn minority_stalls_while_majority_finalizes() {
+    let (finalized, sim) = run_partitioned(0..3, 4..7);
+    assert_eq!(sim.conflicting_finalization(), None);
+    let last_partitioned = &finalized[5];
+    assert!(last_partitioned[..3].iter().all(|&f| f == 2), "{:?}", last_partitioned);
+    assert!(last_partitioned[3..].iter().all(|&f| f == 5), "{:?}", last_partitioned);
+    assert!(finalized[6].iter().all(|&f| f == 6));
+    assert!(sim.slow_finality(3).is_empty());
+}
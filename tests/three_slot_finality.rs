@@ -0,0 +1,42 @@
+//! The headline property: with honest validators and instant delivery,
+//! every proposed block is finalized within 3 slots of its proposal.
+
+use threeSF::simulation::{Simulation, SimulationConfig};
+
+fn assert_three_slot_finality(config: SimulationConfig) {
+    let description = format!("{} validators, {} slots, seed {:?}", config.num_validators, config.num_slots, config.seed);
+    let mut sim = Simulation::new(config);
+    sim.run();
+    let slow = sim.slow_finality(3);
+    assert!(slow.is_empty(), "3-slot finality violated with {}:\n{}",
+            description, slow.iter().map(|s| format!("  {}", s)).collect::<Vec<_>>().join("\n"));
+}
+
+#[test]
+fn default_run_finalizes_within_three_slots() {
+    assert_three_slot_finality(SimulationConfig::default());
+}
+
+#[test]
+fn seeded_runs_finalize_within_three_slots() {
+    for seed in 0..8 {
+        assert_three_slot_finality(SimulationConfig { seed: Some(seed), num_slots: 16, ..SimulationConfig::default() });
+    }
+}
+
+#[test]
+fn validator_set_size_does_not_slow_finality() {
+    for num_validators in [4, 7, 31] {
+        assert_three_slot_finality(SimulationConfig { num_validators, num_slots: 12, ..SimulationConfig::default() });
+    }
+}
+
+#[test]
+fn late_blocks_are_reported() {
+    let mut sim = Simulation::new(SimulationConfig { num_slots: 6, ..SimulationConfig::default() });
+    sim.run();
+    // Judged against a zero-slot deadline, nothing finalizes in time
+    let slow = sim.slow_finality(0);
+    assert!(!slow.is_empty());
+    assert!(slow.iter().all(|s| s.finalized_in.is_none_or(|slot| slot > s.proposed_in)));
+}